@@ -1,9 +1,17 @@
 use std::env;
 use std::path::PathBuf;
 
+#[cfg(feature = "bindgen")]
 mod build_bindgen;
+#[cfg(feature = "bindgen")]
 use crate::build_bindgen::bindgen;
 
+mod build_system;
+use crate::build_system::{find_system_library, use_system_library};
+
+mod build_target;
+use crate::build_target::{configure_cross, target_os};
+
 // Functions to determine feature flags
 fn enable_playlist() -> &'static str {
     if cfg!(feature = "playlist") {
@@ -22,7 +30,110 @@ fn build_shared_libs_flag() -> &'static str {
     }
 }
 
+// Parse an ON/OFF style environment variable, None if it's not set
+fn env_flag(name: &str) -> Option<bool> {
+    println!("cargo:rerun-if-env-changed={}", name);
+    let value = env::var(name).ok()?;
+    match value.to_ascii_uppercase().as_str() {
+        "1" | "ON" | "YES" | "TRUE" => Some(true),
+        "0" | "OFF" | "NO" | "FALSE" => Some(false),
+        _ => {
+            println!(
+                "cargo:warning=Ignoring {}={}, expected ON or OFF",
+                name, value
+            );
+            None
+        }
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+// Render with OpenGL ES instead of desktop OpenGL?
+fn enable_gles() -> bool {
+    env_flag("PROJECTM_ENABLE_GLES").unwrap_or(cfg!(feature = "gles"))
+}
+
+// Use OpenMP to parallelize per-pixel equations? None keeps libprojectM's default, which is ON.
+fn enable_openmp() -> Option<bool> {
+    env_flag("PROJECTM_ENABLE_OPENMP").or_else(|| cfg!(feature = "openmp").then_some(true))
+}
+
+// CMake build type, overrides the one derived from the cargo profile (e.g. "Debug" or "Release")
+fn cmake_build_type() -> Option<String> {
+    println!("cargo:rerun-if-env-changed=PROJECTM_CMAKE_BUILD_TYPE");
+    env::var("PROJECTM_CMAKE_BUILD_TYPE")
+        .ok()
+        .filter(|build_type| !build_type.is_empty())
+}
+
+// Additional CMake definitions, e.g. PROJECTM_CMAKE_DEFINES="ENABLE_SYSTEM_GLM=ON;CMAKE_VERBOSE_MAKEFILE=ON"
+fn extra_cmake_defines() -> Vec<(String, String)> {
+    println!("cargo:rerun-if-env-changed=PROJECTM_CMAKE_DEFINES");
+    let defines = env::var("PROJECTM_CMAKE_DEFINES").unwrap_or_default();
+
+    defines
+        .split(';')
+        .map(str::trim)
+        .filter(|define| !define.is_empty())
+        .filter_map(|define| match define.split_once('=') {
+            Some((key, value)) => Some((key.trim().to_string(), value.trim().to_string())),
+            None => {
+                println!(
+                    "cargo:warning=Ignoring CMake define '{}', expected KEY=VALUE",
+                    define
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+// Options shared by all platforms
+fn configure_options(cmake_config: &mut cmake::Config) {
+    cmake_config.define("ENABLE_GLES", on_off(enable_gles()));
+    if let Some(openmp) = enable_openmp() {
+        cmake_config.define("ENABLE_OPENMP", on_off(openmp));
+    }
+
+    if let Some(build_type) = cmake_build_type() {
+        cmake_config.profile(&build_type);
+    }
+
+    for (key, value) in extra_cmake_defines() {
+        cmake_config.define(key, value);
+    }
+}
+
 fn main() {
+    // Prefer an installed libprojectM if requested, falling back to the vendored sources
+    let include_dirs = if use_system_library() {
+        match find_system_library() {
+            Some(library) => library.include_dirs,
+            None => {
+                println!("cargo:warning=No usable system libprojectM found, building the vendored sources.");
+                build_vendored()
+            }
+        }
+    } else {
+        build_vendored()
+    };
+
+    // Generate Rust bindings using bindgen, otherwise src/bindings is used
+    #[cfg(feature = "bindgen")]
+    bindgen(&include_dirs);
+    #[cfg(not(feature = "bindgen"))]
+    let _ = include_dirs;
+}
+
+// Build libprojectM from the libprojectM submodule and return its include directory.
+fn build_vendored() -> Vec<PathBuf> {
     // Path to the projectM source code
     let projectm_path = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("libprojectM");
 
@@ -41,9 +152,10 @@ fn main() {
     let build_shared_libs = build_shared_libs_flag();
 
     let dst;
+    let target_os = target_os();
 
     // Platform-specific CMake configurations
-    if cfg!(target_os = "windows") {
+    if target_os == "windows" {
         // Ensure VCPKG installation root is set
         let vcpkg_root = match env::var("VCPKG_INSTALLATION_ROOT") {
             Ok(val) => val,
@@ -98,38 +210,50 @@ fn main() {
             .define("BUILD_TESTING", "OFF")
             .define("BUILD_EXAMPLES", "OFF")
             .define("BUILD_SHARED_LIBS", build_shared_libs); // static/dynamic
+        configure_options(&mut cmake_config);
 
         dst = cmake_config.build();
-    } else if cfg!(target_os = "emscripten") {
+    } else if target_os == "emscripten" {
         // Configure and build libprojectM using CMake for Emscripten
-        dst = cmake::Config::new(&projectm_path)
+        let mut cmake_config = cmake::Config::new(&projectm_path);
+        cmake_config
             .define("ENABLE_PLAYLIST", enable_playlist_flag)
             .define("BUILD_TESTING", "OFF")
             .define("BUILD_EXAMPLES", "OFF")
             .define("ENABLE_EMSCRIPTEN", "ON")
-            .define("BUILD_SHARED_LIBS", build_shared_libs) // static/dynamic
-            .build();
+            .define("BUILD_SHARED_LIBS", build_shared_libs); // static/dynamic
+        configure_options(&mut cmake_config);
+
+        dst = cmake_config.build();
     } else {
         // Configure and build libprojectM using CMake for other platforms (Linux, macOS)
-        dst = cmake::Config::new(&projectm_path)
+        let mut cmake_config = cmake::Config::new(&projectm_path);
+        cmake_config
             .define("ENABLE_PLAYLIST", enable_playlist_flag)
             .define("BUILD_TESTING", "OFF")
             .define("BUILD_EXAMPLES", "OFF")
-            .define("BUILD_SHARED_LIBS", build_shared_libs) // static/dynamic
-            .build();
+            .define("BUILD_SHARED_LIBS", build_shared_libs); // static/dynamic
+        configure_options(&mut cmake_config);
+        configure_cross(&mut cmake_config);
+
+        dst = cmake_config.build();
     }
 
     // Specify the library search path
     println!("cargo:rustc-link-search=native={}/lib", dst.display());
 
-    // Determine the build profile (release or debug)
+    // Determine the build profile (release or debug), debug libraries have a "d" suffix
     let profile = env::var("PROFILE").unwrap_or_else(|_| "release".to_string());
+    let debug_libs = match cmake_build_type() {
+        Some(build_type) => build_type.eq_ignore_ascii_case("debug"),
+        None => profile != "release",
+    };
 
     // Platform and feature-specific library linking
-    if cfg!(target_os = "windows") || cfg!(target_os = "emscripten") {
+    if target_os == "windows" || target_os == "emscripten" {
         // Static or Dynamic linking based on 'static' feature
         if cfg!(feature = "static") {
-            if profile == "release" {
+            if !debug_libs {
                 println!("cargo:rustc-link-lib=static=projectM-4");
                 if cfg!(feature = "playlist") {
                     println!("cargo:rustc-link-lib=static=projectM-4-playlist");
@@ -141,7 +265,7 @@ fn main() {
                 }
             }
         } else {
-            if profile == "release" {
+            if !debug_libs {
                 println!("cargo:rustc-link-lib=dylib=projectM-4");
                 if cfg!(feature = "playlist") {
                     println!("cargo:rustc-link-lib=dylib=projectM-4-playlist");
@@ -156,7 +280,7 @@ fn main() {
     } else {
         // For other platforms (Linux, macOS)
         if cfg!(feature = "static") {
-            if profile == "release" {
+            if !debug_libs {
                 println!("cargo:rustc-link-lib=static=projectM-4");
                 if cfg!(feature = "playlist") {
                     println!("cargo:rustc-link-lib=static=projectM-4-playlist");
@@ -168,7 +292,7 @@ fn main() {
                 }
             }
         } else {
-            if profile == "release" {
+            if !debug_libs {
                 println!("cargo:rustc-link-lib=dylib=projectM-4");
                 if cfg!(feature = "playlist") {
                     println!("cargo:rustc-link-lib=dylib=projectM-4-playlist");
@@ -182,29 +306,36 @@ fn main() {
         }
     }
 
-    // Platform-specific link flags for C++ and OpenGL
-    #[cfg(target_os = "macos")]
-    {
-        println!("cargo:rustc-link-lib=c++");
-        println!("cargo:rustc-link-lib=framework=OpenGL");
-    }
-    #[cfg(target_os = "linux")]
-    {
-        // On Linux, link stdc++ and GL.
-        println!("cargo:rustc-link-lib=stdc++");
-        println!("cargo:rustc-link-lib=GL");
-        println!("cargo:rustc-link-lib=gomp");
-    }
-    #[cfg(target_os = "windows")]
-    {
-        println!("cargo:rustc-link-lib=opengl32");
-    }
-    #[cfg(target_os = "emscripten")]
-    {
-        // Emscripten typically handles GL calls differently, so you might skip or rely on the
-        // emscripten compiler for linking.
-    }
+    link_platform_libraries(&target_os);
+
+    vec![dst.join("include")]
+}
 
-    // Generate Rust bindings using bindgen
-    bindgen();
+// Platform-specific link flags for C++ and OpenGL, the dependencies of libprojectM itself
+fn link_platform_libraries(target_os: &str) {
+    match target_os {
+        "macos" => {
+            println!("cargo:rustc-link-lib=c++");
+            println!("cargo:rustc-link-lib=framework=OpenGL");
+        }
+        "linux" => {
+            // On Linux, link stdc++ and GL (or GLES), and the GNU OpenMP runtime if enabled.
+            println!("cargo:rustc-link-lib=stdc++");
+            if enable_gles() {
+                println!("cargo:rustc-link-lib=GLESv2");
+            } else {
+                println!("cargo:rustc-link-lib=GL");
+            }
+            if enable_openmp().unwrap_or(true) {
+                println!("cargo:rustc-link-lib=gomp");
+            }
+        }
+        "windows" => {
+            println!("cargo:rustc-link-lib=opengl32");
+        }
+        _ => {
+            // Emscripten typically handles GL calls differently, so you might skip or rely on the
+            // emscripten compiler for linking.
+        }
+    }
 }
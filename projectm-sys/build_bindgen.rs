@@ -1,7 +1,12 @@
-use std::{env, path::PathBuf};
+use std::{env, fs, path::PathBuf};
 
-pub fn bindgen() {
+use crate::build_target::bindgen_clang_args;
+
+// Regenerate the bindings from the libprojectM headers. The result is written to OUT_DIR and,
+// if PROJECTM_UPDATE_BINDINGS is set, copied over the pregenerated bindings in src/bindings.
+pub fn bindgen(include_dirs: &[PathBuf]) {
     println!("cargo:rerun-if-changed=wrapper.h");
+    println!("cargo:rerun-if-env-changed=PROJECTM_UPDATE_BINDINGS");
 
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let out_path = out_dir.join("bindings.rs");
@@ -14,15 +19,36 @@ pub fn bindgen() {
         }
     }
 
+    fn get_pregenerated() -> String {
+        if cfg!(feature = "playlist") {
+            "src/bindings/playlist.rs".to_string()
+        } else {
+            "src/bindings/default.rs".to_string()
+        }
+    }
+
     let bindings = bindgen::Builder::default()
         .header(get_header())
         .allowlist_function("projectm_.*")
-        .clang_arg(format!("-I{}/include", out_dir.display()))
+        // Keep the output independent of header comments and the host's type layouts,
+        // so the pregenerated bindings can be compared against it.
+        .generate_comments(false)
+        .layout_tests(false)
+        .clang_args(
+            include_dirs
+                .iter()
+                .map(|dir| format!("-I{}", dir.display())),
+        )
+        .clang_args(bindgen_clang_args())
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
         .generate()
         .expect("Unable to generate bindings");
 
     bindings
-        .write_to_file(out_path)
+        .write_to_file(&out_path)
         .expect("Couldn't write bindings!");
+
+    if env::var_os("PROJECTM_UPDATE_BINDINGS").is_some() {
+        fs::copy(&out_path, get_pregenerated()).expect("Couldn't update pregenerated bindings!");
+    }
 }
@@ -0,0 +1,156 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::build_target::{is_cross_compiling, sysroot, target_os};
+use crate::{env_flag, link_platform_libraries};
+
+// The bindings target the libprojectM 4.x C API.
+const MIN_VERSION: (u32, u32) = (4, 0);
+const MAX_MAJOR_VERSION: u32 = 4;
+
+pub struct SystemLibrary {
+    pub include_dirs: Vec<PathBuf>,
+}
+
+// Link against the system library if the `system` feature or PROJECTM_SYSTEM is set.
+pub fn use_system_library() -> bool {
+    println!("cargo:rerun-if-env-changed=PROJECTM_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=PROJECTM_INCLUDE_DIR");
+
+    env_flag("PROJECTM_SYSTEM")
+        .unwrap_or_else(|| cfg!(feature = "system") || env::var_os("PROJECTM_LIB_DIR").is_some())
+}
+
+// Find an installed libprojectM-4, either in PROJECTM_LIB_DIR/PROJECTM_INCLUDE_DIR
+// or through pkg-config, and emit the link flags for it.
+pub fn find_system_library() -> Option<SystemLibrary> {
+    if let Some(lib_dir) = env::var_os("PROJECTM_LIB_DIR").map(PathBuf::from) {
+        return from_directories(&lib_dir);
+    }
+
+    from_pkg_config()
+}
+
+fn from_directories(lib_dir: &Path) -> Option<SystemLibrary> {
+    let include_dir = match env::var_os("PROJECTM_INCLUDE_DIR").map(PathBuf::from) {
+        Some(dir) => dir,
+        None => lib_dir.parent()?.join("include"),
+    };
+
+    let version = match read_header_version(&include_dir) {
+        Some(version) => version,
+        None => {
+            println!(
+                "cargo:warning=Could not read projectM-4/version.h in {}",
+                include_dir.display()
+            );
+            return None;
+        }
+    };
+    if !check_version(version) {
+        return None;
+    }
+
+    let kind = if cfg!(feature = "static") {
+        "static"
+    } else {
+        "dylib"
+    };
+
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib={}=projectM-4", kind);
+    if cfg!(feature = "playlist") {
+        println!("cargo:rustc-link-lib={}=projectM-4-playlist", kind);
+    }
+    // A shared library brings its own dependencies, a static one doesn't
+    if cfg!(feature = "static") {
+        link_platform_libraries(&target_os());
+    }
+
+    Some(SystemLibrary {
+        include_dirs: vec![include_dir],
+    })
+}
+
+fn from_pkg_config() -> Option<SystemLibrary> {
+    // pkg-config needs to know where the target's libraries live when cross compiling
+    if is_cross_compiling() && env::var_os("PKG_CONFIG_SYSROOT_DIR").is_none() {
+        if let Some(sysroot) = sysroot() {
+            env::set_var("PKG_CONFIG_SYSROOT_DIR", sysroot);
+        }
+    }
+
+    let mut config = pkg_config::Config::new();
+    config.statik(cfg!(feature = "static"));
+
+    let library = match config.probe("projectM-4") {
+        Ok(library) => library,
+        Err(err) => {
+            println!(
+                "cargo:warning=pkg-config could not find projectM-4: {}",
+                err
+            );
+            return None;
+        }
+    };
+
+    if !check_version(parse_version(&library.version)?) {
+        return None;
+    }
+
+    let mut include_dirs = library.include_paths;
+
+    if cfg!(feature = "playlist") {
+        match config.probe("projectM-4-playlist") {
+            Ok(playlist) => include_dirs.extend(playlist.include_paths),
+            Err(err) => {
+                println!(
+                    "cargo:warning=pkg-config could not find projectM-4-playlist: {}",
+                    err
+                );
+                return None;
+            }
+        }
+    }
+
+    Some(SystemLibrary { include_dirs })
+}
+
+fn check_version(version: (u32, u32)) -> bool {
+    if version < MIN_VERSION || version.0 > MAX_MAJOR_VERSION {
+        println!(
+            "cargo:warning=System libprojectM {}.{} is not compatible with these bindings (need {}.{} up to {}.x)",
+            version.0, version.1, MIN_VERSION.0, MIN_VERSION.1, MAX_MAJOR_VERSION
+        );
+        return false;
+    }
+    true
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.trim().parse().ok()?;
+    let minor = parts.next().unwrap_or("0").trim().parse().ok()?;
+    Some((major, minor))
+}
+
+// Read PROJECTM_VERSION_MAJOR/MINOR from the installed version header.
+fn read_header_version(include_dir: &Path) -> Option<(u32, u32)> {
+    let header = fs::read_to_string(include_dir.join("projectM-4").join("version.h")).ok()?;
+
+    let define = |name: &str| -> Option<u32> {
+        header.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("#define"), Some(n), Some(value)) if n == name => value.parse().ok(),
+                _ => None,
+            }
+        })
+    };
+
+    Some((
+        define("PROJECTM_VERSION_MAJOR")?,
+        define("PROJECTM_VERSION_MINOR")?,
+    ))
+}
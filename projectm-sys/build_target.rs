@@ -0,0 +1,71 @@
+use std::env;
+use std::path::PathBuf;
+
+// The build script is compiled for the host, so cfg!(target_os) describes the host.
+// Cargo passes the actual target through these environment variables.
+pub fn target_os() -> String {
+    env::var("CARGO_CFG_TARGET_OS").unwrap()
+}
+
+pub fn target() -> String {
+    env::var("TARGET").unwrap()
+}
+
+pub fn is_cross_compiling() -> bool {
+    env::var("HOST").ok() != env::var("TARGET").ok()
+}
+
+// Read a target-specific variable (e.g. PROJECTM_SYSROOT_aarch64_unknown_linux_gnu),
+// falling back to the generic one
+fn target_env(name: &str) -> Option<String> {
+    let specific = format!("{}_{}", name, target().replace('-', "_"));
+    println!("cargo:rerun-if-env-changed={}", specific);
+    println!("cargo:rerun-if-env-changed={}", name);
+
+    env::var(&specific)
+        .or_else(|_| env::var(name))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+// Root filesystem of the target system when cross compiling
+pub fn sysroot() -> Option<PathBuf> {
+    target_env("PROJECTM_SYSROOT").map(PathBuf::from)
+}
+
+// CMake toolchain file for the target. The cmake crate also reads CMAKE_TOOLCHAIN_FILE_<target>.
+pub fn cmake_toolchain_file() -> Option<PathBuf> {
+    target_env("PROJECTM_CMAKE_TOOLCHAIN_FILE").map(PathBuf::from)
+}
+
+// Cross compilation settings for CMake
+pub fn configure_cross(cmake_config: &mut cmake::Config) {
+    if let Some(toolchain_file) = cmake_toolchain_file() {
+        cmake_config.define("CMAKE_TOOLCHAIN_FILE", toolchain_file);
+    }
+
+    if let Some(sysroot) = sysroot() {
+        cmake_config
+            .define("CMAKE_SYSROOT", &sysroot)
+            .define("CMAKE_FIND_ROOT_PATH", &sysroot)
+            .define("CMAKE_FIND_ROOT_PATH_MODE_PROGRAM", "NEVER")
+            .define("CMAKE_FIND_ROOT_PATH_MODE_LIBRARY", "ONLY")
+            .define("CMAKE_FIND_ROOT_PATH_MODE_INCLUDE", "ONLY")
+            .define("CMAKE_FIND_ROOT_PATH_MODE_PACKAGE", "ONLY");
+    }
+}
+
+// Cross compilation settings for clang when generating bindings
+#[cfg(feature = "bindgen")]
+pub fn bindgen_clang_args() -> Vec<String> {
+    let mut args = Vec::new();
+
+    if is_cross_compiling() {
+        args.push(format!("--target={}", target()));
+    }
+    if let Some(sysroot) = sysroot() {
+        args.push(format!("--sysroot={}", sysroot.display()));
+    }
+
+    args
+}
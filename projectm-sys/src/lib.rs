@@ -2,4 +2,12 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+// Bindings regenerated by the build script with the `bindgen` feature
+#[cfg(feature = "bindgen")]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+// Pregenerated bindings, so that building doesn't require libclang
+#[cfg(all(not(feature = "bindgen"), feature = "playlist"))]
+include!("bindings/playlist.rs");
+#[cfg(all(not(feature = "bindgen"), not(feature = "playlist")))]
+include!("bindings/default.rs");
@@ -0,0 +1,28 @@
+// Checks that the pregenerated bindings in src/bindings match what bindgen produces from the
+// libprojectM headers. Run with `cargo test --features bindgen`; to update the pregenerated
+// files, build with `PROJECTM_UPDATE_BINDINGS=1 cargo build --features bindgen`.
+#[cfg(feature = "bindgen")]
+#[test]
+fn pregenerated_bindings_match_headers() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+    let pregenerated = if cfg!(feature = "playlist") {
+        include_str!("../src/bindings/playlist.rs")
+    } else {
+        include_str!("../src/bindings/default.rs")
+    };
+
+    // Ignore the generator banner and formatting differences.
+    fn normalize(bindings: &str) -> String {
+        bindings
+            .lines()
+            .filter(|line| !line.starts_with("/* automatically generated"))
+            .flat_map(str::chars)
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    }
+
+    assert!(
+        normalize(generated) == normalize(pregenerated),
+        "pregenerated bindings are out of date, run `PROJECTM_UPDATE_BINDINGS=1 cargo build --features bindgen`"
+    );
+}
@@ -0,0 +1,33 @@
+//! Audio processing
+//!
+//! Pure Rust tools that work on the PCM data passed to the `pcm_add_*`
+//! functions of [ProjectM](crate::core::ProjectM). Samples are floats in the
+//! range -1.0 to 1.0 with interleaved channels, as libprojectm uses them.
+
+pub mod analysis;
+pub mod capture;
+pub mod delay;
+pub mod gain;
+pub mod pipe;
+pub mod signals;
+pub mod spectrum;
+
+use crate::core::ProjectMChannels;
+
+/// Convert a signed 16 bit sample to a float sample.
+pub fn int16_to_float(sample: i16) -> f32 {
+    sample as f32 / 32768.0
+}
+
+/// Convert an unsigned 8 bit sample, centered at 128, to a float sample.
+pub fn uint8_to_float(sample: u8) -> f32 {
+    (sample as f32 - 128.0) / 128.0
+}
+
+/// Average interleaved samples to one sample per frame.
+pub fn downmix(samples: &[f32], channels: ProjectMChannels) -> impl Iterator<Item = f32> + '_ {
+    let channels = channels.max(1) as usize;
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+}
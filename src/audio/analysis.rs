@@ -0,0 +1,313 @@
+//! Band levels, beats and tempo
+//!
+//! An [Analyzer] splits the audio into bass, mid and treble and reports the
+//! levels once per video frame, the way Milkdrop computes its `bass`, `mid`
+//! and `treb` preset variables: 1.0 is the loudness of the last few seconds,
+//! and the `_att` variants follow the level with some attenuation. On top of
+//! that it detects beats on the bass level and estimates the tempo.
+//!
+//! [ProjectM](crate::core::ProjectM) runs an analyzer on everything passed to
+//! `pcm_add_*` once it is enabled:
+//!
+//! ```no_run
+//! use projectm::core::ProjectM;
+//!
+//! let projectm = ProjectM::create();
+//! projectm.enable_audio_analysis(44100);
+//! projectm.set_beat_event_callback(|frame| {
+//!     println!("beat at {:.2}s, tempo {:?}", frame.time, frame.tempo);
+//! });
+//! ```
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use crate::audio::downmix;
+use crate::core::ProjectMChannels;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// Frames per second, libprojectm's default.
+pub const DEFAULT_FRAME_RATE: u32 = 60;
+
+/// Upper edge of the bass band in Hz.
+const BASS_CUTOFF: f32 = 250.0;
+
+/// Lower edge of the treble band in Hz.
+const TREBLE_CUTOFF: f32 = 4000.0;
+
+/// Scaled bass level a beat has to rise above.
+const BEAT_THRESHOLD: f32 = 1.5;
+
+/// Shortest time between two beats in seconds (240 BPM).
+const MIN_BEAT_INTERVAL: f64 = 0.25;
+
+/// Beats older than this many seconds don't count towards the tempo.
+const TEMPO_WINDOW: f64 = 8.0;
+
+/// Beats needed for a tempo estimate.
+const MIN_TEMPO_BEATS: usize = 4;
+
+/// Long-term level below which the input counts as silence.
+const SILENCE: f32 = 0.001;
+
+/// The analysis result of one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioFrame {
+    /// End of the frame in seconds since the analysis started.
+    pub time: f64,
+    pub bass: f32,
+    pub mid: f32,
+    pub treb: f32,
+    /// Average of the three bands.
+    pub vol: f32,
+    pub bass_att: f32,
+    pub mid_att: f32,
+    pub treb_att: f32,
+    pub vol_att: f32,
+    /// A beat starts in this frame.
+    pub beat: bool,
+    /// Estimated tempo in beats per minute, once enough beats were detected.
+    pub tempo: Option<f32>,
+}
+
+pub struct Analyzer {
+    sample_rate: u32,
+    frame_rate: u32,
+    frame_length: usize,
+    // Milkdrop's smoothing rates are defined for 30 fps, this power adapts them
+    rate_exponent: f32,
+    beat_sensitivity: f32,
+
+    bass_coefficient: f32,
+    treble_coefficient: f32,
+    bass_lowpass: f32,
+    treble_lowpass: f32,
+
+    // Sums of squares of the frame being collected
+    energy: [f32; 3],
+    samples_in_frame: usize,
+    frames: u64,
+    // Samples per channel in the completed frames
+    position: u64,
+
+    average: [f32; 3],
+    long_average: [f32; 3],
+    above_threshold: bool,
+    beats: VecDeque<f64>,
+    latest: Option<AudioFrame>,
+}
+
+impl Analyzer {
+    /// Analyzer reporting [DEFAULT_FRAME_RATE] frames per second.
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_frame_rate(sample_rate, DEFAULT_FRAME_RATE)
+    }
+
+    pub fn with_frame_rate(sample_rate: u32, frame_rate: u32) -> Self {
+        let sample_rate = sample_rate.max(1);
+        let frame_rate = frame_rate.max(1);
+        let frame_length = (sample_rate / frame_rate).max(1) as usize;
+
+        Analyzer {
+            sample_rate,
+            frame_rate,
+            frame_length,
+            rate_exponent: 30.0 * frame_length as f32 / sample_rate as f32,
+            beat_sensitivity: 1.0,
+            bass_coefficient: lowpass_coefficient(BASS_CUTOFF, sample_rate),
+            treble_coefficient: lowpass_coefficient(TREBLE_CUTOFF, sample_rate),
+            bass_lowpass: 0.0,
+            treble_lowpass: 0.0,
+            energy: [0.0; 3],
+            samples_in_frame: 0,
+            frames: 0,
+            position: 0,
+            average: [0.0; 3],
+            long_average: [0.0; 3],
+            above_threshold: false,
+            beats: VecDeque::new(),
+            latest: None,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn frame_rate(&self) -> u32 {
+        self.frame_rate
+    }
+
+    /// Report frames at a new rate, e.g. after the instance's fps changed.
+    /// The levels and beats detected so far are kept.
+    pub fn set_frame_rate(&mut self, frame_rate: u32) {
+        let frame_rate = frame_rate.max(1);
+        self.frame_rate = frame_rate;
+        self.frame_length = (self.sample_rate / frame_rate).max(1) as usize;
+        self.rate_exponent = 30.0 * self.frame_length as f32 / self.sample_rate as f32;
+    }
+
+    /// Samples per channel in one frame.
+    pub fn frame_length(&self) -> usize {
+        self.frame_length
+    }
+
+    pub fn beat_sensitivity(&self) -> f32 {
+        self.beat_sensitivity
+    }
+
+    /// Scale the bass level before beat detection, like libprojectm's beat
+    /// sensitivity. Higher values detect more beats, 0 disables detection.
+    pub fn set_beat_sensitivity(&mut self, sensitivity: f32) {
+        self.beat_sensitivity = sensitivity.max(0.0);
+    }
+
+    /// The last completed frame.
+    pub fn latest(&self) -> Option<&AudioFrame> {
+        self.latest.as_ref()
+    }
+
+    /// Tempo estimate in beats per minute from the beats of the last seconds.
+    pub fn tempo(&self) -> Option<f32> {
+        if self.beats.len() < MIN_TEMPO_BEATS {
+            return None;
+        }
+
+        let mut intervals: Vec<f64> = self
+            .beats
+            .iter()
+            .zip(self.beats.iter().skip(1))
+            .map(|(previous, next)| fold_tempo(60.0 / (next - previous)))
+            .collect();
+        intervals.sort_by(|a, b| a.total_cmp(b));
+
+        Some(intervals[intervals.len() / 2] as f32)
+    }
+
+    /// Forget all history, as if the analyzer was newly created.
+    pub fn reset(&mut self) {
+        let beat_sensitivity = self.beat_sensitivity;
+        *self = Self::with_frame_rate(self.sample_rate, self.frame_rate);
+        self.beat_sensitivity = beat_sensitivity;
+    }
+
+    /// Analyze interleaved samples. `on_frame` is called for every frame
+    /// completed by them; samples of an incomplete frame are kept for the
+    /// next call.
+    pub fn process<F>(&mut self, samples: &[f32], channels: ProjectMChannels, mut on_frame: F)
+    where
+        F: FnMut(&AudioFrame),
+    {
+        for sample in downmix(samples, channels) {
+            self.bass_lowpass += self.bass_coefficient * (sample - self.bass_lowpass);
+            self.treble_lowpass += self.treble_coefficient * (sample - self.treble_lowpass);
+
+            let bands = [
+                self.bass_lowpass,
+                self.treble_lowpass - self.bass_lowpass,
+                sample - self.treble_lowpass,
+            ];
+            for (energy, band) in self.energy.iter_mut().zip(bands) {
+                *energy += band * band;
+            }
+
+            self.samples_in_frame += 1;
+            if self.samples_in_frame >= self.frame_length {
+                let frame = self.finish_frame();
+                on_frame(&frame);
+            }
+        }
+    }
+
+    fn finish_frame(&mut self) -> AudioFrame {
+        // Warm up the long-term average quickly during the first frames
+        let long_rate = if self.frames < 50 { 0.9f32 } else { 0.992 };
+        let long_rate = long_rate.powf(self.rate_exponent);
+
+        let mut level = [0.0f32; 3];
+        let mut attenuated = [0.0f32; 3];
+        let mut silent = true;
+
+        for band in 0..3 {
+            let immediate = (self.energy[band] / self.samples_in_frame as f32).sqrt();
+
+            let rate = if immediate > self.average[band] {
+                0.2f32
+            } else {
+                0.5
+            };
+            let rate = rate.powf(self.rate_exponent);
+            self.average[band] = self.average[band] * rate + immediate * (1.0 - rate);
+            self.long_average[band] =
+                self.long_average[band] * long_rate + immediate * (1.0 - long_rate);
+
+            if self.long_average[band] < SILENCE {
+                level[band] = 1.0;
+                attenuated[band] = 1.0;
+            } else {
+                silent = false;
+                level[band] = immediate / self.long_average[band];
+                attenuated[band] = self.average[band] / self.long_average[band];
+            }
+        }
+
+        self.energy = [0.0; 3];
+        self.position += self.samples_in_frame as u64;
+        self.samples_in_frame = 0;
+        self.frames += 1;
+        let time = self.position as f64 / self.sample_rate as f64;
+
+        let above_threshold = !silent && level[0] * self.beat_sensitivity > BEAT_THRESHOLD;
+        let since_last_beat = self.beats.back().map(|last| time - last);
+        let beat = above_threshold
+            && !self.above_threshold
+            && since_last_beat.map_or(true, |interval| interval >= MIN_BEAT_INTERVAL);
+        self.above_threshold = above_threshold;
+
+        if beat {
+            self.beats.push_back(time);
+        }
+        while matches!(self.beats.front(), Some(first) if time - first > TEMPO_WINDOW) {
+            self.beats.pop_front();
+        }
+
+        let frame = AudioFrame {
+            time,
+            bass: level[0],
+            mid: level[1],
+            treb: level[2],
+            vol: (level[0] + level[1] + level[2]) / 3.0,
+            bass_att: attenuated[0],
+            mid_att: attenuated[1],
+            treb_att: attenuated[2],
+            vol_att: (attenuated[0] + attenuated[1] + attenuated[2]) / 3.0,
+            beat,
+            tempo: self.tempo(),
+        };
+        self.latest = Some(frame);
+        frame
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+/// Coefficient of a one-pole lowpass filter.
+fn lowpass_coefficient(cutoff: f32, sample_rate: u32) -> f32 {
+    1.0 - (-2.0 * PI * cutoff / sample_rate as f32).exp()
+}
+
+// Beats at half or double speed describe the same tempo, report it between 80 and 160 BPM
+fn fold_tempo(mut bpm: f64) -> f64 {
+    while bpm < 80.0 {
+        bpm *= 2.0;
+    }
+    while bpm >= 160.0 {
+        bpm /= 2.0;
+    }
+    bpm
+}
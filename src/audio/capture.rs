@@ -0,0 +1,507 @@
+//! Audio capture
+//!
+//! A [Capture] collects audio on the capture thread and hands it to an
+//! instance on the thread that renders, so the instance itself is never
+//! touched from another thread. Call [feed](Capture::feed) once per frame:
+//!
+//! ```no_run
+//! # #[cfg(feature = "capture")] {
+//! use projectm::audio::capture::{Capture, Input};
+//! use projectm::core::ProjectM;
+//!
+//! let projectm = ProjectM::create();
+//! let mut capture = Capture::open(Input::Device(None)).unwrap();
+//! loop {
+//!     capture.feed(&projectm);
+//!     projectm.render_frame();
+//! }
+//! # }
+//! ```
+//!
+//! Capture devices are opened through cpal with the `capture` feature. The
+//! [Input::Null] and [Input::Loopback] inputs need no hardware and are always
+//! available.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::core::{Backend, ProjectM, ProjectMChannels};
+
+/// Seconds of audio kept when [feed](Capture::feed) isn't called often enough.
+const BUFFER_SECONDS: u32 = 1;
+
+/// Time between attempts to reopen a lost device.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Buffer length of the null input.
+const NULL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Where captured audio comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// Silence, produced in real time.
+    Null {
+        sample_rate: u32,
+        channels: ProjectMChannels,
+    },
+    /// Audio written to [Capture::buffer] by the application, from any thread.
+    Loopback {
+        sample_rate: u32,
+        channels: ProjectMChannels,
+    },
+    /// A capture device by name, or the default one.
+    #[cfg(feature = "capture")]
+    Device(Option<String>),
+}
+
+/// Captured samples in the format they were delivered in.
+#[derive(Debug, Clone, PartialEq)]
+enum Chunk {
+    Float(Vec<f32>),
+    Int16(Vec<i16>),
+    Uint8(Vec<u8>),
+}
+
+impl Chunk {
+    fn len(&self) -> usize {
+        match self {
+            Chunk::Float(samples) => samples.len(),
+            Chunk::Int16(samples) => samples.len(),
+            Chunk::Uint8(samples) => samples.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Format {
+    sample_rate: u32,
+    input_channels: usize,
+    channels: ProjectMChannels,
+    capacity: usize,
+}
+
+impl Format {
+    fn new(sample_rate: u32, channels: u32) -> Self {
+        let input_channels = channels.max(1) as usize;
+        let channels = channels.clamp(1, 2);
+
+        Format {
+            sample_rate,
+            input_channels,
+            channels,
+            capacity: (sample_rate * BUFFER_SECONDS * channels) as usize,
+        }
+    }
+
+    fn keep_channels<T: Copy>(&self, samples: &[T]) -> Vec<T> {
+        if self.input_channels == self.channels as usize {
+            return samples.to_vec();
+        }
+        samples
+            .chunks_exact(self.input_channels)
+            .flat_map(|frame| frame[..self.channels as usize].iter().copied())
+            .collect()
+    }
+}
+
+struct Queue {
+    chunks: VecDeque<Chunk>,
+    samples: usize,
+    // Shared, a reopened device may deliver a different format
+    format: Format,
+}
+
+/// Thread safe queue of captured audio. Clones share the same queue.
+#[derive(Clone)]
+pub struct CaptureBuffer {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl CaptureBuffer {
+    /// Queue for interleaved audio with `channels` channels. Channels after
+    /// the second are dropped, libprojectm takes mono or stereo audio.
+    pub fn new(sample_rate: u32, channels: u32) -> Self {
+        CaptureBuffer {
+            queue: Arc::new(Mutex::new(Queue {
+                chunks: VecDeque::new(),
+                samples: 0,
+                format: Format::new(sample_rate, channels),
+            })),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.lock().format.sample_rate
+    }
+
+    /// Channels passed to libprojectm.
+    pub fn channels(&self) -> ProjectMChannels {
+        self.lock().format.channels
+    }
+
+    /// Samples waiting to be fed.
+    pub fn len(&self) -> usize {
+        self.lock().samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push_float(&self, samples: &[f32]) {
+        self.push(samples, Chunk::Float);
+    }
+
+    pub fn push_int16(&self, samples: &[i16]) {
+        self.push(samples, Chunk::Int16);
+    }
+
+    pub fn push_uint8(&self, samples: &[u8]) {
+        self.push(samples, Chunk::Uint8);
+    }
+
+    pub fn clear(&self) {
+        let mut queue = self.lock();
+        queue.chunks.clear();
+        queue.samples = 0;
+    }
+
+    /// Pass all queued audio to an instance, using the `pcm_add_*` function
+    /// for the captured format. Returns the number of samples fed.
+    pub fn feed<B: Backend>(&self, projectm: &ProjectM<B>) -> usize {
+        // Don't hold the lock while libprojectm processes the audio
+        let (chunks, channels): (Vec<Chunk>, _) = {
+            let mut queue = self.lock();
+            queue.samples = 0;
+            (queue.chunks.drain(..).collect(), queue.format.channels)
+        };
+
+        let max_samples = B::pcm_get_max_samples() as usize;
+        let piece = (max_samples / channels as usize * channels as usize).max(channels as usize);
+        let mut fed = 0;
+
+        for chunk in chunks {
+            fed += chunk.len();
+            match chunk {
+                Chunk::Float(samples) => {
+                    for piece in samples.chunks(piece) {
+                        projectm.pcm_add_float(piece, channels);
+                    }
+                }
+                Chunk::Int16(samples) => {
+                    for piece in samples.chunks(piece) {
+                        projectm.pcm_add_int16(piece, channels);
+                    }
+                }
+                Chunk::Uint8(samples) => {
+                    for piece in samples.chunks(piece) {
+                        projectm.pcm_add_uint8(piece, channels);
+                    }
+                }
+            }
+        }
+
+        fed
+    }
+
+    // Switch to the format of a reopened input, dropping audio in the old one
+    fn set_format(&self, sample_rate: u32, channels: u32) {
+        let mut queue = self.lock();
+        queue.chunks.clear();
+        queue.samples = 0;
+        queue.format = Format::new(sample_rate, channels);
+    }
+
+    fn push<T: Copy>(&self, samples: &[T], chunk: fn(Vec<T>) -> Chunk) {
+        let mut queue = self.lock();
+        let chunk = chunk(queue.format.keep_channels(samples));
+        queue.samples += chunk.len();
+        queue.chunks.push_back(chunk);
+
+        // Drop the oldest audio rather than let the visuals fall behind
+        while queue.samples > queue.format.capacity && queue.chunks.len() > 1 {
+            if let Some(dropped) = queue.chunks.pop_front() {
+                queue.samples -= dropped.len();
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue> {
+        // A panic on another thread leaves the queue itself consistent
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+enum Stream {
+    Loopback,
+    Null {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    },
+    // Captures for as long as it is kept
+    #[cfg(feature = "capture")]
+    Device(#[allow(dead_code)] cpal::Stream),
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if let Stream::Null { stop, thread } = self {
+            stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// An open input feeding a [CaptureBuffer].
+pub struct Capture {
+    input: Input,
+    buffer: CaptureBuffer,
+    stream: Option<Stream>,
+    lost: Arc<AtomicBool>,
+    last_attempt: Instant,
+}
+
+impl Capture {
+    pub fn open(input: Input) -> io::Result<Self> {
+        let lost = Arc::new(AtomicBool::new(false));
+        let (buffer, stream) = start(&input, &lost, None)?;
+
+        Ok(Capture {
+            input,
+            buffer,
+            stream: Some(stream),
+            lost,
+            last_attempt: Instant::now(),
+        })
+    }
+
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    /// The queue the input writes to. Write to it directly with [Input::Loopback].
+    /// A reopened device keeps writing to it.
+    pub fn buffer(&self) -> &CaptureBuffer {
+        &self.buffer
+    }
+
+    /// False after the device was lost, until it could be reopened.
+    pub fn is_connected(&self) -> bool {
+        !self.lost.load(Ordering::Relaxed)
+    }
+
+    /// Pass the audio captured since the last call to an instance, and try
+    /// to reopen a lost device. Returns the number of samples fed.
+    pub fn feed<B: Backend>(&mut self, projectm: &ProjectM<B>) -> usize {
+        if !self.is_connected() && self.last_attempt.elapsed() >= RECONNECT_INTERVAL {
+            self.reconnect();
+        }
+        self.buffer.feed(projectm)
+    }
+
+    fn reconnect(&mut self) {
+        self.last_attempt = Instant::now();
+        // Close the old stream before opening the device again
+        self.stream = None;
+
+        let lost = Arc::new(AtomicBool::new(false));
+        if let Ok((_, stream)) = start(&self.input, &lost, Some(&self.buffer)) {
+            self.stream = Some(stream);
+            self.lost = lost;
+        }
+    }
+}
+
+// Open an input writing to `buffer`, or to a new buffer if there is none yet
+#[cfg_attr(not(feature = "capture"), allow(unused_variables))]
+fn start(
+    input: &Input,
+    lost: &Arc<AtomicBool>,
+    buffer: Option<&CaptureBuffer>,
+) -> io::Result<(CaptureBuffer, Stream)> {
+    match input {
+        Input::Null {
+            sample_rate,
+            channels,
+        } => {
+            let buffer = buffer_for(buffer, *sample_rate, *channels);
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let buffer = buffer.clone();
+                let stop = stop.clone();
+                thread::spawn(move || produce_silence(buffer, stop))
+            };
+            Ok((
+                buffer,
+                Stream::Null {
+                    stop,
+                    thread: Some(thread),
+                },
+            ))
+        }
+        Input::Loopback {
+            sample_rate,
+            channels,
+        } => Ok((
+            buffer_for(buffer, *sample_rate, *channels),
+            Stream::Loopback,
+        )),
+        #[cfg(feature = "capture")]
+        Input::Device(name) => device::start(name.as_deref(), lost, buffer),
+    }
+}
+
+fn buffer_for(buffer: Option<&CaptureBuffer>, sample_rate: u32, channels: u32) -> CaptureBuffer {
+    match buffer {
+        Some(buffer) => {
+            buffer.set_format(sample_rate, channels);
+            buffer.clone()
+        }
+        None => CaptureBuffer::new(sample_rate, channels),
+    }
+}
+
+fn produce_silence(buffer: CaptureBuffer, stop: Arc<AtomicBool>) {
+    let started = Instant::now();
+    let mut produced: u64 = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(NULL_INTERVAL);
+        let due = (started.elapsed().as_secs_f64() * buffer.sample_rate() as f64) as u64;
+        let frames = (due - produced) as usize;
+        buffer.push_float(&vec![0.0; frames * buffer.channels() as usize]);
+        produced = due;
+    }
+}
+
+/// Names of the capture devices of the default host.
+#[cfg(feature = "capture")]
+pub fn input_devices() -> io::Result<Vec<String>> {
+    device::input_devices()
+}
+
+#[cfg(feature = "capture")]
+mod device {
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    use super::{buffer_for, CaptureBuffer, Stream};
+
+    pub(super) fn input_devices() -> io::Result<Vec<String>> {
+        let host = cpal::default_host();
+        let devices = host.input_devices().map_err(other)?;
+        Ok(devices.filter_map(|device| device.name().ok()).collect())
+    }
+
+    pub(super) fn start(
+        name: Option<&str>,
+        lost: &Arc<AtomicBool>,
+        buffer: Option<&CaptureBuffer>,
+    ) -> io::Result<(CaptureBuffer, Stream)> {
+        let host = cpal::default_host();
+        let device = match name {
+            Some(name) => host
+                .input_devices()
+                .map_err(other)?
+                .find(|device| device.name().map(|n| n == name).unwrap_or(false)),
+            None => host.default_input_device(),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("capture device {} not found", name.unwrap_or("(default)")),
+            )
+        })?;
+
+        let config = device.default_input_config().map_err(other)?;
+        let sample_format = config.sample_format();
+        let config: cpal::StreamConfig = config.into();
+        let buffer = buffer_for(buffer, config.sample_rate.0, config.channels as u32);
+
+        let on_error = {
+            let lost = lost.clone();
+            move |err| {
+                if let cpal::StreamError::DeviceNotAvailable = err {
+                    lost.store(true, Ordering::Relaxed);
+                }
+            }
+        };
+        let writer = buffer.clone();
+
+        // libprojectm takes f32, i16 and u8 as they are, other formats are converted to f32
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &config,
+                move |data: &[f32], _: &cpal::InputCallbackInfo| writer.push_float(data),
+                on_error,
+                None,
+            ),
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &config,
+                move |data: &[i16], _: &cpal::InputCallbackInfo| writer.push_int16(data),
+                on_error,
+                None,
+            ),
+            cpal::SampleFormat::U8 => device.build_input_stream(
+                &config,
+                move |data: &[u8], _: &cpal::InputCallbackInfo| writer.push_uint8(data),
+                on_error,
+                None,
+            ),
+            cpal::SampleFormat::I8 => build_float::<i8, _>(&device, &config, writer, on_error),
+            cpal::SampleFormat::I32 => build_float::<i32, _>(&device, &config, writer, on_error),
+            cpal::SampleFormat::I64 => build_float::<i64, _>(&device, &config, writer, on_error),
+            cpal::SampleFormat::U16 => build_float::<u16, _>(&device, &config, writer, on_error),
+            cpal::SampleFormat::U32 => build_float::<u32, _>(&device, &config, writer, on_error),
+            cpal::SampleFormat::U64 => build_float::<u64, _>(&device, &config, writer, on_error),
+            cpal::SampleFormat::F64 => build_float::<f64, _>(&device, &config, writer, on_error),
+            format => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("unsupported sample format {}", format),
+                ))
+            }
+        }
+        .map_err(other)?;
+        stream.play().map_err(other)?;
+
+        Ok((buffer, Stream::Device(stream)))
+    }
+
+    fn build_float<T, E>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        writer: CaptureBuffer,
+        on_error: E,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where
+        T: cpal::SizedSample,
+        f32: cpal::FromSample<T>,
+        E: FnMut(cpal::StreamError) + Send + 'static,
+    {
+        device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let samples: Vec<f32> = data.iter().map(|&sample| sample.to_sample()).collect();
+                writer.push_float(&samples);
+            },
+            on_error,
+            None,
+        )
+    }
+
+    fn other<E: std::error::Error + Send + Sync + 'static>(err: E) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, err)
+    }
+}
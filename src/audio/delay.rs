@@ -0,0 +1,104 @@
+//! Latency compensation
+//!
+//! Audio is usually decoded and passed to `pcm_add_*` before it comes out of
+//! the speakers, so the visuals run ahead of what is heard. A [DelayLine]
+//! holds the audio back by the output latency. On an instance it is set with
+//! [set_audio_latency](crate::core::ProjectM::set_audio_latency); every
+//! `render_frame` call then moves the delay line on by one frame at the
+//! instance's fps and passes on the audio that is due.
+//!
+//! The delay line keeps its own clock, which only moves when it is told to,
+//! so the latency is the same when rendering faster than real time.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::core::ProjectMChannels;
+
+struct Chunk {
+    added: Duration,
+    channels: ProjectMChannels,
+    samples: Vec<f32>,
+}
+
+/// Holds audio until its clock is `latency` past the time the audio was added.
+pub struct DelayLine {
+    latency: Duration,
+    now: Duration,
+    chunks: VecDeque<Chunk>,
+}
+
+impl DelayLine {
+    pub fn new(latency: Duration) -> Self {
+        DelayLine {
+            latency,
+            now: Duration::ZERO,
+            chunks: VecDeque::new(),
+        }
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Change the latency. Audio already held is released by the new latency.
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    /// Samples being held.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.samples.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Time on the delay line's clock, the sum of all [advance](Self::advance) calls.
+    pub fn time(&self) -> Duration {
+        self.now
+    }
+
+    /// Hold interleaved samples, added at the current [time](Self::time).
+    pub fn push(&mut self, samples: &[f32], channels: ProjectMChannels) {
+        self.chunks.push_back(Chunk {
+            added: self.now,
+            channels,
+            samples: samples.to_vec(),
+        });
+    }
+
+    /// Move the clock on by `elapsed`, e.g. one frame, and pass all audio
+    /// that is due then to `output`, oldest first.
+    pub fn advance<F>(&mut self, elapsed: Duration, mut output: F)
+    where
+        F: FnMut(&[f32], ProjectMChannels),
+    {
+        self.now += elapsed;
+
+        while let Some(chunk) = self.chunks.front() {
+            if self.now.saturating_sub(chunk.added) < self.latency {
+                break;
+            }
+            if let Some(chunk) = self.chunks.pop_front() {
+                output(&chunk.samples, chunk.channels);
+            }
+        }
+    }
+
+    /// Pass all held audio to `output`, due or not.
+    pub fn flush<F>(&mut self, mut output: F)
+    where
+        F: FnMut(&[f32], ProjectMChannels),
+    {
+        for chunk in self.chunks.drain(..) {
+            output(&chunk.samples, chunk.channels);
+        }
+    }
+
+    /// Drop all held audio, e.g. when playback is stopped or seeks.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
@@ -0,0 +1,233 @@
+//! Gain, normalization and automatic gain control
+//!
+//! A [GainStage] evens out the level of the audio before libprojectm sees
+//! it, so quiet inputs still move the visuals and loud ones don't trigger a
+//! hard cut on every beat. Set one on an instance and it applies to all
+//! `pcm_add_*` calls:
+//!
+//! ```no_run
+//! use projectm::audio::gain::{GainMode, GainStage};
+//! use projectm::core::ProjectM;
+//!
+//! let projectm = ProjectM::create();
+//! projectm.set_gain_stage(Some(GainStage::new(
+//!     GainMode::Automatic {
+//!         target_db: -12.0,
+//!         attack: 0.01,
+//!         release: 2.0,
+//!         max_gain_db: 30.0,
+//!     },
+//!     44100,
+//! )));
+//! ```
+//!
+//! Stereo channels share one gain, so the stereo image is kept. The output
+//! is clipped to -1.0 to 1.0.
+
+use crate::core::ProjectMChannels;
+
+/// Levels below this many dBFS count as silence; the gain isn't raised on silence.
+const GATE_DB: f32 = -60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GainMode {
+    /// A constant gain.
+    Fixed { gain_db: f32 },
+    /// Scale the peak level of the last `window` seconds to `target_db`.
+    PeakNormalize {
+        target_db: f32,
+        window: f32,
+        max_gain_db: f32,
+    },
+    /// Scale the RMS level of the last `window` seconds to `target_db`.
+    RmsNormalize {
+        target_db: f32,
+        window: f32,
+        max_gain_db: f32,
+    },
+    /// Follow the peak envelope of the signal, rising within `attack` and
+    /// falling within `release` seconds, and keep it at `target_db`.
+    Automatic {
+        target_db: f32,
+        attack: f32,
+        release: f32,
+        max_gain_db: f32,
+    },
+}
+
+/// Levels of the audio processed by the last call, in dBFS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Meter {
+    pub input_peak_db: f32,
+    pub input_rms_db: f32,
+    pub output_peak_db: f32,
+    pub output_rms_db: f32,
+    /// Gain at the end of the call.
+    pub gain_db: f32,
+    /// Output samples that had to be clipped.
+    pub clipped: usize,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Meter {
+            input_peak_db: f32::NEG_INFINITY,
+            input_rms_db: f32::NEG_INFINITY,
+            output_peak_db: f32::NEG_INFINITY,
+            output_rms_db: f32::NEG_INFINITY,
+            gain_db: 0.0,
+            clipped: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GainStage {
+    mode: GainMode,
+    sample_rate: u32,
+    // Detector state: peak hold, mean square or envelope, depending on the mode
+    level: f32,
+    gain: f32,
+    meter: Meter,
+}
+
+impl GainStage {
+    pub fn new(mode: GainMode, sample_rate: u32) -> Self {
+        let gain = match mode {
+            GainMode::Fixed { gain_db } => db_to_linear(gain_db),
+            _ => 1.0,
+        };
+
+        GainStage {
+            mode,
+            sample_rate: sample_rate.max(1),
+            level: 0.0,
+            gain,
+            meter: Meter::default(),
+        }
+    }
+
+    pub fn mode(&self) -> GainMode {
+        self.mode
+    }
+
+    /// Change the mode, keeping the current gain as the starting point.
+    pub fn set_mode(&mut self, mode: GainMode) {
+        if let GainMode::Fixed { gain_db } = mode {
+            self.gain = db_to_linear(gain_db);
+        }
+        self.mode = mode;
+        self.level = 0.0;
+    }
+
+    /// The current gain in dB.
+    pub fn gain_db(&self) -> f32 {
+        linear_to_db(self.gain)
+    }
+
+    pub fn meter(&self) -> Meter {
+        self.meter
+    }
+
+    /// Apply the gain to interleaved samples in place.
+    pub fn process(&mut self, samples: &mut [f32], channels: ProjectMChannels) {
+        let channels = channels.max(1) as usize;
+        let gate = db_to_linear(GATE_DB);
+
+        let mut input_peak = 0.0f32;
+        let mut input_square = 0.0f32;
+        let mut output_peak = 0.0f32;
+        let mut output_square = 0.0f32;
+        let mut clipped = 0;
+
+        for frame in samples.chunks_mut(channels) {
+            let peak = frame
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            self.update_gain(peak, gate);
+
+            for sample in frame.iter_mut() {
+                input_peak = input_peak.max(sample.abs());
+                input_square += *sample * *sample;
+
+                let output = *sample * self.gain;
+                if output.abs() > 1.0 {
+                    clipped += 1;
+                }
+                *sample = output.clamp(-1.0, 1.0);
+
+                output_peak = output_peak.max(sample.abs());
+                output_square += *sample * *sample;
+            }
+        }
+
+        let count = samples.len().max(1) as f32;
+        self.meter = Meter {
+            input_peak_db: linear_to_db(input_peak),
+            input_rms_db: linear_to_db((input_square / count).sqrt()),
+            output_peak_db: linear_to_db(output_peak),
+            output_rms_db: linear_to_db((output_square / count).sqrt()),
+            gain_db: self.gain_db(),
+            clipped,
+        };
+    }
+
+    fn update_gain(&mut self, peak: f32, gate: f32) {
+        let (level, target_db, max_gain_db) = match self.mode {
+            GainMode::Fixed { .. } => return,
+            GainMode::PeakNormalize {
+                target_db,
+                window,
+                max_gain_db,
+            } => {
+                let decay = self.coefficient(window);
+                self.level = peak.max(self.level * decay);
+                (self.level, target_db, max_gain_db)
+            }
+            GainMode::RmsNormalize {
+                target_db,
+                window,
+                max_gain_db,
+            } => {
+                // Mean square of the last window
+                let decay = self.coefficient(window);
+                self.level = self.level * decay + peak * peak * (1.0 - decay);
+                (self.level.sqrt(), target_db, max_gain_db)
+            }
+            GainMode::Automatic {
+                target_db,
+                attack,
+                release,
+                max_gain_db,
+            } => {
+                let time = if peak > self.level { attack } else { release };
+                let decay = self.coefficient(time);
+                self.level = self.level * decay + peak * (1.0 - decay);
+                (self.level, target_db, max_gain_db)
+            }
+        };
+
+        // Hold the gain through silence instead of amplifying the noise floor
+        if level > gate {
+            self.gain = (db_to_linear(target_db) / level).min(db_to_linear(max_gain_db));
+        }
+    }
+
+    /// Per sample decay of a smoother with a time constant of `seconds`.
+    fn coefficient(&self, seconds: f32) -> f32 {
+        if seconds <= 0.0 {
+            0.0
+        } else {
+            (-1.0 / (seconds * self.sample_rate as f32)).exp()
+        }
+    }
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Level in dB, negative infinity for 0.
+pub fn linear_to_db(linear: f32) -> f32 {
+    20.0 * linear.log10()
+}
@@ -0,0 +1,277 @@
+//! Raw PCM from pipes
+//!
+//! A [PcmSource] reads headerless PCM audio from stdin, a file descriptor, a
+//! FIFO or any other reader and passes it to an instance with the matching
+//! `pcm_add_*` function. Audio is paced either per rendered frame, for
+//! offline rendering, or by wall clock time for live playback:
+//!
+//! ```no_run
+//! use projectm::audio::pipe::{PcmFormat, PcmSource, SampleFormat};
+//! use projectm::core::ProjectM;
+//!
+//! // ffmpeg -i song.mp3 -f s16le -ac 2 -ar 44100 - | visualizer
+//! let projectm = ProjectM::create();
+//! let format = PcmFormat::new(SampleFormat::S16Le, 2, 44100);
+//! let mut source = PcmSource::stdin(format).unwrap();
+//! while source.feed_frame(&projectm, 60).unwrap() {
+//!     projectm.render_frame();
+//! }
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::core::{Backend, ProjectM, ProjectMChannels};
+
+/// Sample encodings, named like ffmpeg's raw formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Signed 16 bit little endian.
+    S16Le,
+    /// 32 bit float little endian.
+    F32Le,
+    /// Unsigned 8 bit.
+    U8,
+}
+
+impl SampleFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleFormat::S16Le => 2,
+            SampleFormat::F32Le => 4,
+            SampleFormat::U8 => 1,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleFormat::S16Le => "s16le",
+            SampleFormat::F32Le => "f32le",
+            SampleFormat::U8 => "u8",
+        }
+    }
+}
+
+impl FromStr for SampleFormat {
+    type Err = io::Error;
+
+    fn from_str(name: &str) -> io::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "s16le" => Ok(SampleFormat::S16Le),
+            "f32le" => Ok(SampleFormat::F32Le),
+            "u8" => Ok(SampleFormat::U8),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown sample format '{}', use s16le, f32le or u8", name),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Layout of a raw PCM stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub sample_format: SampleFormat,
+    /// 1 or 2, interleaved.
+    pub channels: ProjectMChannels,
+    pub sample_rate: u32,
+}
+
+impl PcmFormat {
+    pub fn new(sample_format: SampleFormat, channels: ProjectMChannels, sample_rate: u32) -> Self {
+        PcmFormat {
+            sample_format,
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// Bytes of one sample for every channel.
+    pub fn bytes_per_frame(&self) -> usize {
+        self.sample_format.bytes_per_sample() * self.channels as usize
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if !(1..=2).contains(&self.channels) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} channels are not supported, use 1 or 2", self.channels),
+            ));
+        }
+        if self.sample_rate == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the sample rate must not be 0",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for PcmFormat {
+    /// CD audio, `-f s16le -ac 2 -ar 44100`.
+    fn default() -> Self {
+        PcmFormat::new(SampleFormat::S16Le, 2, 44100)
+    }
+}
+
+/// Reads raw PCM and passes it on in the pace it should be heard.
+pub struct PcmSource<R: Read> {
+    reader: R,
+    format: PcmFormat,
+    bytes: Vec<u8>,
+    frames_fed: u64,
+    // Fraction of a sample frame carried over between rendered frames
+    frame_remainder: f64,
+    started: Option<Instant>,
+    end_of_stream: bool,
+}
+
+impl PcmSource<io::Stdin> {
+    pub fn stdin(format: PcmFormat) -> io::Result<Self> {
+        Self::new(io::stdin(), format)
+    }
+}
+
+impl PcmSource<File> {
+    /// Open a FIFO (named pipe) or a file with raw PCM data.
+    pub fn open<P: AsRef<Path>>(path: P, format: PcmFormat) -> io::Result<Self> {
+        Self::new(File::open(path)?, format)
+    }
+
+    /// Read from an inherited file descriptor, e.g. `3` for `3<&0`-style redirection.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor that nothing else owns.
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd(fd: std::os::unix::io::RawFd, format: PcmFormat) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+        Self::new(File::from_raw_fd(fd), format)
+    }
+}
+
+impl<R: Read> PcmSource<R> {
+    pub fn new(reader: R, format: PcmFormat) -> io::Result<Self> {
+        format.check()?;
+
+        Ok(PcmSource {
+            reader,
+            format,
+            bytes: Vec::new(),
+            frames_fed: 0,
+            frame_remainder: 0.0,
+            started: None,
+            end_of_stream: false,
+        })
+    }
+
+    pub fn format(&self) -> PcmFormat {
+        self.format
+    }
+
+    /// Sample frames passed to the instance so far.
+    pub fn frames_fed(&self) -> u64 {
+        self.frames_fed
+    }
+
+    /// Seconds of audio passed to the instance so far.
+    pub fn position(&self) -> f64 {
+        self.frames_fed as f64 / self.format.sample_rate as f64
+    }
+
+    pub fn is_end_of_stream(&self) -> bool {
+        self.end_of_stream
+    }
+
+    /// Feed the audio of one video frame at `fps` frames per second. Returns
+    /// false once the stream has ended.
+    pub fn feed_frame<B: Backend>(&mut self, projectm: &ProjectM<B>, fps: u32) -> io::Result<bool> {
+        let due = self.format.sample_rate as f64 / fps.max(1) as f64 + self.frame_remainder;
+        let frames = due.floor();
+        self.frame_remainder = due - frames;
+
+        self.feed_frames(projectm, frames as usize)?;
+        Ok(!self.end_of_stream)
+    }
+
+    /// Feed the audio due since the first call, by wall clock time. Blocks
+    /// until the data has arrived. Returns false once the stream has ended.
+    pub fn feed_elapsed<B: Backend>(&mut self, projectm: &ProjectM<B>) -> io::Result<bool> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let due = (started.elapsed().as_secs_f64() * self.format.sample_rate as f64) as u64;
+
+        self.feed_frames(projectm, due.saturating_sub(self.frames_fed) as usize)?;
+        Ok(!self.end_of_stream)
+    }
+
+    /// Read up to `frames` sample frames and feed them. Returns the number of
+    /// frames fed, which is less than requested only at the end of the stream.
+    pub fn feed_frames<B: Backend>(
+        &mut self,
+        projectm: &ProjectM<B>,
+        frames: usize,
+    ) -> io::Result<usize> {
+        let frame_size = self.format.bytes_per_frame();
+        self.bytes.resize(frames * frame_size, 0);
+
+        let mut filled = 0;
+        while filled < self.bytes.len() {
+            match self.reader.read(&mut self.bytes[filled..]) {
+                Ok(0) => {
+                    self.end_of_stream = true;
+                    break;
+                }
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        // A partial frame at the end of the stream is dropped
+        let frames = filled / frame_size;
+        let bytes = &self.bytes[..frames * frame_size];
+        let channels = self.format.channels;
+        let max_samples = B::pcm_get_max_samples() as usize;
+        let piece = (max_samples / channels as usize * channels as usize).max(channels as usize);
+
+        match self.format.sample_format {
+            SampleFormat::S16Le => {
+                let samples: Vec<i16> = bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect();
+                for piece in samples.chunks(piece) {
+                    projectm.pcm_add_int16(piece, channels);
+                }
+            }
+            SampleFormat::F32Le => {
+                let samples: Vec<f32> = bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                for piece in samples.chunks(piece) {
+                    projectm.pcm_add_float(piece, channels);
+                }
+            }
+            SampleFormat::U8 => {
+                for piece in bytes.chunks(piece) {
+                    projectm.pcm_add_uint8(piece, channels);
+                }
+            }
+        }
+
+        self.frames_fed += frames as u64;
+        Ok(frames)
+    }
+}
@@ -0,0 +1,439 @@
+//! Synthetic test signals
+//!
+//! Deterministic signals for tests and demos. The same parameters (and seed
+//! for the noise signals) always produce the same samples on a given platform;
+//! the math functions of other platforms may round differently.
+//! [Signal::chunks] cuts a signal into buffers that can be passed straight to
+//! [pcm_add_float](crate::core::ProjectM::pcm_add_float):
+//!
+//! ```no_run
+//! use projectm::audio::signals::{ClickTrack, Signal};
+//! use projectm::core::{ProjectM, STEREO};
+//!
+//! let projectm = ProjectM::create();
+//! for chunk in ClickTrack::new(44100, 120.0).chunks(STEREO, 10.0) {
+//!     projectm.pcm_add_float(&chunk, STEREO);
+//! }
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::core::{ProjectM, ProjectMChannels};
+
+/// An endless source of stereo frames.
+pub trait Signal {
+    fn sample_rate(&self) -> u32;
+
+    /// The next frame as (left, right).
+    fn next_frame(&mut self) -> (f32, f32);
+
+    /// `duration` seconds of interleaved samples in buffers of at most
+    /// [pcm_get_max_samples](ProjectM::pcm_get_max_samples) samples.
+    fn chunks(self, channels: ProjectMChannels, duration: f64) -> Chunks<Self>
+    where
+        Self: Sized,
+    {
+        let chunk_samples = ProjectM::pcm_get_max_samples() as usize;
+        self.chunks_of(channels, duration, chunk_samples)
+    }
+
+    /// Like [chunks](Signal::chunks) with buffers of at most `chunk_samples` samples.
+    fn chunks_of(
+        self,
+        channels: ProjectMChannels,
+        duration: f64,
+        chunk_samples: usize,
+    ) -> Chunks<Self>
+    where
+        Self: Sized,
+    {
+        let channels = channels.clamp(1, 2);
+        let frames = (duration.max(0.0) * self.sample_rate() as f64).round() as u64;
+        Chunks {
+            signal: self,
+            channels,
+            chunk_frames: (chunk_samples / channels as usize).max(1),
+            remaining: frames,
+        }
+    }
+}
+
+/// Buffers of interleaved samples, see [Signal::chunks].
+pub struct Chunks<S: Signal> {
+    signal: S,
+    channels: ProjectMChannels,
+    chunk_frames: usize,
+    remaining: u64,
+}
+
+impl<S: Signal> Iterator for Chunks<S> {
+    type Item = Vec<f32>;
+
+    fn next(&mut self) -> Option<Vec<f32>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let frames = self.remaining.min(self.chunk_frames as u64) as usize;
+        self.remaining -= frames as u64;
+
+        let mut chunk = Vec::with_capacity(frames * self.channels as usize);
+        for _ in 0..frames {
+            let (left, right) = self.signal.next_frame();
+            if self.channels == 1 {
+                chunk.push((left + right) / 2.0);
+            } else {
+                chunk.push(left);
+                chunk.push(right);
+            }
+        }
+        Some(chunk)
+    }
+}
+
+/// All zero.
+pub struct Silence {
+    sample_rate: u32,
+}
+
+impl Silence {
+    pub fn new(sample_rate: u32) -> Self {
+        Silence { sample_rate }
+    }
+}
+
+impl Signal for Silence {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        (0.0, 0.0)
+    }
+}
+
+/// A sine wave on both channels.
+pub struct Sine {
+    sample_rate: u32,
+    frequency: f64,
+    amplitude: f32,
+    phase: f64,
+}
+
+impl Sine {
+    pub fn new(sample_rate: u32, frequency: f64, amplitude: f32) -> Self {
+        Sine {
+            sample_rate,
+            frequency,
+            amplitude,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Signal for Sine {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let sample = (2.0 * PI * self.phase).sin() as f32 * self.amplitude;
+        self.phase = (self.phase + self.frequency / self.sample_rate as f64).fract();
+        (sample, sample)
+    }
+}
+
+/// A sine sweeping exponentially from one frequency to another, then starting over.
+pub struct Sweep {
+    sample_rate: u32,
+    start: f64,
+    end: f64,
+    period: u64,
+    amplitude: f32,
+    position: u64,
+    phase: f64,
+}
+
+impl Sweep {
+    /// Sweep from `start` to `end` Hz in `duration` seconds.
+    pub fn new(sample_rate: u32, start: f64, end: f64, duration: f64, amplitude: f32) -> Self {
+        Sweep {
+            sample_rate,
+            start,
+            end,
+            period: ((duration * sample_rate as f64) as u64).max(1),
+            amplitude,
+            position: 0,
+            phase: 0.0,
+        }
+    }
+
+    /// The frequency of the next frame in Hz.
+    pub fn frequency(&self) -> f64 {
+        let progress = self.position as f64 / self.period as f64;
+        self.start * (self.end / self.start).powf(progress)
+    }
+}
+
+impl Signal for Sweep {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let sample = (2.0 * PI * self.phase).sin() as f32 * self.amplitude;
+        self.phase = (self.phase + self.frequency() / self.sample_rate as f64).fract();
+        self.position = (self.position + 1) % self.period;
+        (sample, sample)
+    }
+}
+
+/// Uniform white noise on both channels.
+pub struct WhiteNoise {
+    sample_rate: u32,
+    amplitude: f32,
+    random: Xorshift,
+}
+
+impl WhiteNoise {
+    pub fn new(sample_rate: u32, amplitude: f32, seed: u64) -> Self {
+        WhiteNoise {
+            sample_rate,
+            amplitude,
+            random: Xorshift::new(seed),
+        }
+    }
+}
+
+impl Signal for WhiteNoise {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let sample = self.random.next_f32() * self.amplitude;
+        (sample, sample)
+    }
+}
+
+/// Noise with equal energy per octave, on both channels.
+pub struct PinkNoise {
+    sample_rate: u32,
+    amplitude: f32,
+    random: Xorshift,
+    state: [f32; 7],
+}
+
+impl PinkNoise {
+    pub fn new(sample_rate: u32, amplitude: f32, seed: u64) -> Self {
+        PinkNoise {
+            sample_rate,
+            amplitude,
+            random: Xorshift::new(seed),
+            state: [0.0; 7],
+        }
+    }
+}
+
+impl Signal for PinkNoise {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        // Paul Kellet's filter, scaled to stay within about -1.0 to 1.0
+        let white = self.random.next_f32();
+        let b = &mut self.state;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+
+        let sample = (pink * 0.11).clamp(-1.0, 1.0) * self.amplitude;
+        (sample, sample)
+    }
+}
+
+/// A short full scale click on every beat.
+pub struct ClickTrack {
+    sample_rate: u32,
+    period: u64,
+    click: u64,
+    position: u64,
+}
+
+impl ClickTrack {
+    pub fn new(sample_rate: u32, bpm: f64) -> Self {
+        ClickTrack {
+            sample_rate,
+            period: beat_period(sample_rate, bpm),
+            // 2 ms
+            click: (sample_rate as u64 / 500).max(1),
+            position: 0,
+        }
+    }
+}
+
+impl Signal for ClickTrack {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let sample = if self.position < self.click {
+            // Alternating samples, a burst at the Nyquist frequency
+            if self.position % 2 == 0 {
+                1.0
+            } else {
+                -1.0
+            }
+        } else {
+            0.0
+        };
+        self.position = (self.position + 1) % self.period;
+        (sample, sample)
+    }
+}
+
+/// A drum sound of a [DrumPattern].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drum {
+    Kick,
+    Snare,
+    HiHat,
+}
+
+/// A looped drum pattern with one step per sixteenth note.
+///
+/// Patterns are strings with one character per step: `k` kick, `s` snare,
+/// `h` hi-hat and anything else a rest, e.g. `"k...s...k.k.s..."`.
+pub struct DrumPattern {
+    sample_rate: u32,
+    steps: Vec<Option<Drum>>,
+    step_length: u64,
+    position: u64,
+    random: Xorshift,
+}
+
+impl DrumPattern {
+    pub fn new(sample_rate: u32, bpm: f64, pattern: &str) -> Self {
+        let steps = pattern
+            .chars()
+            .map(|step| match step {
+                'k' | 'K' => Some(Drum::Kick),
+                's' | 'S' => Some(Drum::Snare),
+                'h' | 'H' => Some(Drum::HiHat),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        DrumPattern {
+            sample_rate,
+            steps: if steps.is_empty() { vec![None] } else { steps },
+            step_length: (beat_period(sample_rate, bpm) / 4).max(1),
+            position: 0,
+            random: Xorshift::new(1),
+        }
+    }
+
+    /// Four on the floor with snares on two and four and eighth note hi-hats.
+    pub fn rock(sample_rate: u32, bpm: f64) -> Self {
+        Self::new(sample_rate, bpm, "k.h.s.h.k.h.s.h.")
+    }
+}
+
+impl Signal for DrumPattern {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let step = (self.position / self.step_length) as usize % self.steps.len();
+        let t = (self.position % self.step_length) as f64 / self.sample_rate as f64;
+        let noise = self.random.next_f32() as f64;
+
+        let sample = match self.steps[step] {
+            // Sine dropping from 150 to 50 Hz
+            Some(Drum::Kick) => {
+                let phase = 50.0 * t + 100.0 * (1.0 - (-t * 30.0).exp()) / 30.0;
+                (2.0 * PI * phase).sin() * (-t * 12.0).exp()
+            }
+            Some(Drum::Snare) => {
+                let tone = (2.0 * PI * 200.0 * t).sin() * (-t * 30.0).exp();
+                0.5 * tone + 0.6 * noise * (-t * 20.0).exp()
+            }
+            Some(Drum::HiHat) => 0.3 * noise * (-t * 80.0).exp(),
+            None => 0.0,
+        };
+
+        let total = self.step_length * self.steps.len() as u64;
+        self.position = (self.position + 1) % total;
+        let sample = sample.clamp(-1.0, 1.0) as f32;
+        (sample, sample)
+    }
+}
+
+/// A sine with the right channel shifted against the left one, e.g. 180
+/// degrees for an out of phase signal that cancels out when mixed to mono.
+pub struct StereoPhase {
+    sample_rate: u32,
+    frequency: f64,
+    offset: f64,
+    amplitude: f32,
+    phase: f64,
+}
+
+impl StereoPhase {
+    pub fn new(sample_rate: u32, frequency: f64, degrees: f64, amplitude: f32) -> Self {
+        StereoPhase {
+            sample_rate,
+            frequency,
+            offset: degrees / 360.0,
+            amplitude,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Signal for StereoPhase {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let left = (2.0 * PI * self.phase).sin() as f32 * self.amplitude;
+        let right = (2.0 * PI * (self.phase + self.offset)).sin() as f32 * self.amplitude;
+        self.phase = (self.phase + self.frequency / self.sample_rate as f64).fract();
+        (left, right)
+    }
+}
+
+fn beat_period(sample_rate: u32, bpm: f64) -> u64 {
+    ((sample_rate as f64 * 60.0 / bpm.max(f64::MIN_POSITIVE)).round() as u64).max(1)
+}
+
+/// xorshift64*, fixed so that noise doesn't change with the `rand` version.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn new(seed: u64) -> Self {
+        // The state must not be zero
+        Xorshift(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+
+    /// Uniform in -1.0 to 1.0.
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let value = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (value >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
@@ -0,0 +1,364 @@
+//! Waveform and spectrum snapshots
+//!
+//! [ProjectM](crate::core::ProjectM) keeps the most recent audio passed to
+//! `pcm_add_*` in a [WaveformBuffer]. [get_waveform](crate::core::ProjectM::get_waveform)
+//! returns a copy of it and [get_spectrum](crate::core::ProjectM::get_spectrum)
+//! the magnitude spectrum computed by a [Spectrum]:
+//!
+//! ```no_run
+//! use projectm::audio::spectrum::{Binning, Spectrum, WindowFunction};
+//! use projectm::core::ProjectM;
+//!
+//! let projectm = ProjectM::create();
+//! let spectrum = Spectrum::new(1024, 44100)
+//!     .window(WindowFunction::Hann)
+//!     .binning(Binning::Logarithmic { bands: 32, min_frequency: 40.0 });
+//! let bars = projectm.get_spectrum(&spectrum);
+//! ```
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+use crate::core::ProjectMChannels;
+
+/// Audio frames kept by a new [WaveformBuffer], libprojectm's maximum samples per call.
+pub const DEFAULT_WAVEFORM_LENGTH: usize = 2048;
+
+/// Copy of the most recent audio, oldest sample first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Waveform {
+    pub left: Vec<f32>,
+    /// Same as `left` for mono input.
+    pub right: Vec<f32>,
+}
+
+impl Waveform {
+    pub fn len(&self) -> usize {
+        self.left.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    /// Average of both channels.
+    pub fn mono(&self) -> Vec<f32> {
+        self.left
+            .iter()
+            .zip(&self.right)
+            .map(|(left, right)| (left + right) / 2.0)
+            .collect()
+    }
+}
+
+/// Keeps the last frames of interleaved mono or stereo audio.
+#[derive(Debug, Clone)]
+pub struct WaveformBuffer {
+    frames: VecDeque<(f32, f32)>,
+    length: usize,
+}
+
+impl WaveformBuffer {
+    pub fn new(length: usize) -> Self {
+        WaveformBuffer {
+            frames: VecDeque::with_capacity(length),
+            length,
+        }
+    }
+
+    /// Number of frames kept.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Change the number of frames kept, dropping the oldest ones if needed.
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length;
+        while self.frames.len() > length {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Add interleaved samples. Channels after the second are ignored.
+    pub fn push(&mut self, samples: &[f32], channels: ProjectMChannels) {
+        let channels = channels.max(1) as usize;
+        // Only the newest frames can end up in the buffer
+        let skip = (samples.len() / channels).saturating_sub(self.length);
+
+        for frame in samples.chunks_exact(channels).skip(skip) {
+            if self.frames.len() == self.length {
+                self.frames.pop_front();
+            }
+            let left = frame[0];
+            let right = frame.get(1).copied().unwrap_or(left);
+            self.frames.push_back((left, right));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        Waveform {
+            left: self.frames.iter().map(|frame| frame.0).collect(),
+            right: self.frames.iter().map(|frame| frame.1).collect(),
+        }
+    }
+}
+
+impl Default for WaveformBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_WAVEFORM_LENGTH)
+    }
+}
+
+/// Window applied to the samples before the transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowFunction {
+    /// The window value at `index` of `size` samples.
+    pub fn value(self, index: usize, size: usize) -> f32 {
+        if size < 2 {
+            return 1.0;
+        }
+        let phase = 2.0 * PI * index as f32 / (size - 1) as f32;
+        match self {
+            WindowFunction::Rectangular => 1.0,
+            WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+            WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+            WindowFunction::Blackman => 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos(),
+        }
+    }
+}
+
+/// How the FFT bins are reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binning {
+    /// Every bin from 0 Hz up to the Nyquist frequency, `size / 2 + 1` values.
+    Linear,
+    /// `bands` bands with logarithmically spaced edges from `min_frequency`
+    /// up to the Nyquist frequency. Each band is the highest bin magnitude in it.
+    Logarithmic { bands: usize, min_frequency: f32 },
+}
+
+/// Configuration of a magnitude spectrum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    size: usize,
+    sample_rate: u32,
+    window: WindowFunction,
+    binning: Binning,
+}
+
+impl Spectrum {
+    /// A spectrum over the last `size` samples with a Hann window and linear bins.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is not a power of two of at least 2.
+    pub fn new(size: usize, sample_rate: u32) -> Self {
+        assert!(
+            size >= 2 && size.is_power_of_two(),
+            "FFT size must be a power of two"
+        );
+        Spectrum {
+            size,
+            sample_rate: sample_rate.max(1),
+            window: WindowFunction::Hann,
+            binning: Binning::Linear,
+        }
+    }
+
+    pub fn window(mut self, window: WindowFunction) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn binning(mut self, binning: Binning) -> Self {
+        self.binning = binning;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Center frequency of an FFT bin in Hz.
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate as f32 / self.size as f32
+    }
+
+    /// Lower and upper edge in Hz of each value returned by [compute](Spectrum::compute).
+    pub fn band_edges(&self) -> Vec<(f32, f32)> {
+        let half_bin = self.bin_frequency(1) / 2.0;
+        match self.binning {
+            Binning::Linear => (0..=self.size / 2)
+                .map(|bin| {
+                    let center = self.bin_frequency(bin);
+                    ((center - half_bin).max(0.0), center + half_bin)
+                })
+                .collect(),
+            Binning::Logarithmic {
+                bands,
+                min_frequency,
+            } => {
+                let min = min_frequency.max(f32::MIN_POSITIVE);
+                let ratio = (self.sample_rate as f32 / 2.0 / min).powf(1.0 / bands as f32);
+                (0..bands)
+                    .map(|band| {
+                        (
+                            min * ratio.powi(band as i32),
+                            min * ratio.powi(band as i32 + 1),
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Magnitudes of the last `size` mono samples, zero padded at the start
+    /// if there are fewer. A full scale sine has a magnitude of about 1.0.
+    pub fn compute(&self, samples: &[f32]) -> Vec<f32> {
+        let start = samples.len().saturating_sub(self.size);
+        let samples = &samples[start..];
+        let padding = self.size - samples.len();
+
+        let mut window_sum = 0.0;
+        let mut buffer: Vec<Complex> = (0..self.size)
+            .map(|index| {
+                let window = self.window.value(index, self.size);
+                window_sum += window;
+                let sample = if index < padding {
+                    0.0
+                } else {
+                    samples[index - padding]
+                };
+                Complex::new(sample * window, 0.0)
+            })
+            .collect();
+        fft(&mut buffer);
+
+        let nyquist = self.size / 2;
+        let magnitudes: Vec<f32> = buffer[..=nyquist]
+            .iter()
+            .enumerate()
+            .map(|(bin, value)| {
+                // DC and Nyquist have no mirrored negative frequency
+                let scale = if bin == 0 || bin == nyquist { 1.0 } else { 2.0 };
+                value.norm() * scale / window_sum
+            })
+            .collect();
+
+        match self.binning {
+            Binning::Linear => magnitudes,
+            Binning::Logarithmic { .. } => self
+                .band_edges()
+                .into_iter()
+                .map(|(low, high)| self.band_magnitude(&magnitudes, low, high))
+                .collect(),
+        }
+    }
+
+    fn band_magnitude(&self, magnitudes: &[f32], low: f32, high: f32) -> f32 {
+        let bin_width = self.bin_frequency(1);
+        let first = (low / bin_width).ceil() as usize;
+        let last = ((high / bin_width).ceil() as usize).min(magnitudes.len());
+
+        if first < last {
+            magnitudes[first..last].iter().copied().fold(0.0, f32::max)
+        } else {
+            // Bands narrower than a bin take the bin they fall into
+            let bin = ((low + high) / 2.0 / bin_width).round() as usize;
+            magnitudes[bin.min(magnitudes.len() - 1)]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    fn norm(self) -> f32 {
+        self.re.hypot(self.im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// In-place iterative radix-2 FFT, the length must be a power of two.
+fn fft(buffer: &mut [Complex]) {
+    let n = buffer.len();
+
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let twiddle = Complex::new((angle * k as f32).cos(), (angle * k as f32).sin());
+                let even = buffer[start + k];
+                let odd = buffer[start + k + length / 2] * twiddle;
+                buffer[start + k] = even + odd;
+                buffer[start + k + length / 2] = even - odd;
+            }
+        }
+        length <<= 1;
+    }
+}
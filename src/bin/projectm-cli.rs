@@ -0,0 +1,629 @@
+//! Render presets to images or a video without a window
+//!
+//! ```text
+//! projectm-cli presets/ --audio song.wav --output song.mp4 --size 1920x1080
+//! ffmpeg -i song.mp3 -f s16le -ac 2 -ar 44100 - | projectm-cli presets/ --raw s16le --output frames/
+//! ```
+//!
+//! The OpenGL context is created with EGL on a pixel buffer, so no display is
+//! needed. Videos are encoded by piping the frames to `ffmpeg`.
+//!
+//! Audio is fed and presets change by rendered time: one frame at `--fps` per
+//! frame rendered, however long rendering it takes. libprojectM 4.0 has no
+//! way to set the frame time though, so the animation within a preset and the
+//! transitions between presets follow the wall clock. A render that runs
+//! faster or slower than real time animates at a different pace, and two
+//! renders of the same input are not identical.
+
+use std::ffi::c_void;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Child, Command, Stdio};
+use std::ptr;
+
+use khronos_egl as egl;
+use projectm::audio::downmix;
+use projectm::audio::pipe::{PcmFormat, PcmSource, SampleFormat};
+use projectm::core::{ProjectM, ProjectMChannels, MONO, STEREO};
+use projectm::playlist::Playlist;
+use projectm::textures::encode_tga;
+
+const USAGE: &str = "\
+Usage: projectm-cli [OPTIONS] <PRESET or DIRECTORY>...
+
+Render presets against an audio file or raw PCM stream, without a window.
+
+Options:
+  -a, --audio <FILE>               WAV file, or raw PCM with --raw; - reads stdin
+      --raw <FORMAT>               read raw PCM: s16le, f32le or u8
+      --channels <N>               channels of raw PCM [default: 2]
+      --rate <HZ>                  sample rate of raw PCM [default: 44100]
+  -o, --output <PATH>              a directory for TGA images, or a video file
+                                   encoded with ffmpeg; nothing is written without
+  -s, --size <WxH>                 frame size [default: 1280x720]
+  -r, --fps <N>                    frames per second [default: 60]
+      --mesh <WxH>                 per-vertex mesh size [default: libprojectM's]
+  -d, --duration <SECONDS>         seconds to render [default: length of the audio]
+      --preset-duration <SECONDS>  seconds per preset [default: 30]
+      --shuffle                    play the presets in random order
+      --seed <N>                   seed the random order of --shuffle
+  -t, --textures <DIR>             add a texture search path, may be repeated
+  -h, --help                       print this help
+";
+
+struct Options {
+    presets: Vec<PathBuf>,
+    audio: Option<PathBuf>,
+    raw: Option<SampleFormat>,
+    channels: ProjectMChannels,
+    rate: u32,
+    output: Option<PathBuf>,
+    width: usize,
+    height: usize,
+    fps: u32,
+    mesh: Option<(usize, usize)>,
+    duration: Option<f64>,
+    preset_duration: f64,
+    shuffle: bool,
+    seed: Option<u64>,
+    textures: Vec<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            presets: Vec::new(),
+            audio: None,
+            raw: None,
+            channels: STEREO,
+            rate: 44100,
+            output: None,
+            width: 1280,
+            height: 720,
+            fps: 60,
+            mesh: None,
+            duration: None,
+            preset_duration: 30.0,
+            shuffle: false,
+            seed: None,
+            textures: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            // Accept both "--fps 30" and "--fps=30"
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || match inline {
+                Some(value) => Ok(value.to_string()),
+                None => args.next().ok_or(format!("{} needs a value", flag)),
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => return Err(String::new()),
+                "-a" | "--audio" => options.audio = Some(value()?.into()),
+                "--raw" => options.raw = Some(value()?.parse().map_err(|e| format!("{}", e))?),
+                "--channels" => options.channels = number(&flag, &value()?)?,
+                "--rate" => options.rate = number(&flag, &value()?)?,
+                "-o" | "--output" => options.output = Some(value()?.into()),
+                "-s" | "--size" => (options.width, options.height) = size(&flag, &value()?)?,
+                "-r" | "--fps" => options.fps = number(&flag, &value()?)?,
+                "--mesh" => options.mesh = Some(size(&flag, &value()?)?),
+                "-d" | "--duration" => options.duration = Some(number(&flag, &value()?)?),
+                "--preset-duration" => options.preset_duration = number(&flag, &value()?)?,
+                "--shuffle" => options.shuffle = true,
+                "--seed" => options.seed = Some(number(&flag, &value()?)?),
+                "-t" | "--textures" => options.textures.push(value()?.into()),
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unknown option '{}'", arg))
+                }
+                _ => options.presets.push(arg.into()),
+            }
+        }
+
+        if options.presets.is_empty() {
+            return Err("no presets given".to_string());
+        }
+        if options.fps == 0 {
+            return Err("--fps must be at least 1".to_string());
+        }
+        if options.preset_duration <= 0.0 {
+            return Err("--preset-duration must be positive".to_string());
+        }
+        if options.audio.is_none() && options.duration.is_none() {
+            return Err("--duration is needed without --audio".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+fn size(flag: &str, value: &str) -> Result<(usize, usize), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("invalid size '{}' for {}, use WxH", value, flag))?;
+    Ok((number(flag, width)?, number(flag, height)?))
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) if message.is_empty() => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("projectm-cli: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&options) {
+        eprintln!("projectm-cli: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    // libprojectM needs a current context when the instance is created
+    let context = Context::new(options.width, options.height)?;
+
+    let projectm = ProjectM::create();
+    projectm
+        .set_window_size(options.width, options.height)
+        .map_err(invalid_input)?;
+    projectm.set_fps(options.fps).map_err(invalid_input)?;
+    if let Some((mesh_x, mesh_y)) = options.mesh {
+        projectm
+            .set_mesh_size(mesh_x, mesh_y)
+            .map_err(invalid_input)?;
+    }
+    // Presets are switched here, by rendered time
+    projectm.set_preset_locked(true);
+    for path in &options.textures {
+        projectm
+            .add_texture_search_path(path)
+            .map_err(invalid_input)?;
+    }
+
+    let mut playlist = Playlist::create(&projectm);
+    for path in &options.presets {
+        if path.is_dir() {
+            playlist.add_path(path, true).map_err(invalid_input)?;
+        } else {
+            playlist.add_preset(path, true).map_err(invalid_input)?;
+        }
+    }
+    if playlist.is_empty() {
+        return Err(invalid_input("no presets found"));
+    }
+    if let Some(seed) = options.seed {
+        playlist.set_seed(seed);
+    }
+
+    let mut audio = Audio::open(options)?;
+    let total_frames = match options.duration {
+        Some(duration) => Some((duration * options.fps as f64).round() as u64),
+        None => audio
+            .duration()
+            .map(|duration| (duration * options.fps as f64).ceil() as u64),
+    };
+    let preset_frames = ((options.preset_duration * options.fps as f64).round() as u64).max(1);
+
+    let mut output = Output::open(options, &audio)?;
+    let mut pixels = vec![0; options.width * options.height * 4];
+    let mut frame = 0;
+
+    while total_frames.map_or(true, |total| frame < total) {
+        // Raw streams of unknown length render until they end
+        if !audio.feed_frame(&projectm, options.fps)? && total_frames.is_none() {
+            break;
+        }
+
+        if frame % preset_frames == 0 {
+            if options.shuffle {
+                playlist.play_random();
+            } else {
+                playlist.play((frame / preset_frames) as u32 % playlist.len());
+            }
+        }
+
+        projectm.render_frame();
+        context.read_pixels(&mut pixels);
+        output.write_frame(frame, &pixels)?;
+        frame += 1;
+    }
+
+    output.finish()?;
+    eprintln!(
+        "projectm-cli: rendered {} frames ({:.1} s)",
+        frame,
+        frame as f64 / options.fps as f64
+    );
+    Ok(())
+}
+
+fn invalid_input<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+}
+
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// An OpenGL context on an offscreen EGL surface.
+struct Context {
+    egl: egl::Instance<egl::Static>,
+    display: egl::Display,
+    surface: egl::Surface,
+    context: egl::Context,
+    width: usize,
+    height: usize,
+}
+
+impl Context {
+    fn new(width: usize, height: usize) -> io::Result<Self> {
+        let egl = egl::Instance::new(egl::Static);
+        // Mesa's surfaceless platform needs no display server, other drivers
+        // get their default display
+        let surfaceless = unsafe {
+            egl.get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+        }
+        .ok()
+        .filter(|display| egl.initialize(*display).is_ok());
+        let display = match surfaceless {
+            Some(display) => display,
+            None => {
+                let display = unsafe { egl.get_display(egl::DEFAULT_DISPLAY) }
+                    .ok_or_else(|| egl_error("no display", egl::Error::BadDisplay))?;
+                egl.initialize(display)
+                    .map_err(|err| egl_error("initialize", err))?;
+                display
+            }
+        };
+
+        let (api, renderable, major, minor) = if cfg!(feature = "gles") {
+            (egl::OPENGL_ES_API, egl::OPENGL_ES3_BIT, 3, 0)
+        } else {
+            (egl::OPENGL_API, egl::OPENGL_BIT, 3, 3)
+        };
+        egl.bind_api(api)
+            .map_err(|err| egl_error("bind API", err))?;
+
+        let config = egl
+            .choose_first_config(
+                display,
+                &[
+                    egl::SURFACE_TYPE,
+                    egl::PBUFFER_BIT,
+                    egl::RENDERABLE_TYPE,
+                    renderable,
+                    egl::RED_SIZE,
+                    8,
+                    egl::GREEN_SIZE,
+                    8,
+                    egl::BLUE_SIZE,
+                    8,
+                    egl::ALPHA_SIZE,
+                    8,
+                    egl::NONE,
+                ],
+            )
+            .map_err(|err| egl_error("choose config", err))?
+            .ok_or_else(|| egl_error("no RGBA8 pbuffer config", egl::Error::BadConfig))?;
+
+        let surface = egl
+            .create_pbuffer_surface(
+                display,
+                config,
+                &[
+                    egl::WIDTH,
+                    width as egl::Int,
+                    egl::HEIGHT,
+                    height as egl::Int,
+                    egl::NONE,
+                ],
+            )
+            .map_err(|err| egl_error("create surface", err))?;
+
+        let mut attributes = vec![
+            egl::CONTEXT_MAJOR_VERSION,
+            major,
+            egl::CONTEXT_MINOR_VERSION,
+            minor,
+        ];
+        if !cfg!(feature = "gles") {
+            attributes.extend([
+                egl::CONTEXT_OPENGL_PROFILE_MASK,
+                egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            ]);
+        }
+        attributes.push(egl::NONE);
+        let context = egl
+            .create_context(display, config, None, &attributes)
+            .map_err(|err| egl_error("create context", err))?;
+        egl.make_current(display, Some(surface), Some(surface), Some(context))
+            .map_err(|err| egl_error("make current", err))?;
+
+        gl::load_with(|name| {
+            egl.get_proc_address(name)
+                .map_or(ptr::null(), |function| function as *const c_void)
+        });
+
+        Ok(Context {
+            egl,
+            display,
+            surface,
+            context,
+            width,
+            height,
+        })
+    }
+
+    /// Read the rendered frame as RGBA, rows top to bottom.
+    fn read_pixels(&self, pixels: &mut [u8]) {
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut c_void,
+            );
+        }
+
+        // OpenGL starts at the bottom row
+        let row = self.width * 4;
+        for y in 0..self.height / 2 {
+            let (top, bottom) = pixels.split_at_mut((self.height - 1 - y) * row);
+            top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+        }
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.destroy_surface(self.display, self.surface);
+        let _ = self.egl.terminate(self.display);
+    }
+}
+
+fn egl_error(action: &str, err: egl::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("EGL {}: {}", action, err))
+}
+
+enum Audio {
+    Silence,
+    Wav {
+        reader: hound::WavReader<Box<dyn Read>>,
+        remainder: f64,
+    },
+    Raw(PcmSource<Box<dyn Read>>),
+}
+
+impl Audio {
+    fn open(options: &Options) -> io::Result<Self> {
+        let path = match &options.audio {
+            Some(path) => path,
+            None => return Ok(Audio::Silence),
+        };
+        let input: Box<dyn Read> = if path.as_os_str() == "-" {
+            Box::new(io::stdin())
+        } else {
+            Box::new(BufReader::new(File::open(path)?))
+        };
+
+        match options.raw {
+            Some(sample_format) => {
+                let format = PcmFormat::new(sample_format, options.channels, options.rate);
+                Ok(Audio::Raw(PcmSource::new(input, format)?))
+            }
+            None => {
+                let reader = hound::WavReader::new(input).map_err(wav_error)?;
+                Ok(Audio::Wav {
+                    reader,
+                    remainder: 0.0,
+                })
+            }
+        }
+    }
+
+    /// Seconds of audio, if known up front.
+    fn duration(&self) -> Option<f64> {
+        match self {
+            Audio::Wav { reader, .. } => {
+                Some(reader.duration() as f64 / reader.spec().sample_rate as f64)
+            }
+            _ => None,
+        }
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        match self {
+            Audio::Silence => None,
+            Audio::Wav { reader, .. } => Some(reader.spec().sample_rate),
+            Audio::Raw(source) => Some(source.format().sample_rate),
+        }
+    }
+
+    /// Feed the audio of one video frame. Returns false once the audio has ended.
+    fn feed_frame(&mut self, projectm: &ProjectM, fps: u32) -> io::Result<bool> {
+        match self {
+            Audio::Silence => Ok(true),
+            Audio::Raw(source) => source.feed_frame(projectm, fps),
+            Audio::Wav { reader, remainder } => {
+                let spec = reader.spec();
+                let due = spec.sample_rate as f64 / fps as f64 + *remainder;
+                *remainder = due.fract();
+                let samples = due.floor() as usize * spec.channels as usize;
+
+                let samples: Vec<f32> = match spec.sample_format {
+                    hound::SampleFormat::Float => reader
+                        .samples::<f32>()
+                        .take(samples)
+                        .collect::<Result<_, _>>(),
+                    // Full scale depends on the sample width, e.g. 8 bit samples are -128..127
+                    hound::SampleFormat::Int => {
+                        let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+                        reader
+                            .samples::<i32>()
+                            .take(samples)
+                            .map(|sample| sample.map(|sample| sample as f32 / scale))
+                            .collect()
+                    }
+                }
+                .map_err(wav_error)?;
+
+                // libprojectM takes mono or stereo
+                let (samples, channels) = match spec.channels {
+                    1 => (samples, MONO),
+                    2 => (samples, STEREO),
+                    channels => (
+                        downmix(&samples, channels as ProjectMChannels).collect(),
+                        MONO,
+                    ),
+                };
+                let max_samples = ProjectM::pcm_get_max_samples() as usize;
+                for piece in samples.chunks(max_samples / 2 * 2) {
+                    projectm.pcm_add_float(piece, channels);
+                }
+
+                Ok(reader.len() > 0)
+            }
+        }
+    }
+}
+
+fn wav_error(err: hound::Error) -> io::Error {
+    match err {
+        hound::Error::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, format!("WAV: {}", err)),
+    }
+}
+
+enum Output {
+    None,
+    Frames {
+        directory: PathBuf,
+        width: u32,
+        height: u32,
+    },
+    Video(Child),
+}
+
+impl Output {
+    fn open(options: &Options, audio: &Audio) -> io::Result<Self> {
+        let path = match &options.output {
+            Some(path) => path,
+            None => return Ok(Output::None),
+        };
+
+        // Paths with a file extension are videos, anything else is a directory of frames
+        if path.extension().is_none() || path.is_dir() {
+            fs::create_dir_all(path)?;
+            return Ok(Output::Frames {
+                directory: path.clone(),
+                width: options.width as u32,
+                height: options.height as u32,
+            });
+        }
+
+        let mut ffmpeg = Command::new("ffmpeg");
+        ffmpeg
+            .args([
+                "-y",
+                "-loglevel",
+                "error",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgba",
+            ])
+            .arg("-s")
+            .arg(format!("{}x{}", options.width, options.height))
+            .arg("-framerate")
+            .arg(options.fps.to_string())
+            .args(["-i", "-"]);
+
+        // Mux the audio when it can be read again from its file
+        match (&options.audio, audio.sample_rate()) {
+            (Some(audio), Some(rate)) if audio.as_os_str() != "-" => {
+                if let Some(format) = options.raw {
+                    ffmpeg
+                        .args(["-f", format.name(), "-ar"])
+                        .arg(rate.to_string())
+                        .arg("-ac")
+                        .arg(options.channels.to_string());
+                }
+                ffmpeg.arg("-i").arg(audio).arg("-shortest");
+            }
+            _ => {}
+        }
+
+        let child = ffmpeg
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("starting ffmpeg: {}", err)))?;
+        Ok(Output::Video(child))
+    }
+
+    fn write_frame(&mut self, frame: u64, pixels: &[u8]) -> io::Result<()> {
+        match self {
+            Output::None => Ok(()),
+            Output::Frames {
+                directory,
+                width,
+                height,
+            } => {
+                let path = directory.join(format!("frame-{:06}.tga", frame));
+                let mut file = BufWriter::new(File::create(path)?);
+                file.write_all(&encode_tga(*width, *height, pixels)?)?;
+                file.flush()
+            }
+            Output::Video(child) => match child.stdin.as_mut() {
+                Some(stdin) => stdin.write_all(pixels),
+                None => Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "ffmpeg has exited",
+                )),
+            },
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        if let Output::Video(mut child) = self {
+            // Closing stdin ends the video
+            drop(child.stdin.take());
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("ffmpeg failed with {}", status),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
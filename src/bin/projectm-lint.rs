@@ -0,0 +1,259 @@
+//! Check preset files for mistakes and formatting
+//!
+//! ```text
+//! projectm-lint presets/
+//! projectm-lint --fix --json presets/ > report.json
+//! ```
+//!
+//! Exits with 1 if a preset has errors, or isn't formatted and `--fix` isn't
+//! given, and with 2 on invalid arguments. Files that aren't valid UTF-8 are
+//! read as Latin-1, so fixing them keeps their bytes. `--fix` doesn't close
+//! equation gaps that skip code, those stay errors to fix by hand.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use projectm::preset::{Diagnostic, Preset, Severity};
+use serde::Serialize;
+
+const USAGE: &str = "\
+Usage: projectm-lint [OPTIONS] <PRESET or DIRECTORY>...
+
+Check presets for lines libprojectM can't read or ignores, and for formatting.
+Directories are searched for .milk files recursively.
+
+Options:
+      --fix            normalize the formatting and renumber equation lines in place
+      --json           print a JSON report
+      --deny-warnings  fail on warnings too
+  -h, --help           print this help
+";
+
+#[derive(Default)]
+struct Options {
+    paths: Vec<PathBuf>,
+    fix: bool,
+    json: bool,
+    deny_warnings: bool,
+}
+
+#[derive(Serialize)]
+struct Report {
+    files: Vec<FileReport>,
+    errors: usize,
+    warnings: usize,
+    unformatted: usize,
+    fixed: usize,
+}
+
+#[derive(Serialize)]
+struct FileReport {
+    path: PathBuf,
+    diagnostics: Vec<Diagnostic>,
+    formatted: bool,
+    fixed: bool,
+}
+
+fn main() {
+    let mut options = Options::default();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return;
+            }
+            "--fix" => options.fix = true,
+            "--json" => options.json = true,
+            "--deny-warnings" => options.deny_warnings = true,
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option '{}'", arg)),
+            _ => options.paths.push(arg.into()),
+        }
+    }
+    if options.paths.is_empty() {
+        usage_error("no presets given");
+    }
+
+    let mut files = Vec::new();
+    for path in &options.paths {
+        collect(path, &mut files);
+    }
+
+    let mut report = Report {
+        files: Vec::new(),
+        errors: 0,
+        warnings: 0,
+        unformatted: 0,
+        fixed: 0,
+    };
+    for path in files {
+        let file = check(&path, options.fix);
+        for diagnostic in &file.diagnostics {
+            match diagnostic.severity {
+                Severity::Error => report.errors += 1,
+                Severity::Warning => report.warnings += 1,
+            }
+        }
+        report.unformatted += usize::from(!file.formatted);
+        report.fixed += usize::from(file.fixed);
+        report.files.push(file);
+    }
+
+    if options.json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(err) => {
+                eprintln!("projectm-lint: {}", err);
+                process::exit(2);
+            }
+        }
+    } else {
+        print_text(&report);
+    }
+
+    let failed = report.errors > 0
+        || report.unformatted > 0
+        || (options.deny_warnings && report.warnings > 0);
+    process::exit(i32::from(failed));
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("projectm-lint: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+/// Add `path`, or the .milk files below it if it is a directory, in sorted order.
+fn collect(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(_) => {
+            // Reported when the file is read
+            files.push(path.to_path_buf());
+            return;
+        }
+    };
+    entries.sort();
+
+    for entry in entries {
+        let is_preset = entry
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("milk"));
+        if entry.is_dir() || is_preset {
+            collect(&entry, files);
+        }
+    }
+}
+
+fn check(path: &Path, fix: bool) -> FileReport {
+    let mut file = FileReport {
+        path: path.to_path_buf(),
+        diagnostics: Vec::new(),
+        formatted: true,
+        fixed: false,
+    };
+
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            file.diagnostics
+                .push(diagnostic(0, Severity::Error, "read", err));
+            return file;
+        }
+    };
+
+    let (text, latin1) = match String::from_utf8(bytes) {
+        Ok(text) => (text, false),
+        Err(err) => {
+            let bytes = err.into_bytes();
+            let valid = std::str::from_utf8(&bytes)
+                .map_err(|err| err.valid_up_to())
+                .unwrap_err();
+            let line = bytes[..valid].iter().filter(|&&b| b == b'\n').count() + 1;
+            file.diagnostics.push(diagnostic(
+                line,
+                Severity::Warning,
+                "encoding",
+                "not valid UTF-8, read as Latin-1",
+            ));
+            (bytes.iter().map(|&b| b as char).collect(), true)
+        }
+    };
+
+    let mut preset = Preset::parse(&text);
+    let mut formatted = preset.format().to_string();
+    if text.contains("\r\n") {
+        formatted = formatted.replace('\n', "\r\n");
+    }
+    file.formatted = formatted == text;
+
+    if fix && !file.formatted {
+        let bytes: Vec<u8> = if latin1 {
+            formatted.chars().map(|c| c as u8).collect()
+        } else {
+            formatted.clone().into_bytes()
+        };
+        match fs::write(path, bytes) {
+            Ok(()) => {
+                file.formatted = true;
+                file.fixed = true;
+                preset = Preset::parse(&formatted);
+            }
+            Err(err) => file
+                .diagnostics
+                .push(diagnostic(0, Severity::Error, "write", err)),
+        }
+    }
+
+    file.diagnostics.extend(preset.lint());
+    file.diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    file
+}
+
+fn diagnostic<M: ToString>(
+    line: usize,
+    severity: Severity,
+    code: &'static str,
+    message: M,
+) -> Diagnostic {
+    Diagnostic {
+        line,
+        severity,
+        code,
+        message: message.to_string(),
+    }
+}
+
+fn print_text(report: &Report) {
+    for file in &report.files {
+        let path = file.path.display();
+        for diagnostic in &file.diagnostics {
+            let location = match diagnostic.line {
+                0 => format!("{}", path),
+                line => format!("{}:{}", path, line),
+            };
+            println!(
+                "{}: {}[{}]: {}",
+                location, diagnostic.severity, diagnostic.code, diagnostic.message
+            );
+        }
+        if file.fixed {
+            println!("{}: fixed", path);
+        } else if !file.formatted {
+            println!("{}: not formatted, run with --fix", path);
+        }
+    }
+
+    println!(
+        "{} files checked: {} errors, {} warnings, {} not formatted, {} fixed",
+        report.files.len(),
+        report.errors,
+        report.warnings,
+        report.unformatted,
+        report.fixed
+    );
+}
@@ -0,0 +1,307 @@
+//! Builder for configured instances
+//!
+//! ```no_run
+//! use projectm::core::ProjectM;
+//!
+//! let projectm = ProjectM::builder()
+//!     .window_size(1280, 720)
+//!     .mesh_size(64, 48)
+//!     .preset_duration(20.0)
+//!     .preset("presets/example.milk", false)
+//!     .build()
+//!     .expect("invalid configuration");
+//! ```
+
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use crate::core::{Backend, Libprojectm, ProjectM};
+use crate::parameters::{check_path, check_string, Parameter, ParameterError};
+use crate::settings::Settings;
+
+type SwitchRequestedCallback = Box<dyn FnMut(bool)>;
+type SwitchFailedCallback = Box<dyn FnMut(String, String)>;
+
+/// Collects the configuration of a [ProjectM] instance and validates it
+/// before the instance is created. Options that are not set keep the
+/// libprojectm defaults.
+pub struct ProjectMBuilder<B: Backend = Libprojectm> {
+    window_size: Option<(usize, usize)>,
+    mesh_size: Option<(usize, usize)>,
+    fps: Option<u32>,
+    beat_sensitivity: Option<f32>,
+    hard_cut_enabled: Option<bool>,
+    hard_cut_duration: Option<f64>,
+    hard_cut_sensitivity: Option<f32>,
+    soft_cut_duration: Option<f64>,
+    preset_duration: Option<f64>,
+    aspect_correction: Option<bool>,
+    easter_egg: Option<f32>,
+    preset_locked: Option<bool>,
+    texture_search_paths: Vec<PathBuf>,
+    preset: Option<(String, bool)>,
+    preset_switch_requested: Option<SwitchRequestedCallback>,
+    preset_switch_failed: Option<SwitchFailedCallback>,
+    backend: PhantomData<B>,
+}
+
+impl ProjectMBuilder {
+    pub fn new() -> Self {
+        Self::with_backend()
+    }
+}
+
+impl Default for ProjectMBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProjectM {
+    pub fn builder() -> ProjectMBuilder {
+        ProjectMBuilder::new()
+    }
+}
+
+impl<B: Backend> ProjectMBuilder<B> {
+    /// Builder for an instance on another [Backend].
+    pub fn with_backend() -> Self {
+        ProjectMBuilder {
+            window_size: None,
+            mesh_size: None,
+            fps: None,
+            beat_sensitivity: None,
+            hard_cut_enabled: None,
+            hard_cut_duration: None,
+            hard_cut_sensitivity: None,
+            soft_cut_duration: None,
+            preset_duration: None,
+            aspect_correction: None,
+            easter_egg: None,
+            preset_locked: None,
+            texture_search_paths: Vec::new(),
+            preset: None,
+            preset_switch_requested: None,
+            preset_switch_failed: None,
+            backend: PhantomData,
+        }
+    }
+
+    /// Take all parameters from saved [Settings].
+    pub fn settings(mut self, settings: &Settings) -> Self {
+        // A window size of 0x0 means it hasn't been set yet
+        if settings.window_size != (0, 0) {
+            self.window_size = Some(settings.window_size);
+        }
+        self.mesh_size = Some(settings.mesh_size);
+        self.fps = Some(settings.fps);
+        self.beat_sensitivity = Some(settings.beat_sensitivity);
+        self.hard_cut_enabled = Some(settings.hard_cut_enabled);
+        self.hard_cut_duration = Some(settings.hard_cut_duration);
+        self.hard_cut_sensitivity = Some(settings.hard_cut_sensitivity);
+        self.soft_cut_duration = Some(settings.soft_cut_duration);
+        self.preset_duration = Some(settings.preset_duration);
+        self.aspect_correction = Some(settings.aspect_correction);
+        self.easter_egg = Some(settings.easter_egg);
+        self.preset_locked = Some(settings.preset_locked);
+        self
+    }
+
+    pub fn window_size(mut self, width: usize, height: usize) -> Self {
+        self.window_size = Some((width, height));
+        self
+    }
+
+    pub fn mesh_size(mut self, mesh_x: usize, mesh_y: usize) -> Self {
+        self.mesh_size = Some((mesh_x, mesh_y));
+        self
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = Some(fps);
+        self
+    }
+
+    pub fn beat_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.beat_sensitivity = Some(sensitivity);
+        self
+    }
+
+    pub fn hard_cut_enabled(mut self, enabled: bool) -> Self {
+        self.hard_cut_enabled = Some(enabled);
+        self
+    }
+
+    pub fn hard_cut_duration(mut self, seconds: f64) -> Self {
+        self.hard_cut_duration = Some(seconds);
+        self
+    }
+
+    pub fn hard_cut_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.hard_cut_sensitivity = Some(sensitivity);
+        self
+    }
+
+    pub fn soft_cut_duration(mut self, seconds: f64) -> Self {
+        self.soft_cut_duration = Some(seconds);
+        self
+    }
+
+    pub fn preset_duration(mut self, seconds: f64) -> Self {
+        self.preset_duration = Some(seconds);
+        self
+    }
+
+    pub fn aspect_correction(mut self, enabled: bool) -> Self {
+        self.aspect_correction = Some(enabled);
+        self
+    }
+
+    pub fn easter_egg(mut self, value: f32) -> Self {
+        self.easter_egg = Some(value);
+        self
+    }
+
+    pub fn preset_locked(mut self, locked: bool) -> Self {
+        self.preset_locked = Some(locked);
+        self
+    }
+
+    pub fn texture_search_paths<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.texture_search_paths = paths
+            .into_iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+        self
+    }
+
+    /// Preset to load once the instance is configured.
+    pub fn preset(mut self, filename: &str, smooth_transition: bool) -> Self {
+        self.preset = Some((filename.to_string(), smooth_transition));
+        self
+    }
+
+    pub fn preset_switch_requested_event_callback<F: FnMut(bool) + 'static>(
+        mut self,
+        callback: F,
+    ) -> Self {
+        self.preset_switch_requested = Some(Box::new(callback));
+        self
+    }
+
+    pub fn preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
+        mut self,
+        callback: F,
+    ) -> Self {
+        self.preset_switch_failed = Some(Box::new(callback));
+        self
+    }
+
+    /// Check every option that was set.
+    pub fn validate(&self) -> Result<(), ParameterError> {
+        if let Some((width, height)) = self.window_size {
+            Parameter::WindowWidth.check(width as f64)?;
+            Parameter::WindowHeight.check(height as f64)?;
+        }
+        if let Some((mesh_x, mesh_y)) = self.mesh_size {
+            Parameter::MeshWidth.check(mesh_x as f64)?;
+            Parameter::MeshHeight.check(mesh_y as f64)?;
+        }
+        if let Some(fps) = self.fps {
+            Parameter::Fps.check(fps as f64)?;
+        }
+        if let Some(sensitivity) = self.beat_sensitivity {
+            Parameter::BeatSensitivity.check(sensitivity as f64)?;
+        }
+        if let Some(seconds) = self.hard_cut_duration {
+            Parameter::HardCutDuration.check(seconds)?;
+        }
+        if let Some(sensitivity) = self.hard_cut_sensitivity {
+            Parameter::HardCutSensitivity.check(sensitivity as f64)?;
+        }
+        if let Some(seconds) = self.soft_cut_duration {
+            Parameter::SoftCutDuration.check(seconds)?;
+        }
+        if let Some(seconds) = self.preset_duration {
+            Parameter::PresetDuration.check(seconds)?;
+        }
+        if let Some(value) = self.easter_egg {
+            Parameter::EasterEgg.check(value as f64)?;
+        }
+        for path in &self.texture_search_paths {
+            check_path(path)?;
+        }
+        if let Some((filename, _)) = &self.preset {
+            check_string(filename)?;
+        }
+        Ok(())
+    }
+
+    /// Validate the configuration, then create and configure the instance.
+    pub fn build(self) -> Result<ProjectM<B>, ParameterError> {
+        self.validate()?;
+
+        let projectm = ProjectM::<B>::with_backend();
+
+        if let Some(mut callback) = self.preset_switch_requested {
+            projectm.set_preset_switch_requested_event_callback(move |is_hard_cut| {
+                callback(is_hard_cut)
+            });
+        }
+        if let Some(mut callback) = self.preset_switch_failed {
+            projectm.set_preset_switch_failed_event_callback(move |preset_filename, message| {
+                callback(preset_filename, message)
+            });
+        }
+
+        if let Some((width, height)) = self.window_size {
+            projectm.set_window_size(width, height)?;
+        }
+        if let Some((mesh_x, mesh_y)) = self.mesh_size {
+            projectm.set_mesh_size(mesh_x, mesh_y)?;
+        }
+        if let Some(fps) = self.fps {
+            projectm.set_fps(fps)?;
+        }
+        if let Some(sensitivity) = self.beat_sensitivity {
+            projectm.set_beat_sensitivity(sensitivity)?;
+        }
+        if let Some(enabled) = self.hard_cut_enabled {
+            projectm.set_hard_cut_enabled(enabled);
+        }
+        if let Some(seconds) = self.hard_cut_duration {
+            projectm.set_hard_cut_duration(seconds)?;
+        }
+        if let Some(sensitivity) = self.hard_cut_sensitivity {
+            projectm.set_hard_cut_sensitivity(sensitivity)?;
+        }
+        if let Some(seconds) = self.soft_cut_duration {
+            projectm.set_soft_cut_duration(seconds)?;
+        }
+        if let Some(seconds) = self.preset_duration {
+            projectm.set_preset_duration(seconds)?;
+        }
+        if let Some(enabled) = self.aspect_correction {
+            projectm.set_aspect_correction(enabled);
+        }
+        if let Some(value) = self.easter_egg {
+            projectm.set_easter_egg(value)?;
+        }
+        if let Some(locked) = self.preset_locked {
+            projectm.set_preset_locked(locked);
+        }
+        if !self.texture_search_paths.is_empty() {
+            projectm.set_texture_search_paths(&self.texture_search_paths)?;
+        }
+
+        if let Some((filename, smooth_transition)) = self.preset {
+            projectm.load_preset_file(&filename, smooth_transition)?;
+        }
+
+        Ok(projectm)
+    }
+}
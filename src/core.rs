@@ -13,11 +13,20 @@
 //!
 
 extern crate libc;
-extern crate projectm_sys as ffi;
 
-use std::cell::RefCell;
+use crate::audio::analysis::{Analyzer, AudioFrame};
+use crate::audio::delay::DelayLine;
+use crate::audio::gain::{GainStage, Meter};
+use crate::audio::spectrum::{Spectrum, Waveform, WaveformBuffer};
+use crate::audio::{int16_to_float, uint8_to_float};
+use crate::ffi;
+use crate::parameters::{check_path, check_string, Parameter, ParameterError};
 use std::ffi::CString;
-use std::rc::Rc;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
 
 pub type ProjectMHandle = *mut ffi::projectm;
 
@@ -36,39 +45,139 @@ pub const TOUCH_TYPE_BLOB5: ProjectMTouchType = 6;
 pub const TOUCH_TYPE_LINE: ProjectMTouchType = 7;
 pub const TOUCH_TYPE_DOUBLE_LINE: ProjectMTouchType = 8;
 
-struct Projectm {}
+/// The engine operations behind [ProjectM].
+///
+/// [Libprojectm] calls into libprojectm and is the default. Other implementations can
+/// record, replay or forward the calls, e.g. `ProjectM::<MyBackend>::with_backend()`.
+///
+/// The functions get the instance's [Handle](Backend::Handle) rather than
+/// `&self`; it holds whatever state the backend needs. A backend that needs
+/// configuration, e.g. the address of a remote renderer, builds the handle
+/// itself and passes it to [ProjectM::from_handle].
+pub trait Backend {
+    /// Per-instance state, e.g. the native handle.
+    type Handle;
+
+    // Core
+    fn create() -> Self::Handle;
+    fn destroy(instance: &Self::Handle);
+    fn load_preset_file(instance: &Self::Handle, filename: &str, smooth_transition: bool);
+    fn load_preset_data(instance: &Self::Handle, data: &str, smooth_transition: bool);
+    fn reset_textures(instance: &Self::Handle);
+    fn get_version_components() -> (i32, i32, i32);
+    fn get_version_string() -> String;
+    fn get_vcs_version_string() -> String;
+
+    // Callbacks
+    fn set_preset_switch_requested_event_callback<F: FnMut(bool) + 'static>(
+        instance: &Self::Handle,
+        callback: F,
+    );
+    fn set_preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
+        instance: &Self::Handle,
+        callback: F,
+    );
+
+    // Parameters
+    fn set_texture_search_paths(instance: &Self::Handle, texture_search_paths: &[PathBuf]);
+    fn get_beat_sensitivity(instance: &Self::Handle) -> f32;
+    fn set_beat_sensitivity(instance: &Self::Handle, sensitivity: f32);
+    fn get_hard_cut_duration(instance: &Self::Handle) -> f64;
+    fn set_hard_cut_duration(instance: &Self::Handle, seconds: f64);
+    fn get_hard_cut_enabled(instance: &Self::Handle) -> bool;
+    fn set_hard_cut_enabled(instance: &Self::Handle, enabled: bool);
+    fn get_hard_cut_sensitivity(instance: &Self::Handle) -> f32;
+    fn set_hard_cut_sensitivity(instance: &Self::Handle, sensitivity: f32);
+    fn get_soft_cut_duration(instance: &Self::Handle) -> f64;
+    fn set_soft_cut_duration(instance: &Self::Handle, seconds: f64);
+    fn get_preset_duration(instance: &Self::Handle) -> f64;
+    fn set_preset_duration(instance: &Self::Handle, seconds: f64);
+    fn get_mesh_size(instance: &Self::Handle) -> (usize, usize);
+    fn set_mesh_size(instance: &Self::Handle, mesh_x: usize, mesh_y: usize);
+    fn get_fps(instance: &Self::Handle) -> u32;
+    fn set_fps(instance: &Self::Handle, fps: u32);
+    fn get_aspect_correction(instance: &Self::Handle) -> bool;
+    fn set_aspect_correction(instance: &Self::Handle, enabled: bool);
+    fn get_easter_egg(instance: &Self::Handle) -> f32;
+    fn set_easter_egg(instance: &Self::Handle, sensitivity: f32);
+    fn get_preset_locked(instance: &Self::Handle) -> bool;
+    fn set_preset_locked(instance: &Self::Handle, lock: bool);
+    fn get_window_size(instance: &Self::Handle) -> (usize, usize);
+    fn set_window_size(instance: &Self::Handle, width: usize, height: usize);
+
+    // Render OpenGL
+    fn render_frame(instance: &Self::Handle);
+
+    // Touch
+    fn touch(instance: &Self::Handle, x: f32, y: f32, pressure: i32, touch_type: ProjectMTouchType);
+    fn touch_drag(instance: &Self::Handle, x: f32, y: f32, pressure: i32);
+    fn touch_destroy(instance: &Self::Handle, x: f32, y: f32);
+    fn touch_destroy_all(instance: &Self::Handle);
+
+    // Audio
+    fn pcm_get_max_samples() -> u32;
+    fn pcm_add_float(instance: &Self::Handle, samples: &[f32], channels: ProjectMChannels);
+    fn pcm_add_int16(instance: &Self::Handle, samples: &[i16], channels: ProjectMChannels);
+    fn pcm_add_uint8(instance: &Self::Handle, samples: &[u8], channels: ProjectMChannels);
+
+    // Debug
+    fn write_debug_image_on_next_frame(instance: &Self::Handle, output_file: Option<&String>);
+
+    /// The libprojectm instance behind `instance`, if there is one. A
+    /// [Playlist](crate::playlist::Playlist) connects to it directly;
+    /// without one the playlist loads its presets through the backend.
+    fn native_handle(_instance: &Self::Handle) -> Option<ProjectMHandle> {
+        None
+    }
+}
+
+/// A [Backend] whose instances may be used from any thread, which makes
+/// [ProjectM] `Send` and `Sync`.
+///
+/// [ProjectM] doesn't call the backend from two threads at once; the clones
+/// of an instance share a lock, see [ProjectM::get_instance].
+///
+/// # Safety
+///
+/// The handle must be safe to move to another thread and to use from threads
+/// other than the one that created it, one at a time.
+pub unsafe trait ThreadSafeBackend: Backend {}
+
+/// libprojectm, the backend used by default.
+pub struct Libprojectm;
+
+impl Backend for Libprojectm {
+    type Handle = ProjectMHandle;
 
-impl Projectm {
     // -----------------
     // Core
     // -----------------
 
-    fn create() -> *mut ffi::projectm {
+    fn create() -> ProjectMHandle {
         unsafe { ffi::projectm_create() }
     }
 
-    fn destroy(instance: ProjectMHandle) {
-        unsafe { ffi::projectm_destroy(instance) };
+    fn destroy(instance: &ProjectMHandle) {
+        unsafe { ffi::projectm_destroy(*instance) };
     }
 
-    fn load_preset_file(instance: ProjectMHandle, filename: &str, smooth_transition: bool) {
-        unsafe {
-            ffi::projectm_load_preset_file(
-                instance,
-                filename.as_ptr() as *mut i8,
-                smooth_transition,
-            )
+    fn load_preset_file(instance: &ProjectMHandle, filename: &str, smooth_transition: bool) {
+        // NUL bytes are rejected by ProjectM::load_preset_file
+        let Ok(filename) = CString::new(filename) else {
+            return;
         };
+        unsafe { ffi::projectm_load_preset_file(*instance, filename.as_ptr(), smooth_transition) };
     }
 
-    fn load_preset_data(instance: ProjectMHandle, data: &str, smooth_transition: bool) {
-        unsafe {
-            ffi::projectm_load_preset_data(instance, data.as_ptr() as *mut i8, smooth_transition)
+    fn load_preset_data(instance: &ProjectMHandle, data: &str, smooth_transition: bool) {
+        let Ok(data) = CString::new(data) else {
+            return;
         };
+        unsafe { ffi::projectm_load_preset_data(*instance, data.as_ptr(), smooth_transition) };
     }
 
-    fn reset_textures(instance: ProjectMHandle) {
-        unsafe { ffi::projectm_reset_textures(instance) };
+    fn reset_textures(instance: &ProjectMHandle) {
+        unsafe { ffi::projectm_reset_textures(*instance) };
     }
 
     fn get_version_components() -> (i32, i32, i32) {
@@ -119,8 +228,8 @@ impl Projectm {
     // Callbacks
     // -----------------
 
-    fn set_preset_switch_requested_event_callback<F: FnMut(bool)>(
-        instance: ProjectMHandle,
+    fn set_preset_switch_requested_event_callback<F: FnMut(bool) + 'static>(
+        instance: &ProjectMHandle,
         callback: F,
     ) {
         unsafe extern "C" fn trampoline<F: FnMut(bool)>(
@@ -131,20 +240,20 @@ impl Projectm {
         }
         unsafe {
             ffi::projectm_set_preset_switch_requested_event_callback(
-                instance,
+                *instance,
                 Some(trampoline::<F>),
                 (Box::leak(Box::new(callback)) as *mut F).cast::<std::os::raw::c_void>(),
             )
         }
     }
 
-    fn set_preset_switch_failed_event_callback<F: FnMut(String, String)>(
-        instance: ProjectMHandle,
+    fn set_preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
+        instance: &ProjectMHandle,
         callback: F,
     ) {
         unsafe extern "C" fn trampoline<F: FnMut(String, String)>(
-            preset_filename: *const i8,
-            message: *const i8,
+            preset_filename: *const std::os::raw::c_char,
+            message: *const std::os::raw::c_char,
             user_data: *mut std::os::raw::c_void,
         ) {
             let preset_filename_str = unsafe { std::ffi::CStr::from_ptr(preset_filename) };
@@ -158,7 +267,7 @@ impl Projectm {
         }
         unsafe {
             ffi::projectm_set_preset_switch_failed_event_callback(
-                instance,
+                *instance,
                 Some(trampoline::<F>),
                 (Box::leak(Box::new(callback)) as *mut F).cast::<std::os::raw::c_void>(),
             )
@@ -169,14 +278,10 @@ impl Projectm {
     // Parameters
     // -----------------
 
-    fn set_texture_search_paths(
-        instance: ProjectMHandle,
-        texture_search_paths: &[String],
-        count: usize,
-    ) {
+    fn set_texture_search_paths(instance: &ProjectMHandle, texture_search_paths: &[PathBuf]) {
         let texture_search_paths_cstr: Vec<_> = texture_search_paths
             .iter()
-            .map(|arg| CString::new(arg.as_str()).unwrap())
+            .filter_map(|path| path_to_cstring(path).ok())
             .collect();
 
         let mut texture_search_paths_pointer: Vec<_> = texture_search_paths_cstr
@@ -188,62 +293,62 @@ impl Projectm {
 
         unsafe {
             ffi::projectm_set_texture_search_paths(
-                instance,
+                *instance,
                 texture_search_paths_pointer.as_ptr() as *mut *const ::std::os::raw::c_char,
-                count,
+                texture_search_paths.len(),
             )
         };
     }
 
-    fn get_beat_sensitivity(instance: ProjectMHandle) -> f32 {
-        unsafe { ffi::projectm_get_beat_sensitivity(instance) }
+    fn get_beat_sensitivity(instance: &ProjectMHandle) -> f32 {
+        unsafe { ffi::projectm_get_beat_sensitivity(*instance) }
     }
 
-    fn set_beat_sensitivity(instance: ProjectMHandle, sensitivity: f32) {
-        unsafe { ffi::projectm_set_beat_sensitivity(instance, sensitivity) };
+    fn set_beat_sensitivity(instance: &ProjectMHandle, sensitivity: f32) {
+        unsafe { ffi::projectm_set_beat_sensitivity(*instance, sensitivity) };
     }
 
-    fn get_hard_cut_duration(instance: ProjectMHandle) -> f64 {
-        unsafe { ffi::projectm_get_hard_cut_duration(instance) }
+    fn get_hard_cut_duration(instance: &ProjectMHandle) -> f64 {
+        unsafe { ffi::projectm_get_hard_cut_duration(*instance) }
     }
 
-    fn set_hard_cut_duration(instance: ProjectMHandle, seconds: f64) {
-        unsafe { ffi::projectm_set_hard_cut_duration(instance, seconds) };
+    fn set_hard_cut_duration(instance: &ProjectMHandle, seconds: f64) {
+        unsafe { ffi::projectm_set_hard_cut_duration(*instance, seconds) };
     }
 
-    fn get_hard_cut_enabled(instance: ProjectMHandle) -> bool {
-        unsafe { ffi::projectm_get_hard_cut_enabled(instance) }
+    fn get_hard_cut_enabled(instance: &ProjectMHandle) -> bool {
+        unsafe { ffi::projectm_get_hard_cut_enabled(*instance) }
     }
 
-    fn set_hard_cut_enabled(instance: ProjectMHandle, enabled: bool) {
-        unsafe { ffi::projectm_set_hard_cut_enabled(instance, enabled) }
+    fn set_hard_cut_enabled(instance: &ProjectMHandle, enabled: bool) {
+        unsafe { ffi::projectm_set_hard_cut_enabled(*instance, enabled) }
     }
 
-    fn get_hard_cut_sensitivity(instance: ProjectMHandle) -> f32 {
-        unsafe { ffi::projectm_get_hard_cut_sensitivity(instance) }
+    fn get_hard_cut_sensitivity(instance: &ProjectMHandle) -> f32 {
+        unsafe { ffi::projectm_get_hard_cut_sensitivity(*instance) }
     }
 
-    fn set_hard_cut_sensitivity(instance: ProjectMHandle, sensitivity: f32) {
-        unsafe { ffi::projectm_set_hard_cut_sensitivity(instance, sensitivity) }
+    fn set_hard_cut_sensitivity(instance: &ProjectMHandle, sensitivity: f32) {
+        unsafe { ffi::projectm_set_hard_cut_sensitivity(*instance, sensitivity) }
     }
 
-    fn get_soft_cut_duration(instance: ProjectMHandle) -> f64 {
-        unsafe { ffi::projectm_get_soft_cut_duration(instance) }
+    fn get_soft_cut_duration(instance: &ProjectMHandle) -> f64 {
+        unsafe { ffi::projectm_get_soft_cut_duration(*instance) }
     }
 
-    fn set_soft_cut_duration(instance: ProjectMHandle, seconds: f64) {
-        unsafe { ffi::projectm_set_soft_cut_duration(instance, seconds) }
+    fn set_soft_cut_duration(instance: &ProjectMHandle, seconds: f64) {
+        unsafe { ffi::projectm_set_soft_cut_duration(*instance, seconds) }
     }
 
-    fn get_preset_duration(instance: ProjectMHandle) -> f64 {
-        unsafe { ffi::projectm_get_preset_duration(instance) }
+    fn get_preset_duration(instance: &ProjectMHandle) -> f64 {
+        unsafe { ffi::projectm_get_preset_duration(*instance) }
     }
 
-    fn set_preset_duration(instance: ProjectMHandle, seconds: f64) {
-        unsafe { ffi::projectm_set_preset_duration(instance, seconds) }
+    fn set_preset_duration(instance: &ProjectMHandle, seconds: f64) {
+        unsafe { ffi::projectm_set_preset_duration(*instance, seconds) }
     }
 
-    fn get_mesh_size(instance: ProjectMHandle) -> (usize, usize) {
+    fn get_mesh_size(instance: &ProjectMHandle) -> (usize, usize) {
         #[derive(Debug, Default, Copy, Clone)]
         #[repr(C, packed)]
         struct Mesh {
@@ -255,7 +360,7 @@ impl Projectm {
 
         unsafe {
             ffi::projectm_get_mesh_size(
-                instance,
+                *instance,
                 std::ptr::addr_of_mut!(mesh.mesh_x),
                 std::ptr::addr_of_mut!(mesh.mesh_y),
             );
@@ -264,46 +369,47 @@ impl Projectm {
         (mesh.mesh_x, mesh.mesh_y)
     }
 
-    fn set_mesh_size(instance: ProjectMHandle, mesh_x: usize, mesh_y: usize) {
+    fn set_mesh_size(instance: &ProjectMHandle, mesh_x: usize, mesh_y: usize) {
         unsafe {
-            ffi::projectm_set_mesh_size(instance, mesh_x, mesh_y);
+            ffi::projectm_set_mesh_size(*instance, mesh_x, mesh_y);
         }
     }
 
-    fn get_fps(instance: ProjectMHandle) -> u32 {
-        unsafe { ffi::projectm_get_fps(instance).try_into().unwrap() }
+    fn get_fps(instance: &ProjectMHandle) -> u32 {
+        unsafe { ffi::projectm_get_fps(*instance).try_into().unwrap() }
     }
 
-    // FIXME: shouldn't it also be a usize?
-    fn set_fps(instance: ProjectMHandle, fps: u32) {
-        unsafe { ffi::projectm_set_fps(instance, fps as i32) };
+    fn set_fps(instance: &ProjectMHandle, fps: u32) {
+        // libprojectm takes an int, clamp instead of wrapping around
+        let fps = i32::try_from(fps).unwrap_or(i32::MAX);
+        unsafe { ffi::projectm_set_fps(*instance, fps) };
     }
 
-    fn get_aspect_correction(instance: ProjectMHandle) -> bool {
-        unsafe { ffi::projectm_get_aspect_correction(instance) }
+    fn get_aspect_correction(instance: &ProjectMHandle) -> bool {
+        unsafe { ffi::projectm_get_aspect_correction(*instance) }
     }
 
-    fn set_aspect_correction(instance: ProjectMHandle, enabled: bool) {
-        unsafe { ffi::projectm_set_aspect_correction(instance, enabled) };
+    fn set_aspect_correction(instance: &ProjectMHandle, enabled: bool) {
+        unsafe { ffi::projectm_set_aspect_correction(*instance, enabled) };
     }
 
-    fn get_easter_egg(instance: ProjectMHandle) -> f32 {
-        unsafe { ffi::projectm_get_easter_egg(instance) }
+    fn get_easter_egg(instance: &ProjectMHandle) -> f32 {
+        unsafe { ffi::projectm_get_easter_egg(*instance) }
     }
 
-    fn set_easter_egg(instance: ProjectMHandle, sensitivity: f32) {
-        unsafe { ffi::projectm_set_easter_egg(instance, sensitivity) };
+    fn set_easter_egg(instance: &ProjectMHandle, sensitivity: f32) {
+        unsafe { ffi::projectm_set_easter_egg(*instance, sensitivity) };
     }
 
-    fn get_preset_locked(instance: ProjectMHandle) -> bool {
-        unsafe { ffi::projectm_get_preset_locked(instance) }
+    fn get_preset_locked(instance: &ProjectMHandle) -> bool {
+        unsafe { ffi::projectm_get_preset_locked(*instance) }
     }
 
-    fn set_preset_locked(instance: ProjectMHandle, lock: bool) {
-        unsafe { ffi::projectm_set_preset_locked(instance, lock) };
+    fn set_preset_locked(instance: &ProjectMHandle, lock: bool) {
+        unsafe { ffi::projectm_set_preset_locked(*instance, lock) };
     }
 
-    fn get_window_size(instance: ProjectMHandle) -> (usize, usize) {
+    fn get_window_size(instance: &ProjectMHandle) -> (usize, usize) {
         #[derive(Debug, Default, Copy, Clone)]
         #[repr(C, packed)]
         struct Mesh {
@@ -315,7 +421,7 @@ impl Projectm {
 
         unsafe {
             ffi::projectm_get_window_size(
-                instance,
+                *instance,
                 std::ptr::addr_of_mut!(window.width),
                 std::ptr::addr_of_mut!(window.height),
             );
@@ -324,16 +430,16 @@ impl Projectm {
         (window.width, window.height)
     }
 
-    fn set_window_size(instance: ProjectMHandle, width: usize, height: usize) {
-        unsafe { ffi::projectm_set_window_size(instance, width, height) };
+    fn set_window_size(instance: &ProjectMHandle, width: usize, height: usize) {
+        unsafe { ffi::projectm_set_window_size(*instance, width, height) };
     }
 
     // -----------------
     // Render OpenGL
     // -----------------
 
-    fn render_frame(instance: ProjectMHandle) {
-        unsafe { ffi::projectm_opengl_render_frame(instance) };
+    fn render_frame(instance: &ProjectMHandle) {
+        unsafe { ffi::projectm_opengl_render_frame(*instance) };
     }
 
     // -----------------
@@ -341,25 +447,25 @@ impl Projectm {
     // -----------------
 
     fn touch(
-        instance: ProjectMHandle,
+        instance: &ProjectMHandle,
         x: f32,
         y: f32,
         pressure: i32,
         touch_type: ProjectMTouchType,
     ) {
-        unsafe { ffi::projectm_touch(instance, x, y, pressure, touch_type.try_into().unwrap()) };
+        unsafe { ffi::projectm_touch(*instance, x, y, pressure, touch_type.try_into().unwrap()) };
     }
 
-    fn touch_drag(instance: ProjectMHandle, x: f32, y: f32, pressure: i32) {
-        unsafe { ffi::projectm_touch_drag(instance, x, y, pressure) };
+    fn touch_drag(instance: &ProjectMHandle, x: f32, y: f32, pressure: i32) {
+        unsafe { ffi::projectm_touch_drag(*instance, x, y, pressure) };
     }
 
-    fn touch_destroy(instance: ProjectMHandle, x: f32, y: f32) {
-        unsafe { ffi::projectm_touch_destroy(instance, x, y) };
+    fn touch_destroy(instance: &ProjectMHandle, x: f32, y: f32) {
+        unsafe { ffi::projectm_touch_destroy(*instance, x, y) };
     }
 
-    fn touch_destroy_all(instance: ProjectMHandle) {
-        unsafe { ffi::projectm_touch_destroy_all(instance) };
+    fn touch_destroy_all(instance: &ProjectMHandle) {
+        unsafe { ffi::projectm_touch_destroy_all(*instance) };
     }
 
     // -----------------
@@ -370,7 +476,7 @@ impl Projectm {
         unsafe { ffi::projectm_pcm_get_max_samples() }
     }
 
-    fn pcm_add_float(instance: ProjectMHandle, samples: &[f32], channels: ProjectMChannels) {
+    fn pcm_add_float(instance: &ProjectMHandle, samples: &[f32], channels: ProjectMChannels) {
         assert!(
             samples.len() <= Self::pcm_get_max_samples() as usize,
             "Number of samples is greater than max samples"
@@ -378,7 +484,7 @@ impl Projectm {
         let samples_per_channel = samples.len() / channels as usize;
         unsafe {
             ffi::projectm_pcm_add_float(
-                instance,
+                *instance,
                 samples.as_ptr(),
                 samples_per_channel as u32,
                 channels.try_into().unwrap(),
@@ -386,7 +492,7 @@ impl Projectm {
         }
     }
 
-    fn pcm_add_int16(instance: ProjectMHandle, samples: &[i16], channels: ProjectMChannels) {
+    fn pcm_add_int16(instance: &ProjectMHandle, samples: &[i16], channels: ProjectMChannels) {
         assert!(
             samples.len() <= Self::pcm_get_max_samples() as usize,
             "Number of samples is greater than max samples"
@@ -394,7 +500,7 @@ impl Projectm {
         let samples_per_channel = samples.len() / channels as usize;
         unsafe {
             ffi::projectm_pcm_add_int16(
-                instance,
+                *instance,
                 samples.as_ptr(),
                 samples_per_channel as u32,
                 channels.try_into().unwrap(),
@@ -402,7 +508,7 @@ impl Projectm {
         }
     }
 
-    fn pcm_add_uint8(instance: ProjectMHandle, samples: &[u8], channels: ProjectMChannels) {
+    fn pcm_add_uint8(instance: &ProjectMHandle, samples: &[u8], channels: ProjectMChannels) {
         assert!(
             samples.len() <= Self::pcm_get_max_samples() as usize,
             "Number of samples is greater than max samples"
@@ -410,7 +516,7 @@ impl Projectm {
         let samples_per_channel = samples.len() / channels as usize;
         unsafe {
             ffi::projectm_pcm_add_uint8(
-                instance,
+                *instance,
                 samples.as_ptr(),
                 samples_per_channel as u32,
                 channels.try_into().unwrap(),
@@ -422,7 +528,7 @@ impl Projectm {
     // Debug
     // -----------------
 
-    fn write_debug_image_on_next_frame(instance: ProjectMHandle, output_file: Option<&String>) {
+    fn write_debug_image_on_next_frame(instance: &ProjectMHandle, output_file: Option<&String>) {
         // Transform the Rust String into a C String - this is needed due to the
         // fact that Rust Strings are not null terminated.
         let path = output_file.map(|p| {
@@ -437,376 +543,698 @@ impl Projectm {
             .map(|s| s.as_ptr())
             .unwrap_or(std::ptr::null());
 
-        unsafe { ffi::projectm_write_debug_image_on_next_frame(instance, ptr) };
+        unsafe { ffi::projectm_write_debug_image_on_next_frame(*instance, ptr) };
+    }
+
+    fn native_handle(instance: &ProjectMHandle) -> Option<ProjectMHandle> {
+        Some(*instance)
     }
 }
 
-pub struct ProjectM {
-    instance: Rc<RefCell<ProjectMHandle>>,
+unsafe impl ThreadSafeBackend for Libprojectm {}
+
+type BeatEventCallback = Box<dyn FnMut(AudioFrame) + Send>;
+
+pub struct ProjectM<B: Backend = Libprojectm> {
+    instance: Arc<Instance<B>>,
+    texture_search_paths: Arc<Mutex<Vec<PathBuf>>>,
+    gain_stage: Arc<Mutex<Option<GainStage>>>,
+    delay: Arc<Mutex<Option<DelayLine>>>,
+    waveform: Arc<Mutex<WaveformBuffer>>,
+    analyzer: Arc<Mutex<Option<Analyzer>>>,
+    beat_event_callback: Arc<Mutex<Option<BeatEventCallback>>>,
 }
 
-impl ProjectM {
-    pub fn create() -> Self {
-        let instance = Rc::new(RefCell::new(Projectm::create()));
+// The backend's handle, shared by the clones of an instance. Calls hold its
+// lock; the thread holding it may take it again, as libprojectm runs the
+// callbacks inside its calls and they may call back into the instance.
+struct Instance<B: Backend> {
+    handle: B::Handle,
+    owner: Mutex<Owner>,
+    released: Condvar,
+}
 
-        ProjectM { instance }
-    }
+#[derive(Default)]
+struct Owner {
+    thread: Option<ThreadId>,
+    depth: usize,
+}
 
-    pub fn destroy(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            let _ = &Projectm::destroy(*instance);
-        } else {
-            panic!("Failed to borrow instance");
+// The handle is only reached through an InstanceGuard, on one thread at a time
+unsafe impl<B: ThreadSafeBackend> Send for Instance<B> {}
+unsafe impl<B: ThreadSafeBackend> Sync for Instance<B> {}
+
+impl<B: Backend> Instance<B> {
+    fn enter(&self) -> InstanceGuard<'_, B> {
+        let current = thread::current().id();
+        let mut owner = lock(&self.owner);
+        while owner.thread.is_some_and(|thread| thread != current) {
+            owner = self
+                .released
+                .wait(owner)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
         }
-    }
+        owner.thread = Some(current);
+        owner.depth += 1;
 
-    pub fn load_preset_file(&self, filename: &str, smooth_transition: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::load_preset_file(*instance, filename, smooth_transition);
-        } else {
-            panic!("Failed to borrow instance");
+        InstanceGuard {
+            instance: self,
+            not_send: PhantomData,
         }
     }
+}
 
-    pub fn load_preset_data(&self, data: &str, smooth_transition: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::load_preset_data(*instance, data, smooth_transition);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+/// Access to the backend's handle of an instance, see [ProjectM::get_instance].
+pub struct InstanceGuard<'a, B: Backend> {
+    instance: &'a Instance<B>,
+    // The lock belongs to the thread that took it
+    not_send: PhantomData<*const ()>,
+}
+
+impl<B: Backend> std::ops::Deref for InstanceGuard<'_, B> {
+    type Target = B::Handle;
+
+    fn deref(&self) -> &B::Handle {
+        &self.instance.handle
     }
+}
 
-    pub fn reset_textures(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::reset_textures(*instance);
-        } else {
-            panic!("Failed to borrow instance");
+impl<B: Backend> Drop for InstanceGuard<'_, B> {
+    fn drop(&mut self) {
+        let mut owner = lock(&self.instance.owner);
+        owner.depth -= 1;
+        if owner.depth == 0 {
+            owner.thread = None;
+            self.instance.released.notify_one();
         }
     }
+}
+
+// A panic while the state was locked leaves it consistent
+pub(crate) fn lock<T>(state: &Mutex<T>) -> MutexGuard<'_, T> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl ProjectM {
+    pub fn create() -> Self {
+        Self::with_backend()
+    }
 
     pub fn get_version_components() -> (i32, i32, i32) {
-        Projectm::get_version_components()
+        Libprojectm::get_version_components()
     }
 
     pub fn get_version_string() -> String {
-        Projectm::get_version_string()
+        Libprojectm::get_version_string()
     }
 
     pub fn get_vcs_version_string() -> String {
-        Projectm::get_vcs_version_string()
+        Libprojectm::get_vcs_version_string()
+    }
+
+    pub fn pcm_get_max_samples() -> u32 {
+        Libprojectm::pcm_get_max_samples()
+    }
+}
+
+impl<B: Backend> ProjectM<B> {
+    /// Create an instance on another [Backend].
+    pub fn with_backend() -> Self {
+        Self::from_handle(B::create())
+    }
+
+    /// Wrap a handle the backend created, e.g. one configured with the
+    /// address of a remote renderer.
+    pub fn from_handle(handle: B::Handle) -> Self {
+        ProjectM {
+            instance: Arc::new(Instance {
+                handle,
+                owner: Mutex::new(Owner::default()),
+                released: Condvar::new(),
+            }),
+            texture_search_paths: Arc::new(Mutex::new(Vec::new())),
+            gain_stage: Arc::new(Mutex::new(None)),
+            delay: Arc::new(Mutex::new(None)),
+            waveform: Arc::new(Mutex::new(WaveformBuffer::default())),
+            analyzer: Arc::new(Mutex::new(None)),
+            beat_event_callback: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn destroy(&self) {
+        B::destroy(&self.instance.enter());
+    }
+
+    /// Fails if the filename contains a NUL byte.
+    pub fn load_preset_file(
+        &self,
+        filename: &str,
+        smooth_transition: bool,
+    ) -> Result<(), ParameterError> {
+        check_string(filename)?;
+
+        B::load_preset_file(&self.instance.enter(), filename, smooth_transition);
+        Ok(())
+    }
+
+    /// Fails if the preset contains a NUL byte.
+    pub fn load_preset_data(
+        &self,
+        data: &str,
+        smooth_transition: bool,
+    ) -> Result<(), ParameterError> {
+        check_string(data)?;
+
+        B::load_preset_data(&self.instance.enter(), data, smooth_transition);
+        Ok(())
+    }
+
+    pub fn reset_textures(&self) {
+        B::reset_textures(&self.instance.enter());
     }
 
     pub fn set_preset_switch_requested_event_callback<F: FnMut(bool) + 'static>(
         &self,
         callback: F,
     ) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_switch_requested_event_callback(*instance, callback);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::set_preset_switch_requested_event_callback(&self.instance.enter(), callback);
     }
 
     pub fn set_preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
         &self,
         callback: F,
     ) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_switch_failed_event_callback(*instance, callback);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::set_preset_switch_failed_event_callback(&self.instance.enter(), callback);
     }
 
-    pub fn set_texture_search_paths(&self, texture_search_paths: &[String], count: usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_texture_search_paths(*instance, texture_search_paths, count);
-        } else {
-            panic!("Failed to borrow instance");
+    /// Set the directories searched for preset textures, replacing the current ones.
+    /// Returns the entries that are not existing directories; they are kept, so
+    /// they are used once they show up (e.g. removable drives).
+    pub fn set_texture_search_paths<I, P>(
+        &self,
+        texture_search_paths: I,
+    ) -> Result<Vec<PathBuf>, ParameterError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let paths: Vec<PathBuf> = texture_search_paths
+            .into_iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+        for path in &paths {
+            check_path(path)?;
         }
+
+        *lock(&self.texture_search_paths) = paths;
+        Ok(self.update_texture_search_paths())
     }
 
-    pub fn get_beat_sensitivity(&self) -> f32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_beat_sensitivity(*instance)
-        } else {
-            panic!("Failed to borrow instance");
+    /// Append a texture search directory unless it is already set. Returns the
+    /// missing directories like [set_texture_search_paths](Self::set_texture_search_paths).
+    pub fn add_texture_search_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Vec<PathBuf>, ParameterError> {
+        let path = path.as_ref();
+        check_path(path)?;
+
+        {
+            let mut paths = lock(&self.texture_search_paths);
+            if !paths.iter().any(|p| p == path) {
+                paths.push(path.to_path_buf());
+            }
         }
+        Ok(self.update_texture_search_paths())
     }
 
-    pub fn set_beat_sensitivity(&self, sensitivity: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_beat_sensitivity(*instance, sensitivity);
-        } else {
-            panic!("Failed to borrow instance");
+    /// Remove a texture search directory. Returns false if it wasn't set.
+    pub fn remove_texture_search_path<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        let removed = {
+            let mut paths = lock(&self.texture_search_paths);
+            let len = paths.len();
+            paths.retain(|p| p != path);
+            paths.len() != len
+        };
+
+        if removed {
+            self.update_texture_search_paths();
         }
+        removed
+    }
+
+    /// The texture search directories, in search order.
+    pub fn get_texture_search_paths(&self) -> Vec<PathBuf> {
+        lock(&self.texture_search_paths).clone()
+    }
+
+    // Pass the paths to the engine and return the ones that aren't directories
+    fn update_texture_search_paths(&self) -> Vec<PathBuf> {
+        // The instance is always entered before the paths are locked
+        let instance = self.instance.enter();
+        let paths = lock(&self.texture_search_paths);
+        B::set_texture_search_paths(&instance, &paths);
+
+        paths
+            .iter()
+            .filter(|path| !path.is_dir())
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_beat_sensitivity(&self) -> f32 {
+        B::get_beat_sensitivity(&self.instance.enter())
+    }
+
+    /// Valid values: [Parameter::BeatSensitivity].
+    pub fn set_beat_sensitivity<S>(&self, sensitivity: S) -> Result<(), ParameterError>
+    where
+        S: Into<f32>,
+    {
+        let sensitivity = sensitivity.into();
+        Parameter::BeatSensitivity.check(sensitivity as f64)?;
+
+        B::set_beat_sensitivity(&self.instance.enter(), sensitivity);
+        Ok(())
     }
 
     pub fn get_hard_cut_duration(&self) -> f64 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_hard_cut_duration(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_hard_cut_duration(&self.instance.enter())
     }
 
-    pub fn set_hard_cut_duration(&self, seconds: f64) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_hard_cut_duration(*instance, seconds);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::HardCutDuration].
+    pub fn set_hard_cut_duration<S>(&self, seconds: S) -> Result<(), ParameterError>
+    where
+        S: Into<f64>,
+    {
+        let seconds = seconds.into();
+        Parameter::HardCutDuration.check(seconds)?;
+
+        B::set_hard_cut_duration(&self.instance.enter(), seconds);
+        Ok(())
     }
 
     pub fn get_hard_cut_enabled(&self) -> bool {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_hard_cut_enabled(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_hard_cut_enabled(&self.instance.enter())
     }
 
     pub fn set_hard_cut_enabled(&self, enabled: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_hard_cut_enabled(*instance, enabled);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::set_hard_cut_enabled(&self.instance.enter(), enabled);
     }
 
     pub fn get_hard_cut_sensitivity(&self) -> f32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_hard_cut_sensitivity(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_hard_cut_sensitivity(&self.instance.enter())
     }
 
-    pub fn set_hard_cut_sensitivity(&self, sensitivity: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_hard_cut_sensitivity(*instance, sensitivity);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::HardCutSensitivity].
+    pub fn set_hard_cut_sensitivity<S>(&self, sensitivity: S) -> Result<(), ParameterError>
+    where
+        S: Into<f32>,
+    {
+        let sensitivity = sensitivity.into();
+        Parameter::HardCutSensitivity.check(sensitivity as f64)?;
+
+        B::set_hard_cut_sensitivity(&self.instance.enter(), sensitivity);
+        Ok(())
     }
 
     pub fn get_soft_cut_duration(&self) -> f64 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_soft_cut_duration(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_soft_cut_duration(&self.instance.enter())
     }
 
-    pub fn set_soft_cut_duration(&self, seconds: f64) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_soft_cut_duration(*instance, seconds);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::SoftCutDuration].
+    pub fn set_soft_cut_duration<S>(&self, seconds: S) -> Result<(), ParameterError>
+    where
+        S: Into<f64>,
+    {
+        let seconds = seconds.into();
+        Parameter::SoftCutDuration.check(seconds)?;
+
+        B::set_soft_cut_duration(&self.instance.enter(), seconds);
+        Ok(())
     }
 
     pub fn get_preset_duration(&self) -> f64 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_preset_duration(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_preset_duration(&self.instance.enter())
     }
 
-    pub fn set_preset_duration(&self, seconds: f64) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_duration(*instance, seconds);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::PresetDuration].
+    pub fn set_preset_duration<S>(&self, seconds: S) -> Result<(), ParameterError>
+    where
+        S: Into<f64>,
+    {
+        let seconds = seconds.into();
+        Parameter::PresetDuration.check(seconds)?;
+
+        B::set_preset_duration(&self.instance.enter(), seconds);
+        Ok(())
     }
 
     pub fn get_mesh_size(&self) -> (usize, usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_mesh_size(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_mesh_size(&self.instance.enter())
     }
 
-    pub fn set_mesh_size(&self, mesh_x: usize, mesh_y: usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_mesh_size(*instance, mesh_x, mesh_y);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::MeshWidth] and [Parameter::MeshHeight].
+    pub fn set_mesh_size(&self, mesh_x: usize, mesh_y: usize) -> Result<(), ParameterError> {
+        Parameter::MeshWidth.check(mesh_x as f64)?;
+        Parameter::MeshHeight.check(mesh_y as f64)?;
+
+        B::set_mesh_size(&self.instance.enter(), mesh_x, mesh_y);
+        Ok(())
     }
 
     pub fn get_fps(&self) -> u32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_fps(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_fps(&self.instance.enter())
     }
 
-    pub fn set_fps(&self, fps: u32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_fps(*instance, fps);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::Fps].
+    pub fn set_fps(&self, fps: u32) -> Result<(), ParameterError> {
+        Parameter::Fps.check(fps as f64)?;
+
+        B::set_fps(&self.instance.enter(), fps);
+        Ok(())
     }
 
     pub fn get_aspect_correction(&self) -> bool {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_aspect_correction(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_aspect_correction(&self.instance.enter())
     }
 
     pub fn set_aspect_correction(&self, enabled: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_aspect_correction(*instance, enabled);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::set_aspect_correction(&self.instance.enter(), enabled);
     }
 
     pub fn get_easter_egg(&self) -> f32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_easter_egg(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_easter_egg(&self.instance.enter())
     }
 
-    pub fn set_easter_egg(&self, sensitivity: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_easter_egg(*instance, sensitivity);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::EasterEgg].
+    pub fn set_easter_egg(&self, value: f32) -> Result<(), ParameterError> {
+        Parameter::EasterEgg.check(value as f64)?;
+
+        B::set_easter_egg(&self.instance.enter(), value);
+        Ok(())
     }
 
     pub fn get_preset_locked(&self) -> bool {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_preset_locked(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_preset_locked(&self.instance.enter())
     }
 
     pub fn set_preset_locked(&self, lock: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_locked(*instance, lock);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::set_preset_locked(&self.instance.enter(), lock);
     }
 
     pub fn get_window_size(&self) -> (usize, usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_window_size(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::get_window_size(&self.instance.enter())
     }
 
-    pub fn set_window_size(&self, width: usize, height: usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_window_size(*instance, width, height);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Valid values: [Parameter::WindowWidth] and [Parameter::WindowHeight].
+    pub fn set_window_size(&self, width: usize, height: usize) -> Result<(), ParameterError> {
+        Parameter::WindowWidth.check(width as f64)?;
+        Parameter::WindowHeight.check(height as f64)?;
+
+        B::set_window_size(&self.instance.enter(), width, height);
+        Ok(())
     }
 
     pub fn render_frame(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::render_frame(*instance);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        self.release_delayed();
+
+        B::render_frame(&self.instance.enter());
     }
 
     pub fn touch(&self, x: f32, y: f32, pressure: i32, touch_type: ProjectMTouchType) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch(*instance, x, y, pressure, touch_type);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::touch(&self.instance.enter(), x, y, pressure, touch_type);
     }
 
     pub fn touch_drag(&self, x: f32, y: f32, pressure: i32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch_drag(*instance, x, y, pressure);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::touch_drag(&self.instance.enter(), x, y, pressure);
     }
 
     pub fn touch_destroy(&self, x: f32, y: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch_destroy(*instance, x, y);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        B::touch_destroy(&self.instance.enter(), x, y);
     }
 
     pub fn touch_destroy_all(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch_destroy_all(*instance);
-        } else {
-            panic!("Failed to borrow instance");
-        }
-    }
-
-    pub fn pcm_get_max_samples() -> u32 {
-        Projectm::pcm_get_max_samples()
+        B::touch_destroy_all(&self.instance.enter());
     }
 
     pub fn pcm_add_float(&self, samples: &[f32], channels: ProjectMChannels) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::pcm_add_float(*instance, samples, channels);
-        } else {
-            panic!("Failed to borrow instance");
+        let processed;
+        let samples = match lock(&self.gain_stage).as_mut() {
+            Some(gain_stage) => {
+                let mut samples = samples.to_vec();
+                gain_stage.process(&mut samples, channels);
+                processed = samples;
+                &processed[..]
+            }
+            None => samples,
+        };
+
+        let delayed = match lock(&self.delay).as_mut() {
+            Some(delay) => {
+                delay.push(samples, channels);
+                true
+            }
+            None => false,
+        };
+
+        if !delayed {
+            self.add_float(samples, channels);
         }
     }
 
     pub fn pcm_add_int16(&self, samples: &[i16], channels: ProjectMChannels) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::pcm_add_int16(*instance, samples, channels);
-        } else {
-            panic!("Failed to borrow instance");
+        // The gain stage and the delay line work on floats
+        if lock(&self.gain_stage).is_some() || lock(&self.delay).is_some() {
+            let samples: Vec<f32> = samples.iter().copied().map(int16_to_float).collect();
+            return self.pcm_add_float(&samples, channels);
         }
+
+        B::pcm_add_int16(&self.instance.enter(), samples, channels);
+        let samples: Vec<f32> = samples.iter().copied().map(int16_to_float).collect();
+        self.record(&samples, channels);
     }
 
     pub fn pcm_add_uint8(&self, samples: &[u8], channels: ProjectMChannels) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::pcm_add_uint8(*instance, samples, channels);
-        } else {
-            panic!("Failed to borrow instance");
+        // The gain stage and the delay line work on floats
+        if lock(&self.gain_stage).is_some() || lock(&self.delay).is_some() {
+            let samples: Vec<f32> = samples.iter().copied().map(uint8_to_float).collect();
+            return self.pcm_add_float(&samples, channels);
         }
+
+        B::pcm_add_uint8(&self.instance.enter(), samples, channels);
+        let samples: Vec<f32> = samples.iter().copied().map(uint8_to_float).collect();
+        self.record(&samples, channels);
     }
 
-    pub fn write_debug_image_on_next_frame(&self, output_file: Option<&String>) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::write_debug_image_on_next_frame(*instance, output_file);
-        } else {
-            panic!("Failed to borrow instance");
+    /// Process all audio passed to `pcm_add_*` with a [GainStage], or stop
+    /// processing with `None`. Integer samples are converted to float first.
+    pub fn set_gain_stage(&self, gain_stage: Option<GainStage>) {
+        *lock(&self.gain_stage) = gain_stage;
+    }
+
+    /// Levels of the audio the gain stage processed last.
+    pub fn get_gain_meter(&self) -> Option<Meter> {
+        lock(&self.gain_stage).as_ref().map(GainStage::meter)
+    }
+
+    /// Hold all audio passed to `pcm_add_*` back by `milliseconds`, to match
+    /// the output latency of the audio device. Time is counted in rendered
+    /// frames: every [render_frame](ProjectM::render_frame) call moves on by
+    /// one frame at [get_fps](ProjectM::get_fps) and passes on the audio that
+    /// is due. 0 passes audio on right away, including any audio still held.
+    pub fn set_audio_latency(&self, milliseconds: u32) {
+        let latency = Duration::from_millis(milliseconds.into());
+        let mut delay = lock(&self.delay);
+
+        if milliseconds > 0 {
+            match delay.as_mut() {
+                Some(delay) => delay.set_latency(latency),
+                None => *delay = Some(DelayLine::new(latency)),
+            }
+            return;
+        }
+
+        let held = delay.take();
+        drop(delay);
+        if let Some(mut held) = held {
+            held.flush(|samples, channels| self.add_float(samples, channels));
         }
     }
 
-    pub fn get_instance(&self) -> Rc<RefCell<ProjectMHandle>> {
-        self.instance.clone()
+    pub fn get_audio_latency(&self) -> u32 {
+        lock(&self.delay)
+            .as_ref()
+            .map_or(0, |delay| delay.latency().as_millis() as u32)
     }
-}
 
-unsafe impl Send for ProjectM {}
-unsafe impl Sync for ProjectM {}
+    /// Drop the audio held back by the latency, e.g. when playback seeks.
+    pub fn clear_delayed_audio(&self) {
+        if let Some(delay) = lock(&self.delay).as_mut() {
+            delay.clear();
+        }
+    }
+
+    // Move the delay line on by one frame
+    fn release_delayed(&self) {
+        if lock(&self.delay).is_none() {
+            return;
+        }
+        let frame = Duration::from_secs(1) / self.get_fps().max(1);
+
+        let mut due = Vec::new();
+        if let Some(delay) = lock(&self.delay).as_mut() {
+            delay.advance(frame, |samples, channels| {
+                due.push((samples.to_vec(), channels))
+            });
+        }
+
+        // The delay line is released, so the beat callback may change the latency
+        for (samples, channels) in due {
+            self.add_float(&samples, channels);
+        }
+    }
+
+    fn add_float(&self, samples: &[f32], channels: ProjectMChannels) {
+        B::pcm_add_float(&self.instance.enter(), samples, channels);
+        self.record(samples, channels);
+    }
+
+    /// Copy of the most recently added audio.
+    pub fn get_waveform(&self) -> Waveform {
+        lock(&self.waveform).waveform()
+    }
+
+    /// Number of audio frames kept for [get_waveform](ProjectM::get_waveform)
+    /// and [get_spectrum](ProjectM::get_spectrum).
+    pub fn get_waveform_length(&self) -> usize {
+        lock(&self.waveform).length()
+    }
+
+    pub fn set_waveform_length(&self, frames: usize) {
+        lock(&self.waveform).set_length(frames);
+    }
+
+    /// Magnitude spectrum of the most recently added audio, mixed to mono.
+    pub fn get_spectrum(&self, spectrum: &Spectrum) -> Vec<f32> {
+        spectrum.compute(&self.get_waveform().mono())
+    }
+
+    /// Run an [Analyzer] on all audio passed to `pcm_add_*` from now on. It
+    /// reports a frame for each video frame at the instance's fps.
+    pub fn enable_audio_analysis(&self, sample_rate: u32) {
+        let analyzer = Analyzer::with_frame_rate(sample_rate, self.get_fps());
+        *lock(&self.analyzer) = Some(analyzer);
+    }
+
+    pub fn disable_audio_analysis(&self) {
+        *lock(&self.analyzer) = None;
+    }
+
+    pub fn is_audio_analysis_enabled(&self) -> bool {
+        lock(&self.analyzer).is_some()
+    }
+
+    /// The latest analysis result, if analysis is enabled and a frame of audio was added.
+    pub fn get_audio_frame(&self) -> Option<AudioFrame> {
+        lock(&self.analyzer).as_ref()?.latest().copied()
+    }
+
+    /// Called with every analyzed frame that starts a beat. The callback may
+    /// use the instance; beats in audio it adds itself are not reported.
+    pub fn set_beat_event_callback<F: FnMut(AudioFrame) + Send + 'static>(&self, callback: F) {
+        *lock(&self.beat_event_callback) = Some(Box::new(callback));
+    }
+
+    fn record(&self, samples: &[f32], channels: ProjectMChannels) {
+        lock(&self.waveform).push(samples, channels);
+
+        let mut beats = Vec::new();
+
+        // The instance is always entered before the analyzer is locked
+        let parameters = self
+            .is_audio_analysis_enabled()
+            .then(|| (self.get_beat_sensitivity(), self.get_fps()));
+
+        if let Some(analyzer) = lock(&self.analyzer).as_mut() {
+            // Detect the beats libprojectm reacts to, once per video frame
+            if let Some((sensitivity, fps)) = parameters {
+                analyzer.set_beat_sensitivity(sensitivity);
+                if analyzer.frame_rate() != fps {
+                    analyzer.set_frame_rate(fps);
+                }
+            }
+            analyzer.process(samples, channels, |frame| {
+                if frame.beat {
+                    beats.push(*frame);
+                }
+            });
+        }
+
+        if beats.is_empty() {
+            return;
+        }
+
+        // Other threads wait until the callback is done. It runs outside of
+        // its slot, so it may add audio or set a new callback.
+        let _instance = self.instance.enter();
+        let callback = lock(&self.beat_event_callback).take();
+        if let Some(mut callback) = callback {
+            for frame in beats {
+                callback(frame);
+            }
+
+            let mut slot = lock(&self.beat_event_callback);
+            if slot.is_none() {
+                *slot = Some(callback);
+            }
+        }
+    }
+
+    pub fn write_debug_image_on_next_frame(&self, output_file: Option<&String>) {
+        B::write_debug_image_on_next_frame(&self.instance.enter(), output_file);
+    }
+
+    /// The backend's handle. Until the guard is dropped, other threads wait
+    /// to use this instance or its clones; the current thread may still use them.
+    pub fn get_instance(&self) -> InstanceGuard<'_, B> {
+        self.instance.enter()
+    }
+
+    // Whether both are clones of the same instance
+    pub(crate) fn is_same_instance(&self, other: &ProjectM<B>) -> bool {
+        Arc::ptr_eq(&self.instance, &other.instance)
+    }
+}
 
 // allow cloning the handle
-impl Clone for ProjectM {
+impl<B: Backend> Clone for ProjectM<B> {
     fn clone(&self) -> Self {
         ProjectM {
             instance: self.instance.clone(),
+            texture_search_paths: self.texture_search_paths.clone(),
+            gain_stage: self.gain_stage.clone(),
+            delay: self.delay.clone(),
+            waveform: self.waveform.clone(),
+            analyzer: self.analyzer.clone(),
+            beat_event_callback: self.beat_event_callback.clone(),
         }
     }
 }
+
+// Paths are passed to libprojectm as raw bytes, so non-UTF-8 names work on unix
+#[cfg(unix)]
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString, ParameterError> {
+    use std::os::unix::ffi::OsStrExt;
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| ParameterError::InvalidString(path.to_string_lossy().into_owned()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString, ParameterError> {
+    CString::new(path.to_string_lossy().as_bytes())
+        .map_err(|_| ParameterError::InvalidString(path.to_string_lossy().into_owned()))
+}
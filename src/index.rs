@@ -0,0 +1,554 @@
+//! Preset library index
+//!
+//! Walks preset directories, extracts the metadata of every preset and
+//! keeps it in an index that can be saved to disk. Reloading a saved index
+//! and rescanning only re-reads presets whose size or modification time
+//! changed, which keeps startup fast for large libraries.
+
+use std::collections::{btree_map, BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::preset::{Preset, PresetMetadata};
+
+const INDEX_HEADER: &str = "projectm-preset-index 1";
+
+/// File extensions recognized as presets, same as libprojectm's playlist.
+const PRESET_EXTENSIONS: &[&str] = &["milk", "prjm"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub path: PathBuf,
+    pub author: Option<String>,
+    pub title: String,
+    /// File size in bytes.
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch.
+    pub modified: u64,
+    /// 64-bit FNV-1a hash of the file contents.
+    pub hash: u64,
+    pub metadata: PresetMetadata,
+    /// User assigned tags, kept across rescans. The helpers on [PresetIndex]
+    /// store them trimmed and in lowercase.
+    pub tags: Vec<String>,
+}
+
+/// Counts of what changed during a [PresetIndex::scan].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    /// Presets that couldn't be read. Their previous entries, if any, are kept.
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PresetIndex {
+    entries: BTreeMap<PathBuf, IndexEntry>,
+}
+
+impl PresetIndex {
+    pub fn new() -> Self {
+        PresetIndex::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All entries, ordered by path.
+    pub fn entries(&self) -> btree_map::Values<'_, PathBuf, IndexEntry> {
+        self.entries.values()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&IndexEntry> {
+        self.entries.get(path)
+    }
+
+    /// Replace the tags of a preset. Returns false if the preset is not indexed.
+    pub fn set_tags<I, S>(&mut self, path: &Path, tags: I) -> bool
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        match self.entries.get_mut(path) {
+            Some(entry) => {
+                entry.tags.clear();
+                for tag in tags {
+                    add_tag(&mut entry.tags, tag.into());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add a tag to a preset. Returns false if the preset is not indexed.
+    pub fn add_tag(&mut self, path: &Path, tag: &str) -> bool {
+        match self.entries.get_mut(path) {
+            Some(entry) => {
+                add_tag(&mut entry.tags, tag.to_owned());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a tag from a preset. Returns false if the preset did not have the tag.
+    pub fn remove_tag(&mut self, path: &Path, tag: &str) -> bool {
+        match self.entries.get_mut(path) {
+            Some(entry) => {
+                let len = entry.tags.len();
+                let tag = normalize_tag(tag);
+                entry.tags.retain(|t| *t != tag);
+                entry.tags.len() != len
+            }
+            None => false,
+        }
+    }
+
+    /// Scan a directory of presets and bring the index up to date.
+    ///
+    /// Presets whose size and modification time match the indexed entry are
+    /// not read again. Entries below `path` that no longer exist are removed.
+    /// A preset that can't be read is counted in [ScanStats::failed] and
+    /// doesn't stop the scan; the index only changes if the directory itself
+    /// can't be listed, in which case the error is returned.
+    pub fn scan(&mut self, path: &Path, recursive: bool) -> io::Result<ScanStats> {
+        let mut files = Vec::new();
+        collect_presets(path, recursive, &mut files)?;
+        files.sort();
+
+        let mut stats = ScanStats::default();
+        let mut existing: HashMap<PathBuf, IndexEntry> = HashMap::new();
+        let mut kept = BTreeMap::new();
+
+        for (entry_path, entry) in std::mem::take(&mut self.entries) {
+            if entry_path.starts_with(path) {
+                existing.insert(entry_path, entry);
+            } else {
+                kept.insert(entry_path, entry);
+            }
+        }
+
+        for file in files {
+            let previous = existing.remove(&file);
+            let entry = match scan_file(file, previous) {
+                Scanned::Unchanged(entry) => {
+                    stats.unchanged += 1;
+                    entry
+                }
+                Scanned::Updated(entry) => {
+                    stats.updated += 1;
+                    entry
+                }
+                Scanned::Added(entry) => {
+                    stats.added += 1;
+                    entry
+                }
+                // Deleted since the directory was listed
+                Scanned::Missing(Some(_)) => {
+                    stats.removed += 1;
+                    continue;
+                }
+                Scanned::Missing(None) => continue,
+                Scanned::Failed(previous) => {
+                    stats.failed += 1;
+                    match previous {
+                        Some(entry) => entry,
+                        None => continue,
+                    }
+                }
+            };
+            kept.insert(entry.path.clone(), entry);
+        }
+
+        stats.removed += existing.len();
+        self.entries = kept;
+
+        Ok(stats)
+    }
+
+    /// Groups of entries that have identical contents. Each group is ordered by path.
+    pub fn duplicates(&self) -> Vec<Vec<&IndexEntry>> {
+        let mut by_hash: HashMap<(u64, u64), Vec<&IndexEntry>> = HashMap::new();
+
+        for entry in self.entries.values() {
+            by_hash
+                .entry((entry.hash, entry.size))
+                .or_default()
+                .push(entry);
+        }
+
+        // Entries are visited in path order, so the groups already are
+        let mut groups: Vec<_> = by_hash
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .collect();
+        groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+
+        groups
+    }
+
+    /// Load an index previously written with [PresetIndex::save].
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(fs::File::open(path)?);
+        let mut lines = reader.lines();
+
+        match lines.next() {
+            Some(Ok(header)) if header == INDEX_HEADER => {}
+            Some(Err(err)) => return Err(err),
+            _ => return Err(invalid_data("not a preset index file")),
+        }
+
+        let mut entries = BTreeMap::new();
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let entry = parse_entry(&line).ok_or_else(|| {
+                invalid_data(&format!("malformed index entry on line {}", number + 2))
+            })?;
+            entries.insert(entry.path.clone(), entry);
+        }
+
+        Ok(PresetIndex { entries })
+    }
+
+    /// Write the index to a file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+
+        writeln!(writer, "{}", INDEX_HEADER)?;
+        for entry in self.entries.values() {
+            writeln!(writer, "{}", format_entry(entry))?;
+        }
+
+        writer.flush()
+    }
+}
+
+/// Split a preset file name into author and title.
+///
+/// Preset packs usually name files `Author - Title.milk`, collaborations
+/// use `Author + Author - Title.milk`. Names without the separator are
+/// used as the title.
+pub fn parse_file_name(path: &Path) -> (Option<String>, String) {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    match stem.split_once(" - ") {
+        Some((author, title)) if !author.trim().is_empty() && !title.trim().is_empty() => {
+            (Some(author.trim().to_owned()), title.trim().to_owned())
+        }
+        _ => (None, stem.trim().to_owned()),
+    }
+}
+
+/// 64-bit FNV-1a, stable across platforms and Rust releases so it can be persisted.
+pub fn content_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+pub(crate) fn is_preset_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            PRESET_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+        .unwrap_or(false)
+}
+
+pub(crate) fn collect_presets(
+    path: &Path,
+    recursive: bool,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let entry_path = entry.path();
+
+        if file_type.is_dir() {
+            if recursive {
+                collect_presets(&entry_path, recursive, files)?;
+            }
+        } else if is_preset_file(&entry_path) {
+            files.push(entry_path);
+        }
+    }
+
+    Ok(())
+}
+
+enum Scanned {
+    Unchanged(IndexEntry),
+    Updated(IndexEntry),
+    Added(IndexEntry),
+    Missing(Option<IndexEntry>),
+    Failed(Option<IndexEntry>),
+}
+
+/// Bring the entry of one preset up to date, reading it only if it changed.
+fn scan_file(file: PathBuf, previous: Option<IndexEntry>) -> Scanned {
+    let file_metadata = match fs::metadata(&file) {
+        Ok(file_metadata) => file_metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Scanned::Missing(previous),
+        Err(_) => return Scanned::Failed(previous),
+    };
+    let size = file_metadata.len();
+    let modified = file_metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    match previous {
+        Some(entry) if entry.size == size && entry.modified == modified => {
+            Scanned::Unchanged(entry)
+        }
+        previous => {
+            let data = match fs::read(&file) {
+                Ok(data) => data,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    return Scanned::Missing(previous)
+                }
+                Err(_) => return Scanned::Failed(previous),
+            };
+            let mut entry = index_entry(file, size, modified, &data);
+
+            match previous {
+                Some(previous) => {
+                    entry.tags = previous.tags;
+                    Scanned::Updated(entry)
+                }
+                None => Scanned::Added(entry),
+            }
+        }
+    }
+}
+
+fn index_entry(path: PathBuf, size: u64, modified: u64, data: &[u8]) -> IndexEntry {
+    let preset = Preset::parse(&String::from_utf8_lossy(data));
+    let (author, title) = parse_file_name(&path);
+
+    IndexEntry {
+        author,
+        title,
+        size,
+        modified,
+        hash: content_hash(data),
+        metadata: preset.metadata(),
+        tags: Vec::new(),
+        path,
+    }
+}
+
+fn add_tag(tags: &mut Vec<String>, tag: String) {
+    let tag = normalize_tag(&tag);
+    if !tag.is_empty() && !tags.contains(&tag) {
+        tags.push(tag);
+    }
+}
+
+/// Tags are compared case-insensitively, this is the form they are stored and searched in.
+pub(crate) fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// -----------------
+// Index file format
+// -----------------
+//
+// One entry per line, fields separated by tabs. Tabs, newlines and
+// backslashes inside text fields are escaped. Path bytes that aren't UTF-8
+// are written as `\xHH` so they survive a round trip.
+
+fn format_entry(entry: &IndexEntry) -> String {
+    let metadata = &entry.metadata;
+    let fields = [
+        escape_path(&entry.path),
+        entry.size.to_string(),
+        entry.modified.to_string(),
+        format!("{:016x}", entry.hash),
+        entry.author.as_deref().map(escape).unwrap_or_default(),
+        escape(&entry.title),
+        metadata.per_frame_init_equations.to_string(),
+        metadata.per_frame_equations.to_string(),
+        metadata.per_pixel_equations.to_string(),
+        metadata.custom_waves.to_string(),
+        metadata.custom_shapes.to_string(),
+        u8::from(metadata.has_warp_shader).to_string(),
+        u8::from(metadata.has_composite_shader).to_string(),
+        metadata
+            .textures
+            .iter()
+            .map(|texture| escape(texture))
+            .collect::<Vec<_>>()
+            .join(","),
+        entry
+            .tags
+            .iter()
+            .map(|tag| escape(tag))
+            .collect::<Vec<_>>()
+            .join(","),
+    ];
+
+    fields.join("\t")
+}
+
+fn parse_entry(line: &str) -> Option<IndexEntry> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 15 {
+        return None;
+    }
+
+    let author = unescape(fields[4]);
+
+    Some(IndexEntry {
+        path: unescape_path(fields[0]),
+        size: fields[1].parse().ok()?,
+        modified: fields[2].parse().ok()?,
+        hash: u64::from_str_radix(fields[3], 16).ok()?,
+        author: if author.is_empty() {
+            None
+        } else {
+            Some(author)
+        },
+        title: unescape(fields[5]),
+        metadata: PresetMetadata {
+            per_frame_init_equations: fields[6].parse().ok()?,
+            per_frame_equations: fields[7].parse().ok()?,
+            per_pixel_equations: fields[8].parse().ok()?,
+            custom_waves: fields[9].parse().ok()?,
+            custom_shapes: fields[10].parse().ok()?,
+            has_warp_shader: fields[11] == "1",
+            has_composite_shader: fields[12] == "1",
+            textures: parse_list(fields[13]),
+        },
+        tags: parse_list(fields[14]),
+    })
+}
+
+fn parse_list(field: &str) -> Vec<String> {
+    if field.is_empty() {
+        Vec::new()
+    } else {
+        field.split(',').map(unescape).collect()
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ',' => escaped.push_str("\\c"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    String::from_utf8_lossy(&unescape_bytes(s)).into_owned()
+}
+
+fn unescape_bytes(s: &str) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            unescaped.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b't') => unescaped.push(b'\t'),
+            Some(b'n') => unescaped.push(b'\n'),
+            Some(b'r') => unescaped.push(b'\r'),
+            Some(b'c') => unescaped.push(b','),
+            Some(b'x') => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                match std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(value) => unescaped.push(value),
+                    None => unescaped.extend_from_slice(&hex),
+                }
+            }
+            Some(other) => unescaped.push(other),
+            None => unescaped.push(b'\\'),
+        }
+    }
+    unescaped
+}
+
+// Paths are kept as raw bytes on unix, as they are passed to libprojectm
+#[cfg(unix)]
+fn escape_path(path: &Path) -> String {
+    use std::fmt::Write;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut bytes = path.as_os_str().as_bytes();
+    let mut escaped = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                escaped.push_str(&escape(valid));
+                return escaped;
+            }
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                let invalid = err.error_len().unwrap_or(rest.len());
+                escaped.push_str(&escape(&String::from_utf8_lossy(valid)));
+                for byte in &rest[..invalid] {
+                    let _ = write!(escaped, "\\x{:02x}", byte);
+                }
+                bytes = &rest[invalid..];
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn escape_path(path: &Path) -> String {
+    escape(&path.to_string_lossy())
+}
+
+#[cfg(unix)]
+fn unescape_path(s: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    std::ffi::OsString::from_vec(unescape_bytes(s)).into()
+}
+
+#[cfg(not(unix))]
+fn unescape_path(s: &str) -> PathBuf {
+    PathBuf::from(unescape(s))
+}
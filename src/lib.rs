@@ -1,4 +1,26 @@
+#[cfg(not(any(feature = "native", feature = "mock")))]
+compile_error!("Enable either the `native` or the `mock` feature");
+
+// Calls go to libprojectm, or to the in-process fake with the `mock` feature
+#[cfg(not(feature = "mock"))]
+extern crate projectm_sys as ffi;
+#[cfg(feature = "mock")]
+use mock as ffi;
+
+pub mod audio;
+pub mod builder;
 pub mod core;
+pub mod index;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod parameters;
+pub mod preset;
+pub mod record;
+pub mod search;
+pub mod settings;
+pub mod source;
+pub mod textures;
 
 // #[cfg(playlist)]
 pub mod playlist;
+pub mod playlist_file;
@@ -0,0 +1,758 @@
+//! In-process fake of libprojectm
+//!
+//! With the `mock` feature (and without `native`), all calls that would go
+//! to libprojectm are served by this module instead. It needs neither the
+//! native library nor an OpenGL context. Every call is recorded, parameters
+//! are stored so that getters return what was set, and tests can fire the
+//! preset switch callbacks by hand.
+//!
+//! ```
+//! use projectm::core::ProjectM;
+//! use projectm::mock::{self, Call};
+//!
+//! let projectm = ProjectM::create();
+//! projectm.set_beat_sensitivity(1.5).unwrap();
+//! assert_eq!(projectm.get_beat_sensitivity(), 1.5);
+//! assert!(mock::calls(&projectm).contains(&Call::SetBeatSensitivity(1.5)));
+//! ```
+//!
+//! The functions mirror the signatures of the `projectm-sys` bindings, only
+//! the `pub` items outside of the `ffi`-style functions are meant to be used
+//! by applications.
+
+#![allow(non_camel_case_types)]
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::core::ProjectM;
+
+/// A call made to the fake library.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    Create,
+    LoadPresetFile {
+        filename: String,
+        smooth_transition: bool,
+    },
+    LoadPresetData {
+        data: String,
+        smooth_transition: bool,
+    },
+    ResetTextures,
+    SetPresetSwitchRequestedEventCallback,
+    SetPresetSwitchFailedEventCallback,
+    SetTextureSearchPaths(Vec<String>),
+    SetBeatSensitivity(f32),
+    SetHardCutDuration(f64),
+    SetHardCutEnabled(bool),
+    SetHardCutSensitivity(f32),
+    SetSoftCutDuration(f64),
+    SetPresetDuration(f64),
+    SetMeshSize(usize, usize),
+    SetFps(i32),
+    SetAspectCorrection(bool),
+    SetEasterEgg(f32),
+    SetPresetLocked(bool),
+    SetWindowSize(usize, usize),
+    RenderFrame,
+    Touch {
+        x: f32,
+        y: f32,
+        pressure: i32,
+        touch_type: u32,
+    },
+    TouchDrag {
+        x: f32,
+        y: f32,
+        pressure: i32,
+    },
+    TouchDestroy {
+        x: f32,
+        y: f32,
+    },
+    TouchDestroyAll,
+    /// PCM data was added; `samples` is the number of samples per channel.
+    PcmAdd {
+        samples: u32,
+        channels: u32,
+    },
+    WriteDebugImageOnNextFrame(Option<String>),
+}
+
+/// Parameters of a fake instance. The defaults are those of libprojectm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+    pub texture_search_paths: Vec<String>,
+    pub beat_sensitivity: f32,
+    pub hard_cut_duration: f64,
+    pub hard_cut_enabled: bool,
+    pub hard_cut_sensitivity: f32,
+    pub soft_cut_duration: f64,
+    pub preset_duration: f64,
+    pub mesh_size: (usize, usize),
+    pub fps: i32,
+    pub aspect_correction: bool,
+    pub easter_egg: f32,
+    pub preset_locked: bool,
+    pub window_size: (usize, usize),
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            texture_search_paths: Vec::new(),
+            beat_sensitivity: 1.0,
+            hard_cut_duration: 20.0,
+            hard_cut_enabled: false,
+            hard_cut_sensitivity: 2.0,
+            soft_cut_duration: 3.0,
+            preset_duration: 30.0,
+            mesh_size: (48, 32),
+            fps: 60,
+            aspect_correction: true,
+            easter_egg: 0.0,
+            preset_locked: false,
+            window_size: (0, 0),
+        }
+    }
+}
+
+/// Version reported by the fake library.
+pub const VERSION: (i32, i32, i32) = (4, 0, 0);
+
+/// Maximum number of samples per channel accepted by the `pcm_add_*` functions.
+pub const PCM_MAX_SAMPLES: u32 = 2048;
+
+type SwitchRequestedCallback = unsafe extern "C" fn(bool, *mut c_void);
+type SwitchFailedCallback = unsafe extern "C" fn(*const c_char, *const c_char, *mut c_void);
+
+#[derive(Default)]
+struct State {
+    calls: Vec<Call>,
+    parameters: Parameters,
+    frames_rendered: u64,
+    switch_requested: Option<(SwitchRequestedCallback, *mut c_void)>,
+    switch_failed: Option<(SwitchFailedCallback, *mut c_void)>,
+}
+
+/// A fake projectM instance; handles point to one of these.
+pub struct projectm {
+    state: RefCell<State>,
+}
+
+pub type projectm_handle = *mut projectm;
+pub type projectm_channels = c_uint;
+pub type projectm_touch_type = c_uint;
+
+/// Addresses of the instances that were created and not destroyed yet.
+static LIVE: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+fn is_live(instance: projectm_handle) -> bool {
+    let live = LIVE.lock().unwrap_or_else(|err| err.into_inner());
+    live.contains(&(instance as usize))
+}
+
+// -----------------
+// Test API
+// -----------------
+
+/// Panics if the instance was destroyed.
+fn with_state<R>(projectm: &ProjectM, f: impl FnOnce(&mut State) -> R) -> R {
+    let handle = *projectm.get_instance();
+    assert!(is_live(handle), "the mock instance was destroyed");
+    let projectm = unsafe { &*handle };
+    let mut state = projectm.state.borrow_mut();
+    f(&mut state)
+}
+
+/// All calls made on the instance so far, oldest first.
+pub fn calls(projectm: &ProjectM) -> Vec<Call> {
+    with_state(projectm, |state| state.calls.clone())
+}
+
+/// Forget the recorded calls.
+pub fn clear_calls(projectm: &ProjectM) {
+    with_state(projectm, |state| state.calls.clear())
+}
+
+/// The current parameters of the instance.
+pub fn parameters(projectm: &ProjectM) -> Parameters {
+    with_state(projectm, |state| state.parameters.clone())
+}
+
+/// Number of `render_frame` calls.
+pub fn frames_rendered(projectm: &ProjectM) -> u64 {
+    with_state(projectm, |state| state.frames_rendered)
+}
+
+/// Invoke the preset switch requested callback, as libprojectm does when the
+/// preset duration is over or a hard cut is detected.
+pub fn fire_preset_switch_requested(projectm: &ProjectM, is_hard_cut: bool) {
+    // The callback may call back into the instance, so don't hold the state borrow.
+    let callback = with_state(projectm, |state| state.switch_requested);
+    if let Some((callback, user_data)) = callback {
+        unsafe { callback(is_hard_cut, user_data) };
+    }
+}
+
+/// Invoke the preset switch failed callback.
+pub fn fire_preset_switch_failed(projectm: &ProjectM, preset_filename: &str, message: &str) {
+    let callback = with_state(projectm, |state| state.switch_failed);
+    if let Some((callback, user_data)) = callback {
+        let preset_filename = CString::new(preset_filename).unwrap();
+        let message = CString::new(message).unwrap();
+        unsafe { callback(preset_filename.as_ptr(), message.as_ptr(), user_data) };
+    }
+}
+
+// -----------------
+// Core
+// -----------------
+
+unsafe fn state<'a>(instance: projectm_handle) -> std::cell::RefMut<'a, State> {
+    (*instance).state.borrow_mut()
+}
+
+unsafe fn record(instance: projectm_handle, call: Call) {
+    state(instance).calls.push(call);
+}
+
+unsafe fn string(ptr: *const c_char) -> String {
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+pub unsafe fn projectm_create() -> projectm_handle {
+    let instance = Box::into_raw(Box::new(projectm {
+        state: RefCell::new(State::default()),
+    }));
+    record(instance, Call::Create);
+    let mut live = LIVE.lock().unwrap_or_else(|err| err.into_inner());
+    live.push(instance as usize);
+    instance
+}
+
+pub unsafe fn projectm_destroy(instance: projectm_handle) {
+    let mut live = LIVE.lock().unwrap_or_else(|err| err.into_inner());
+    live.retain(|&address| address != instance as usize);
+    drop(Box::from_raw(instance));
+}
+
+pub unsafe fn projectm_load_preset_file(
+    instance: projectm_handle,
+    filename: *const c_char,
+    smooth_transition: bool,
+) {
+    let filename = string(filename);
+    record(
+        instance,
+        Call::LoadPresetFile {
+            filename,
+            smooth_transition,
+        },
+    );
+}
+
+pub unsafe fn projectm_load_preset_data(
+    instance: projectm_handle,
+    data: *const c_char,
+    smooth_transition: bool,
+) {
+    let data = string(data);
+    record(
+        instance,
+        Call::LoadPresetData {
+            data,
+            smooth_transition,
+        },
+    );
+}
+
+pub unsafe fn projectm_reset_textures(instance: projectm_handle) {
+    record(instance, Call::ResetTextures);
+}
+
+pub unsafe fn projectm_get_version_components(
+    major: *mut c_int,
+    minor: *mut c_int,
+    patch: *mut c_int,
+) {
+    *major = VERSION.0;
+    *minor = VERSION.1;
+    *patch = VERSION.2;
+}
+
+pub unsafe fn projectm_get_version_string() -> *mut c_char {
+    let version = format!("{}.{}.{}", VERSION.0, VERSION.1, VERSION.2);
+    CString::new(version).unwrap().into_raw()
+}
+
+pub unsafe fn projectm_get_vcs_version_string() -> *mut c_char {
+    CString::new("mock").unwrap().into_raw()
+}
+
+pub unsafe fn projectm_free_string(str: *const c_char) {
+    if !str.is_null() {
+        drop(CString::from_raw(str as *mut c_char));
+    }
+}
+
+// -----------------
+// Callbacks
+// -----------------
+
+pub unsafe fn projectm_set_preset_switch_requested_event_callback(
+    instance: projectm_handle,
+    callback: Option<SwitchRequestedCallback>,
+    user_data: *mut c_void,
+) {
+    let mut state = state(instance);
+    state.switch_requested = callback.map(|callback| (callback, user_data));
+    state
+        .calls
+        .push(Call::SetPresetSwitchRequestedEventCallback);
+}
+
+pub unsafe fn projectm_set_preset_switch_failed_event_callback(
+    instance: projectm_handle,
+    callback: Option<SwitchFailedCallback>,
+    user_data: *mut c_void,
+) {
+    let mut state = state(instance);
+    state.switch_failed = callback.map(|callback| (callback, user_data));
+    state.calls.push(Call::SetPresetSwitchFailedEventCallback);
+}
+
+// -----------------
+// Parameters
+// -----------------
+
+pub unsafe fn projectm_set_texture_search_paths(
+    instance: projectm_handle,
+    texture_search_paths: *mut *const c_char,
+    count: usize,
+) {
+    let paths: Vec<String> = (0..count)
+        .map(|i| string(*texture_search_paths.add(i)))
+        .collect();
+    let mut state = state(instance);
+    state.parameters.texture_search_paths = paths.clone();
+    state.calls.push(Call::SetTextureSearchPaths(paths));
+}
+
+macro_rules! parameter {
+    ($get:ident, $set:ident, $field:ident, $type:ty, $call:ident) => {
+        pub unsafe fn $get(instance: projectm_handle) -> $type {
+            state(instance).parameters.$field
+        }
+
+        pub unsafe fn $set(instance: projectm_handle, value: $type) {
+            let mut state = state(instance);
+            state.parameters.$field = value;
+            state.calls.push(Call::$call(value));
+        }
+    };
+}
+
+parameter!(
+    projectm_get_beat_sensitivity,
+    projectm_set_beat_sensitivity,
+    beat_sensitivity,
+    f32,
+    SetBeatSensitivity
+);
+parameter!(
+    projectm_get_hard_cut_duration,
+    projectm_set_hard_cut_duration,
+    hard_cut_duration,
+    f64,
+    SetHardCutDuration
+);
+parameter!(
+    projectm_get_hard_cut_enabled,
+    projectm_set_hard_cut_enabled,
+    hard_cut_enabled,
+    bool,
+    SetHardCutEnabled
+);
+parameter!(
+    projectm_get_hard_cut_sensitivity,
+    projectm_set_hard_cut_sensitivity,
+    hard_cut_sensitivity,
+    f32,
+    SetHardCutSensitivity
+);
+parameter!(
+    projectm_get_soft_cut_duration,
+    projectm_set_soft_cut_duration,
+    soft_cut_duration,
+    f64,
+    SetSoftCutDuration
+);
+parameter!(
+    projectm_get_preset_duration,
+    projectm_set_preset_duration,
+    preset_duration,
+    f64,
+    SetPresetDuration
+);
+parameter!(projectm_get_fps, projectm_set_fps, fps, i32, SetFps);
+parameter!(
+    projectm_get_aspect_correction,
+    projectm_set_aspect_correction,
+    aspect_correction,
+    bool,
+    SetAspectCorrection
+);
+parameter!(
+    projectm_get_easter_egg,
+    projectm_set_easter_egg,
+    easter_egg,
+    f32,
+    SetEasterEgg
+);
+parameter!(
+    projectm_get_preset_locked,
+    projectm_set_preset_locked,
+    preset_locked,
+    bool,
+    SetPresetLocked
+);
+
+pub unsafe fn projectm_get_mesh_size(
+    instance: projectm_handle,
+    width: *mut usize,
+    height: *mut usize,
+) {
+    let (mesh_x, mesh_y) = state(instance).parameters.mesh_size;
+    *width = mesh_x;
+    *height = mesh_y;
+}
+
+pub unsafe fn projectm_set_mesh_size(instance: projectm_handle, width: usize, height: usize) {
+    let mut state = state(instance);
+    state.parameters.mesh_size = (width, height);
+    state.calls.push(Call::SetMeshSize(width, height));
+}
+
+pub unsafe fn projectm_get_window_size(
+    instance: projectm_handle,
+    width: *mut usize,
+    height: *mut usize,
+) {
+    let (window_width, window_height) = state(instance).parameters.window_size;
+    *width = window_width;
+    *height = window_height;
+}
+
+pub unsafe fn projectm_set_window_size(instance: projectm_handle, width: usize, height: usize) {
+    let mut state = state(instance);
+    state.parameters.window_size = (width, height);
+    state.calls.push(Call::SetWindowSize(width, height));
+}
+
+// -----------------
+// Render OpenGL
+// -----------------
+
+pub unsafe fn projectm_opengl_render_frame(instance: projectm_handle) {
+    let mut state = state(instance);
+    state.frames_rendered += 1;
+    state.calls.push(Call::RenderFrame);
+}
+
+// -----------------
+// Touch
+// -----------------
+
+pub unsafe fn projectm_touch(
+    instance: projectm_handle,
+    x: f32,
+    y: f32,
+    pressure: c_int,
+    touch_type: projectm_touch_type,
+) {
+    record(
+        instance,
+        Call::Touch {
+            x,
+            y,
+            pressure,
+            touch_type,
+        },
+    );
+}
+
+pub unsafe fn projectm_touch_drag(instance: projectm_handle, x: f32, y: f32, pressure: c_int) {
+    record(instance, Call::TouchDrag { x, y, pressure });
+}
+
+pub unsafe fn projectm_touch_destroy(instance: projectm_handle, x: f32, y: f32) {
+    record(instance, Call::TouchDestroy { x, y });
+}
+
+pub unsafe fn projectm_touch_destroy_all(instance: projectm_handle) {
+    record(instance, Call::TouchDestroyAll);
+}
+
+// -----------------
+// Audio
+// -----------------
+
+pub unsafe fn projectm_pcm_get_max_samples() -> c_uint {
+    PCM_MAX_SAMPLES
+}
+
+pub unsafe fn projectm_pcm_add_float(
+    instance: projectm_handle,
+    _samples: *const f32,
+    count: c_uint,
+    channels: projectm_channels,
+) {
+    record(
+        instance,
+        Call::PcmAdd {
+            samples: count,
+            channels,
+        },
+    );
+}
+
+pub unsafe fn projectm_pcm_add_int16(
+    instance: projectm_handle,
+    _samples: *const i16,
+    count: c_uint,
+    channels: projectm_channels,
+) {
+    record(
+        instance,
+        Call::PcmAdd {
+            samples: count,
+            channels,
+        },
+    );
+}
+
+pub unsafe fn projectm_pcm_add_uint8(
+    instance: projectm_handle,
+    _samples: *const u8,
+    count: c_uint,
+    channels: projectm_channels,
+) {
+    record(
+        instance,
+        Call::PcmAdd {
+            samples: count,
+            channels,
+        },
+    );
+}
+
+// -----------------
+// Debug
+// -----------------
+
+pub unsafe fn projectm_write_debug_image_on_next_frame(
+    instance: projectm_handle,
+    output_file: *const c_char,
+) {
+    let output_file = if output_file.is_null() {
+        None
+    } else {
+        Some(string(output_file))
+    };
+    record(instance, Call::WriteDebugImageOnNextFrame(output_file));
+}
+
+// -----------------
+// Playlist
+// -----------------
+
+type PresetSwitchedCallback = unsafe extern "C" fn(bool, c_uint, *mut c_void);
+
+/// A fake playlist; it keeps the preset paths and the position like libprojectm's
+/// playlist, and reports every switch to the preset switched callback.
+pub struct projectm_playlist {
+    items: Vec<String>,
+    position: u32,
+    shuffle: bool,
+    switched: Option<(PresetSwitchedCallback, *mut c_void)>,
+}
+
+pub type projectm_playlist_handle = *mut projectm_playlist;
+
+unsafe fn playlist<'a>(instance: projectm_playlist_handle) -> &'a mut projectm_playlist {
+    &mut *instance
+}
+
+pub unsafe fn projectm_playlist_create(_instance: projectm_handle) -> projectm_playlist_handle {
+    Box::into_raw(Box::new(projectm_playlist {
+        items: Vec::new(),
+        position: 0,
+        shuffle: false,
+        switched: None,
+    }))
+}
+
+pub unsafe fn projectm_playlist_set_preset_switched_event_callback(
+    instance: projectm_playlist_handle,
+    callback: Option<PresetSwitchedCallback>,
+    user_data: *mut c_void,
+) {
+    playlist(instance).switched = callback.map(|callback| (callback, user_data));
+}
+
+// Report the switch to the current position, as libprojectm does after loading the preset
+unsafe fn switched(instance: projectm_playlist_handle, hard_cut: bool) -> u32 {
+    let position = playlist(instance).position;
+    if let Some((callback, user_data)) = playlist(instance).switched {
+        if !playlist(instance).items.is_empty() {
+            callback(hard_cut, position, user_data);
+        }
+    }
+    position
+}
+
+pub unsafe fn projectm_playlist_destroy(instance: projectm_playlist_handle) {
+    drop(Box::from_raw(instance));
+}
+
+pub unsafe fn projectm_playlist_size(instance: projectm_playlist_handle) -> u32 {
+    playlist(instance).items.len() as u32
+}
+
+pub unsafe fn projectm_playlist_clear(instance: projectm_playlist_handle) {
+    playlist(instance).items.clear();
+    playlist(instance).position = 0;
+}
+
+pub unsafe fn projectm_playlist_item(
+    instance: projectm_playlist_handle,
+    index: u32,
+) -> *mut c_char {
+    match playlist(instance).items.get(index as usize) {
+        Some(item) => CString::new(item.as_str()).unwrap().into_raw(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+pub unsafe fn projectm_playlist_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+unsafe fn add_item(
+    instance: projectm_playlist_handle,
+    item: String,
+    allow_duplicates: bool,
+) -> bool {
+    let items = &mut playlist(instance).items;
+    if !allow_duplicates && items.contains(&item) {
+        return false;
+    }
+    items.push(item);
+    true
+}
+
+fn scan_presets(path: &Path, recurse_subdirs: bool, presets: &mut Vec<String>) {
+    let mut entries: Vec<_> = match std::fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(Result::ok).map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            if recurse_subdirs {
+                scan_presets(&entry, recurse_subdirs, presets);
+            }
+        } else if crate::index::is_preset_file(&entry) {
+            presets.push(entry.to_string_lossy().into_owned());
+        }
+    }
+}
+
+pub unsafe fn projectm_playlist_add_path(
+    instance: projectm_playlist_handle,
+    path: *const c_char,
+    recurse_subdirs: bool,
+    allow_duplicates: bool,
+) -> u32 {
+    let mut presets = Vec::new();
+    scan_presets(Path::new(&string(path)), recurse_subdirs, &mut presets);
+
+    presets
+        .into_iter()
+        .filter(|preset| add_item(instance, preset.clone(), allow_duplicates))
+        .count() as u32
+}
+
+pub unsafe fn projectm_playlist_add_preset(
+    instance: projectm_playlist_handle,
+    filename: *const c_char,
+    allow_duplicates: bool,
+) -> bool {
+    add_item(instance, string(filename), allow_duplicates)
+}
+
+pub unsafe fn projectm_playlist_add_presets(
+    instance: projectm_playlist_handle,
+    filenames: *mut *const c_char,
+    count: u32,
+    allow_duplicates: bool,
+) -> u32 {
+    (0..count as usize)
+        .filter(|i| add_item(instance, string(*filenames.add(*i)), allow_duplicates))
+        .count() as u32
+}
+
+pub unsafe fn projectm_playlist_get_shuffle(instance: projectm_playlist_handle) -> bool {
+    playlist(instance).shuffle
+}
+
+pub unsafe fn projectm_playlist_set_shuffle(instance: projectm_playlist_handle, shuffle: bool) {
+    playlist(instance).shuffle = shuffle;
+}
+
+pub unsafe fn projectm_playlist_get_position(instance: projectm_playlist_handle) -> u32 {
+    playlist(instance).position
+}
+
+pub unsafe fn projectm_playlist_set_position(
+    instance: projectm_playlist_handle,
+    new_position: u32,
+    hard_cut: bool,
+) -> u32 {
+    let len = playlist(instance).items.len() as u32;
+    if len > 0 {
+        playlist(instance).position = new_position.min(len - 1);
+    }
+    switched(instance, hard_cut)
+}
+
+pub unsafe fn projectm_playlist_play_next(
+    instance: projectm_playlist_handle,
+    hard_cut: bool,
+) -> u32 {
+    let len = playlist(instance).items.len() as u32;
+    if len > 0 {
+        playlist(instance).position = (playlist(instance).position + 1) % len;
+    }
+    switched(instance, hard_cut)
+}
+
+pub unsafe fn projectm_playlist_play_previous(
+    instance: projectm_playlist_handle,
+    hard_cut: bool,
+) -> u32 {
+    let len = playlist(instance).items.len() as u32;
+    if len > 0 {
+        playlist(instance).position = (playlist(instance).position + len - 1) % len;
+    }
+    switched(instance, hard_cut)
+}
@@ -0,0 +1,199 @@
+//! Parameter validation
+//!
+//! The setters of [ProjectM](crate::core::ProjectM) check their values
+//! against the ranges below before anything is passed to libprojectm.
+//! [Parameter::range] exposes the ranges, e.g. to configure UI sliders.
+
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Mesh dimensions, `projectm_set_mesh_size` clamps each axis to this range.
+const MESH_SIZE: RangeInclusive<f64> = 8.0..=300.0;
+
+/// Largest window dimension, libprojectm passes it to GL as a `GLsizei`.
+const MAX_WINDOW_SIZE: f64 = i32::MAX as f64;
+
+/// The numeric engine parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Parameter {
+    BeatSensitivity,
+    HardCutDuration,
+    HardCutSensitivity,
+    SoftCutDuration,
+    PresetDuration,
+    MeshWidth,
+    MeshHeight,
+    Fps,
+    EasterEgg,
+    WindowWidth,
+    WindowHeight,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 11] = [
+        Parameter::BeatSensitivity,
+        Parameter::HardCutDuration,
+        Parameter::HardCutSensitivity,
+        Parameter::SoftCutDuration,
+        Parameter::PresetDuration,
+        Parameter::MeshWidth,
+        Parameter::MeshHeight,
+        Parameter::Fps,
+        Parameter::EasterEgg,
+        Parameter::WindowWidth,
+        Parameter::WindowHeight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Parameter::BeatSensitivity => "beat sensitivity",
+            Parameter::HardCutDuration => "hard cut duration",
+            Parameter::HardCutSensitivity => "hard cut sensitivity",
+            Parameter::SoftCutDuration => "soft cut duration",
+            Parameter::PresetDuration => "preset duration",
+            Parameter::MeshWidth => "mesh width",
+            Parameter::MeshHeight => "mesh height",
+            Parameter::Fps => "fps",
+            Parameter::EasterEgg => "easter egg",
+            Parameter::WindowWidth => "window width",
+            Parameter::WindowHeight => "window height",
+        }
+    }
+
+    /// Valid values, inclusive. Durations are in seconds and may be infinite.
+    ///
+    /// The limits come from the setter docs in `projectM-4/parameters.h`.
+    /// The header gives no bounds for sensitivities and durations, so only
+    /// negative values are rejected for those.
+    pub fn range(self) -> RangeInclusive<f64> {
+        match self {
+            Parameter::BeatSensitivity | Parameter::HardCutSensitivity => 0.0..=f64::INFINITY,
+            Parameter::HardCutDuration | Parameter::SoftCutDuration => 0.0..=f64::INFINITY,
+            Parameter::PresetDuration => 0.0..=f64::INFINITY,
+            Parameter::MeshWidth | Parameter::MeshHeight => MESH_SIZE,
+            // `projectm_set_fps` takes an `int32_t`
+            Parameter::Fps => 1.0..=i32::MAX as f64,
+            // The sigma of the preset duration randomization, 0 (the default)
+            // makes libprojectm use 1.0
+            Parameter::EasterEgg => 0.0..=f32::MAX as f64,
+            Parameter::WindowWidth | Parameter::WindowHeight => 1.0..=MAX_WINDOW_SIZE,
+        }
+    }
+
+    /// Check a value against [range](Parameter::range). NaN is always rejected.
+    pub fn check(self, value: f64) -> Result<(), ParameterError> {
+        if self.range().contains(&value) {
+            Ok(())
+        } else {
+            Err(ParameterError::OutOfRange {
+                parameter: self,
+                value,
+            })
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A duration in seconds: not negative and not NaN.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Seconds(f64);
+
+impl Seconds {
+    /// `None` for negative or NaN durations.
+    pub fn new(seconds: f64) -> Option<Self> {
+        (seconds >= 0.0).then_some(Seconds(seconds))
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl From<Seconds> for f64 {
+    fn from(seconds: Seconds) -> f64 {
+        seconds.0
+    }
+}
+
+/// A beat or hard cut sensitivity: not negative and not NaN.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Sensitivity(f32);
+
+impl Sensitivity {
+    /// `None` for negative or NaN sensitivities.
+    pub fn new(sensitivity: f32) -> Option<Self> {
+        (sensitivity >= 0.0).then_some(Sensitivity(sensitivity))
+    }
+
+    pub fn get(self) -> f32 {
+        self.0
+    }
+}
+
+impl From<Sensitivity> for f32 {
+    fn from(sensitivity: Sensitivity) -> f32 {
+        sensitivity.0
+    }
+}
+
+/// A parameter value that libprojectm can't use.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterError {
+    /// The value lies outside of [Parameter::range] (or is NaN).
+    OutOfRange { parameter: Parameter, value: f64 },
+    /// A path or preset that can't be passed to C because it contains a NUL byte.
+    InvalidString(String),
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParameterError::OutOfRange { parameter, value } => {
+                let range = parameter.range();
+                if range.end().is_infinite() {
+                    write!(
+                        f,
+                        "{} must be at least {}, got {}",
+                        parameter,
+                        range.start(),
+                        value
+                    )
+                } else {
+                    write!(
+                        f,
+                        "{} must be between {} and {}, got {}",
+                        parameter,
+                        range.start(),
+                        range.end(),
+                        value
+                    )
+                }
+            }
+            ParameterError::InvalidString(string) => {
+                write!(f, "{:?} contains a NUL byte", string)
+            }
+        }
+    }
+}
+
+impl Error for ParameterError {}
+
+pub(crate) fn check_path(path: &Path) -> Result<(), ParameterError> {
+    // A NUL byte survives the lossy conversion
+    check_string(&path.to_string_lossy())
+}
+
+pub(crate) fn check_string(string: &str) -> Result<(), ParameterError> {
+    if string.contains('\0') {
+        Err(ParameterError::InvalidString(string.to_string()))
+    } else {
+        Ok(())
+    }
+}
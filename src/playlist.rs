@@ -1,34 +1,74 @@
 extern crate libc;
-extern crate projectm_sys as ffi;
+use crate::ffi;
 
-use rand::Rng;
-use std::ffi::CString;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::path::Path;
+use std::sync::Mutex;
 
-use crate::core::ProjectM;
+use crate::core::{lock, path_to_cstring, Backend, Libprojectm, ProjectM, ThreadSafeBackend};
+use crate::index::{collect_presets, PresetIndex};
+use crate::parameters::ParameterError;
+use crate::playlist_file::PlaylistFile;
+use crate::search::Query;
 
-pub struct Playlist {
-    playlist: *mut ffi::projectm_playlist,
-    rng: rand::rngs::ThreadRng,
+pub struct Playlist<B: Backend = Libprojectm> {
+    playlist: Inner<B>,
+    rng: StdRng,
 }
 
-impl Playlist {
-    /// Create a new playlist for [Projectm](ProjectMHandle)
-    pub fn create(projectm: &ProjectM) -> Playlist {
-        let projectm = projectm.get_instance();
-        let instance = projectm.borrow_mut();
+enum Inner<B: Backend> {
+    // libprojectm's playlist, connected to the native instance
+    Native(*mut ffi::projectm_playlist),
+    // Kept here, the presets are loaded through the backend
+    Backend(ProjectM<B>, Mutex<Items>),
+}
+
+#[derive(Default)]
+struct Items {
+    paths: Vec<String>,
+    position: u32,
+    shuffle: bool,
+}
 
-        let playlist;
-        unsafe {
-            playlist = ffi::projectm_playlist_create(*instance);
+impl Items {
+    fn add(&mut self, path: String, allow_duplicates: bool) -> bool {
+        if !allow_duplicates && self.paths.contains(&path) {
+            return false;
         }
+        self.paths.push(path);
+        true
+    }
+}
+
+impl<B: Backend> Playlist<B> {
+    /// Create a new playlist for an instance.
+    ///
+    /// On libprojectm the playlist library switches the presets of the
+    /// instance itself, also when the preset duration is over. On other
+    /// backends the playlist loads its presets with
+    /// [ProjectM::load_preset_file], so e.g. a [Recorder](crate::record::Recorder)
+    /// sees every preset it plays, but only when one of the `play` methods is
+    /// called; call [play_next](Playlist::play_next) from the preset switch
+    /// requested callback to move on by itself.
+    pub fn create(projectm: &ProjectM<B>) -> Playlist<B> {
+        let native = B::native_handle(&projectm.get_instance());
+
+        let playlist = match native {
+            Some(instance) => Inner::Native(unsafe { ffi::projectm_playlist_create(instance) }),
+            None => Inner::Backend(projectm.clone(), Mutex::new(Items::default())),
+        };
         Playlist {
             playlist,
-            rng: rand::thread_rng(),
+            rng: StdRng::from_entropy(),
         }
     }
 
     pub fn len(&self) -> u32 {
-        unsafe { ffi::projectm_playlist_size(self.playlist) }
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe { ffi::projectm_playlist_size(*playlist) },
+            Inner::Backend(_, items) => lock(items).paths.len() as u32,
+        }
     }
 
     pub fn is_empty(&self) -> bool {
@@ -36,25 +76,207 @@ impl Playlist {
     }
 
     /// Scan and add a directory of presets to the playlist.
-    pub fn add_path(&self, path: &str, recursive: bool) {
-        unsafe {
-            let c_path = CString::new(path).unwrap();
-            ffi::projectm_playlist_add_path(self.playlist, c_path.as_ptr(), recursive, false);
+    /// Fails if the path contains a NUL byte.
+    pub fn add_path<P: AsRef<Path>>(&self, path: P, recursive: bool) -> Result<(), ParameterError> {
+        let path = path.as_ref();
+        let c_path = path_to_cstring(path)?;
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                ffi::projectm_playlist_add_path(*playlist, c_path.as_ptr(), recursive, false);
+            },
+            Inner::Backend(_, items) => {
+                let mut presets = Vec::new();
+                // Like libprojectm, add what can be read
+                let _ = collect_presets(path, recursive, &mut presets);
+                presets.sort();
+
+                let mut items = lock(items);
+                for preset in presets {
+                    items.add(preset.to_string_lossy().into_owned(), false);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a single preset file to the playlist.
+    /// Returns false if the preset was not added, e.g. because it is already in the playlist.
+    /// Fails if the path contains a NUL byte.
+    pub fn add_preset(&self, path: &Path, allow_duplicates: bool) -> Result<bool, ParameterError> {
+        let c_path = path_to_cstring(path)?;
+        Ok(match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                ffi::projectm_playlist_add_preset(*playlist, c_path.as_ptr(), allow_duplicates)
+            },
+            Inner::Backend(_, items) => {
+                lock(items).add(path.to_string_lossy().into_owned(), allow_duplicates)
+            }
+        })
+    }
+
+    /// Add several preset files to the playlist. Returns the number of presets added.
+    /// Nothing is added if a path contains a NUL byte.
+    pub fn add_presets<P: AsRef<Path>>(
+        &self,
+        paths: &[P],
+        allow_duplicates: bool,
+    ) -> Result<u32, ParameterError> {
+        let c_paths = paths
+            .iter()
+            .map(|p| path_to_cstring(p.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(match &self.playlist {
+            Inner::Native(playlist) => {
+                let mut c_path_pointers: Vec<_> = c_paths.iter().map(|p| p.as_ptr()).collect();
+
+                unsafe {
+                    ffi::projectm_playlist_add_presets(
+                        *playlist,
+                        c_path_pointers.as_mut_ptr(),
+                        c_path_pointers.len() as u32,
+                        allow_duplicates,
+                    )
+                }
+            }
+            Inner::Backend(_, items) => {
+                let mut items = lock(items);
+                paths
+                    .iter()
+                    .filter(|path| {
+                        let path = path.as_ref().to_string_lossy().into_owned();
+                        items.add(path, allow_duplicates)
+                    })
+                    .count() as u32
+            }
+        })
+    }
+
+    /// Add all presets of `index` that match `query`. Returns the number of presets added.
+    pub fn add_search_results(
+        &self,
+        index: &PresetIndex,
+        query: &Query,
+    ) -> Result<u32, ParameterError> {
+        let paths: Vec<_> = index
+            .search(query)
+            .into_iter()
+            .map(|entry| entry.path.as_path())
+            .collect();
+
+        self.add_presets(&paths, false)
+    }
+
+    /// Add the items of a playlist file in order. Returns the number of presets added.
+    ///
+    /// Only the paths are used by libprojectm; durations, weights and transitions
+    /// stay in the [PlaylistFile] for the application to apply.
+    pub fn add_playlist_file(&self, file: &PlaylistFile) -> Result<u32, ParameterError> {
+        let paths: Vec<_> = file.items.iter().map(|item| item.path.as_path()).collect();
+
+        self.add_presets(&paths, true)
+    }
+
+    /// Get the preset path at `index`.
+    pub fn item(&self, index: u32) -> Option<String> {
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                let item = ffi::projectm_playlist_item(*playlist, index);
+                if item.is_null() {
+                    return None;
+                }
+                let path = std::ffi::CStr::from_ptr(item)
+                    .to_string_lossy()
+                    .into_owned();
+                ffi::projectm_playlist_free_string(item);
+                Some(path)
+            },
+            Inner::Backend(_, items) => lock(items).paths.get(index as usize).cloned(),
+        }
+    }
+
+    /// Get all preset paths in playlist order.
+    pub fn items(&self) -> Vec<String> {
+        (0..self.len())
+            .filter_map(|index| self.item(index))
+            .collect()
+    }
+
+    /// Remove all presets from the playlist.
+    pub fn clear(&self) {
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                ffi::projectm_playlist_clear(*playlist);
+            },
+            Inner::Backend(_, items) => {
+                let mut items = lock(items);
+                items.paths.clear();
+                items.position = 0;
+            }
         }
     }
 
     /// Go to the next preset in the playlist (hard cut).
     pub fn play_next(&mut self) {
-        unsafe {
-            ffi::projectm_playlist_play_next(self.playlist, true);
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                ffi::projectm_playlist_play_next(*playlist, true);
+            },
+            Inner::Backend(_, items) => {
+                let (len, position, shuffle) = {
+                    let items = lock(items);
+                    (items.paths.len() as u32, items.position, items.shuffle)
+                };
+                if len == 0 {
+                    return;
+                }
+                let next = if shuffle {
+                    self.rng.gen_range(0..len)
+                } else {
+                    (position + 1) % len
+                };
+                self.play(next);
+            }
         }
     }
 
     /// Go to the previous preset in the playlist (hard cut).
     pub fn play_prev(&mut self) {
-        unsafe {
-            // FIXME THIS IS WRONG
-            ffi::projectm_playlist_play_previous(self.playlist, true);
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                // FIXME THIS IS WRONG
+                ffi::projectm_playlist_play_previous(*playlist, true);
+            },
+            Inner::Backend(_, items) => {
+                let (len, position) = {
+                    let items = lock(items);
+                    (items.paths.len() as u32, items.position)
+                };
+                if len > 0 {
+                    self.play((position + len - 1) % len);
+                }
+            }
+        }
+    }
+
+    /// Go to the preset at `index` (hard cut).
+    pub fn play(&mut self, index: u32) {
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                ffi::projectm_playlist_set_position(*playlist, index, true);
+            },
+            Inner::Backend(projectm, items) => {
+                let path = {
+                    let mut items = lock(items);
+                    if items.paths.is_empty() {
+                        return;
+                    }
+                    items.position = index.min(items.paths.len() as u32 - 1);
+                    items.paths[items.position as usize].clone()
+                };
+                // The paths were checked for NUL bytes when they were added
+                let _ = projectm.load_preset_file(&path, false);
+            }
         }
     }
 
@@ -62,23 +284,41 @@ impl Playlist {
     pub fn play_random(&mut self) {
         let len = self.len();
         let index: u32 = self.rng.gen_range(0..len);
-        unsafe {
-            ffi::projectm_playlist_set_position(self.playlist, index, true);
-        }
+        self.play(index);
+    }
+
+    /// Seed the generator of [play_random](Playlist::play_random), for a repeatable order.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
     }
 
     /// Set shuffle mode.
     pub fn set_shuffle(&self, shuffle: bool) {
-        unsafe {
-            ffi::projectm_playlist_set_shuffle(self.playlist, shuffle);
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe {
+                ffi::projectm_playlist_set_shuffle(*playlist, shuffle);
+            },
+            Inner::Backend(_, items) => lock(items).shuffle = shuffle,
         }
     }
 
     /// Get shuffle mode.
     pub fn get_shuffle(&self) -> bool {
-        unsafe { ffi::projectm_playlist_get_shuffle(self.playlist) }
+        match &self.playlist {
+            Inner::Native(playlist) => unsafe { ffi::projectm_playlist_get_shuffle(*playlist) },
+            Inner::Backend(_, items) => lock(items).shuffle,
+        }
+    }
+}
+
+impl<B: Backend> Drop for Playlist<B> {
+    fn drop(&mut self) {
+        // Disconnects the playlist from the instance
+        if let Inner::Native(playlist) = self.playlist {
+            unsafe { ffi::projectm_playlist_destroy(playlist) };
+        }
     }
 }
 
-unsafe impl Send for Playlist {}
-unsafe impl Sync for Playlist {}
+unsafe impl<B: ThreadSafeBackend> Send for Playlist<B> {}
+unsafe impl<B: ThreadSafeBackend> Sync for Playlist<B> {}
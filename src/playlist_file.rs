@@ -0,0 +1,262 @@
+//! Playlist files
+//!
+//! Reads and writes lists of presets as M3U/M3U8 or JSON files. M3U files
+//! only carry paths and optional `#EXTINF` durations; the JSON format (behind
+//! the `json` feature) also stores weights, transitions and tags per item.
+//!
+//! Relative paths are resolved against the directory of the playlist file,
+//! and paths below that directory are written relative to it so a playlist
+//! can be moved together with its presets.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How to switch to a playlist item.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum Transition {
+    Hard,
+    Soft {
+        /// Blend duration in seconds.
+        duration: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlaylistItem {
+    pub path: PathBuf,
+    /// Display time in seconds, overriding the instance's preset duration.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub duration: Option<f64>,
+    /// Relative weight when picking items at random.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub weight: Option<f32>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub transition: Option<Transition>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub tags: Vec<String>,
+}
+
+impl PlaylistItem {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        PlaylistItem {
+            path: path.into(),
+            duration: None,
+            weight: None,
+            transition: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Supported playlist file formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl PlaylistFormat {
+    /// Guess the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            #[cfg(feature = "json")]
+            "json" => Some(PlaylistFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlaylistFile {
+    #[cfg_attr(feature = "serde", serde(default = "default_version"))]
+    pub version: u32,
+    pub items: Vec<PlaylistItem>,
+}
+
+#[cfg(feature = "serde")]
+fn default_version() -> u32 {
+    1
+}
+
+impl PlaylistFile {
+    pub fn new() -> Self {
+        PlaylistFile {
+            version: 1,
+            items: Vec::new(),
+        }
+    }
+
+    /// Create a playlist file from the current contents of a [Playlist](crate::playlist::Playlist).
+    pub fn from_playlist(playlist: &crate::playlist::Playlist) -> Self {
+        PlaylistFile {
+            version: 1,
+            items: playlist
+                .items()
+                .into_iter()
+                .map(PlaylistItem::new)
+                .collect(),
+        }
+    }
+
+    /// Load a playlist, choosing the format from the file extension.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let format = PlaylistFormat::from_path(path).ok_or_else(|| unsupported_format(path))?;
+        let data = fs::read(path)?;
+        let base_dir = base_dir(path);
+
+        match format {
+            PlaylistFormat::M3u => Ok(Self::from_m3u(&String::from_utf8_lossy(&data), &base_dir)),
+            #[cfg(feature = "json")]
+            PlaylistFormat::Json => Self::from_json(&String::from_utf8_lossy(&data), &base_dir),
+        }
+    }
+
+    /// Save the playlist, choosing the format from the file extension.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let format = PlaylistFormat::from_path(path).ok_or_else(|| unsupported_format(path))?;
+        let base_dir = base_dir(path);
+
+        let data = match format {
+            PlaylistFormat::M3u => self.to_m3u(&base_dir),
+            #[cfg(feature = "json")]
+            PlaylistFormat::Json => self.to_json(&base_dir)?,
+        };
+
+        fs::write(path, data)
+    }
+
+    /// Parse an M3U/M3U8 playlist. Relative paths are resolved against `base_dir`.
+    pub fn from_m3u(data: &str, base_dir: &Path) -> Self {
+        let mut playlist = PlaylistFile::new();
+        let mut duration = None;
+
+        for line in data.lines() {
+            let line = line.trim().trim_start_matches('\u{feff}');
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                let length = info.split(',').next().unwrap_or("").trim();
+                duration = length.parse::<f64>().ok().filter(|seconds| *seconds > 0.0);
+                continue;
+            }
+
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let mut item = PlaylistItem::new(resolve(base_dir, Path::new(line)));
+            item.duration = duration.take();
+            playlist.items.push(item);
+        }
+
+        playlist
+    }
+
+    /// Write the playlist as extended M3U. Weights, transitions and tags are not stored.
+    pub fn to_m3u(&self, base_dir: &Path) -> String {
+        let mut m3u = String::from("#EXTM3U\n");
+
+        for item in &self.items {
+            if let Some(duration) = item.duration {
+                let title = item
+                    .path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                m3u.push_str(&format!("#EXTINF:{},{}\n", duration, title));
+            }
+            m3u.push_str(&relativize(base_dir, &item.path).to_string_lossy());
+            m3u.push('\n');
+        }
+
+        m3u
+    }
+
+    /// Parse a JSON playlist. Relative paths are resolved against `base_dir`.
+    #[cfg(feature = "json")]
+    pub fn from_json(data: &str, base_dir: &Path) -> io::Result<Self> {
+        let mut playlist: PlaylistFile = serde_json::from_str(data)?;
+
+        for item in &mut playlist.items {
+            item.path = resolve(base_dir, &item.path);
+        }
+
+        Ok(playlist)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, base_dir: &Path) -> io::Result<String> {
+        let mut playlist = self.clone();
+
+        for item in &mut playlist.items {
+            item.path = relativize(base_dir, &item.path);
+        }
+
+        Ok(serde_json::to_string_pretty(&playlist)?)
+    }
+
+    /// Find the item for a preset path, e.g. the one reported by a preset switch.
+    pub fn item(&self, path: &Path) -> Option<&PlaylistItem> {
+        self.items.iter().find(|item| item.path == path)
+    }
+}
+
+impl Default for PlaylistFile {
+    fn default() -> Self {
+        PlaylistFile::new()
+    }
+}
+
+fn base_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn resolve(base_dir: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base_dir.join(path)
+    }
+}
+
+fn relativize(base_dir: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(base_dir)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn unsupported_format(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported playlist format: {}", path.display()),
+    )
+}
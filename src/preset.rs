@@ -0,0 +1,551 @@
+//! Milkdrop preset files
+//!
+//! A small reader for the `.milk` text format used by projectM presets.
+//! It keeps the file line by line so that metadata can be extracted
+//! without handing the preset to libprojectm.
+//!
+//! [Preset::lint] finds the mistakes libprojectm silently ignores, and
+//! [Preset::format] normalizes a file, e.g. for a preset pack's CI.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Samplers that are provided by the renderer and never refer to a texture file.
+const BUILTIN_SAMPLERS: &[&str] = &[
+    "main",
+    "noise_lq",
+    "noise_lq_lite",
+    "noise_mq",
+    "noise_hq",
+    "noisevol_lq",
+    "noisevol_hq",
+    "blur1",
+    "blur2",
+    "blur3",
+];
+
+/// A single line of a preset file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    /// A section header such as `[preset00]`.
+    Section(String),
+    /// A `key=value` assignment.
+    Property { key: String, value: String },
+    /// A line starting with `//`.
+    Comment(String),
+    /// An empty or whitespace-only line.
+    Blank,
+    /// Anything else; libprojectm ignores these.
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preset {
+    lines: Vec<Line>,
+}
+
+/// Features and counts extracted from a preset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PresetMetadata {
+    pub per_frame_init_equations: u32,
+    pub per_frame_equations: u32,
+    pub per_pixel_equations: u32,
+    pub custom_waves: u32,
+    pub custom_shapes: u32,
+    pub has_warp_shader: bool,
+    pub has_composite_shader: bool,
+    /// Names of the textures sampled by the shaders, without the `sampler_` prefix.
+    pub textures: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found by [Preset::lint].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Diagnostic {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub severity: Severity,
+    /// Short kebab-case name of the check, e.g. `equation-gap`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(line: usize, severity: Severity, code: &'static str, message: String) -> Self {
+        Diagnostic {
+            line,
+            severity,
+            code,
+            message,
+        }
+    }
+}
+
+impl PresetMetadata {
+    pub fn has_per_pixel_code(&self) -> bool {
+        self.per_pixel_equations > 0
+    }
+
+    pub fn has_shaders(&self) -> bool {
+        self.has_warp_shader || self.has_composite_shader
+    }
+}
+
+impl Preset {
+    /// Parse the contents of a preset file. Parsing never fails, lines
+    /// that cannot be understood are kept as [Line::Unknown].
+    pub fn parse(data: &str) -> Preset {
+        let lines = data.lines().map(parse_line).collect();
+
+        Preset { lines }
+    }
+
+    /// Read and parse a preset file. Invalid UTF-8 is replaced, as older
+    /// presets are often saved in a legacy code page.
+    pub fn read(path: &Path) -> io::Result<Preset> {
+        let data = fs::read(path)?;
+
+        Ok(Preset::parse(&String::from_utf8_lossy(&data)))
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Iterate over all `key=value` assignments in file order.
+    pub fn properties(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Property { key, value } => Some((key.as_str(), value.as_str())),
+            _ => None,
+        })
+    }
+
+    /// Get the value of the first assignment to `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    pub fn metadata(&self) -> PresetMetadata {
+        let mut metadata = PresetMetadata::default();
+        let mut waves = BTreeSet::new();
+        let mut shapes = BTreeSet::new();
+        let mut shader_code = String::new();
+
+        for (key, value) in self.properties() {
+            let key = key.to_ascii_lowercase();
+
+            if let Some(index) = key.strip_prefix("per_frame_init_") {
+                if is_index(index) && has_code(value) {
+                    metadata.per_frame_init_equations += 1;
+                }
+            } else if let Some(index) = key.strip_prefix("per_frame_") {
+                if is_index(index) && has_code(value) {
+                    metadata.per_frame_equations += 1;
+                }
+            } else if let Some(index) = key.strip_prefix("per_pixel_") {
+                if is_index(index) && has_code(value) {
+                    metadata.per_pixel_equations += 1;
+                }
+            } else if let Some(rest) = key.strip_prefix("wavecode_") {
+                if let Some(index) = rest.strip_suffix("_enabled") {
+                    if is_enabled(value) {
+                        waves.insert(index.to_owned());
+                    }
+                }
+            } else if let Some(rest) = key.strip_prefix("shapecode_") {
+                if let Some(index) = rest.strip_suffix("_enabled") {
+                    if is_enabled(value) {
+                        shapes.insert(index.to_owned());
+                    }
+                }
+            } else if let Some(index) = key.strip_prefix("warp_") {
+                if is_index(index) {
+                    metadata.has_warp_shader = true;
+                    shader_code.push_str(value);
+                    shader_code.push('\n');
+                }
+            } else if let Some(index) = key.strip_prefix("comp_") {
+                if is_index(index) {
+                    metadata.has_composite_shader = true;
+                    shader_code.push_str(value);
+                    shader_code.push('\n');
+                }
+            }
+        }
+
+        metadata.custom_waves = waves.len() as u32;
+        metadata.custom_shapes = shapes.len() as u32;
+        metadata.textures = referenced_textures(&shader_code);
+
+        metadata
+    }
+
+    /// Check for lines libprojectm can't read or ignores, sorted by line.
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut first_use: HashMap<String, usize> = HashMap::new();
+        let mut has_header = false;
+        let mut has_properties = false;
+
+        for (number, line) in (1..).zip(&self.lines) {
+            match line {
+                Line::Section(_) => has_header = true,
+                Line::Unknown(text) => diagnostics.push(Diagnostic::new(
+                    number,
+                    Severity::Warning,
+                    "unknown-line",
+                    format!("'{}' is not a key=value line and is ignored", text.trim()),
+                )),
+                Line::Property { key, value } => {
+                    has_properties = true;
+
+                    if let Some(first) = first_use.get(&key.to_ascii_lowercase()) {
+                        diagnostics.push(Diagnostic::new(
+                            number,
+                            Severity::Warning,
+                            "duplicate-key",
+                            format!(
+                                "'{}' is already set on line {}, this is ignored",
+                                key, first
+                            ),
+                        ));
+                    } else {
+                        first_use.insert(key.to_ascii_lowercase(), number);
+                    }
+
+                    if equation_key(key).is_none() && value.trim().parse::<f64>().is_err() {
+                        diagnostics.push(Diagnostic::new(
+                            number,
+                            Severity::Error,
+                            "invalid-number",
+                            format!("'{}' is not a number: '{}'", key, value.trim()),
+                        ));
+                    }
+                }
+                Line::Comment(_) | Line::Blank => {}
+            }
+        }
+
+        if has_properties && !has_header {
+            diagnostics.push(Diagnostic::new(
+                1,
+                Severity::Warning,
+                "missing-header",
+                "no [preset00] section header".to_owned(),
+            ));
+        }
+
+        for (prefix, lines) in self.equations() {
+            if let Some((expected, index, skips_code)) = self.equation_gap(&lines) {
+                let (line, _) = lines.iter().find(|&&(_, i)| i == index).unwrap();
+                let message = if expected == 1 {
+                    format!("{} equations start at {} instead of 1", prefix, index)
+                } else {
+                    format!(
+                        "{} equations jump from {} to {}",
+                        prefix,
+                        expected - 1,
+                        index
+                    )
+                };
+
+                // Skipping lines that are only comments changes nothing
+                let severity = if skips_code {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                };
+
+                diagnostics.push(Diagnostic::new(
+                    *line,
+                    severity,
+                    "equation-gap",
+                    format!("{}, libprojectm stops reading at the gap", message),
+                ));
+            }
+
+            let mut depth = 0i32;
+            for &(line, _) in &lines {
+                if let Line::Property { value, .. } = &self.lines[line - 1] {
+                    for c in strip_comment(value).chars() {
+                        match c {
+                            '(' => depth += 1,
+                            ')' => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                }
+                if depth < 0 {
+                    break;
+                }
+            }
+            if depth != 0 {
+                diagnostics.push(Diagnostic::new(
+                    lines[0].0,
+                    Severity::Error,
+                    "unbalanced-parentheses",
+                    format!("unbalanced parentheses in the {} equations", prefix),
+                ));
+            }
+        }
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        diagnostics
+    }
+
+    /// A normalized copy: whitespace trimmed, blank line runs collapsed and
+    /// equation lines numbered from 1 without gaps, keeping their order.
+    /// Equations behind a gap that libprojectm skips keep their numbers if
+    /// they have code, renumbering would make them run. The same goes for
+    /// equations with duplicate numbers, libprojectm only runs the first one.
+    pub fn format(&self) -> Preset {
+        let mut lines: Vec<Line> = self
+            .lines
+            .iter()
+            .map(|line| match line {
+                Line::Property { key, value } => Line::Property {
+                    key: key.clone(),
+                    value: value.trim().to_owned(),
+                },
+                Line::Comment(text) => Line::Comment(text.trim_end().to_owned()),
+                Line::Unknown(text) => Line::Unknown(text.trim().to_owned()),
+                line => line.clone(),
+            })
+            .collect();
+
+        for (prefix, mut equations) in self.equations() {
+            // Closing the gap would run code libprojectm skips now
+            if let Some((_, _, true)) = self.equation_gap(&equations) {
+                continue;
+            }
+
+            // Number by the order libprojectm runs them in
+            equations.sort_by_key(|&(line, index)| (index, line));
+
+            // Renumbering a duplicate would run it too
+            if equations.windows(2).any(|pair| pair[0].1 == pair[1].1) {
+                continue;
+            }
+
+            for (number, (line, _)) in (1..).zip(equations) {
+                if let Line::Property { key, .. } = &mut lines[line - 1] {
+                    *key = format!("{}{}", &key[..prefix.len()], number);
+                }
+            }
+        }
+
+        let mut formatted: Vec<Line> = Vec::with_capacity(lines.len());
+        for line in lines {
+            let after_blank = formatted.last().map_or(true, |last| *last == Line::Blank);
+            if line != Line::Blank || !after_blank {
+                formatted.push(line);
+            }
+        }
+        if formatted.last() == Some(&Line::Blank) {
+            formatted.pop();
+        }
+
+        Preset { lines: formatted }
+    }
+
+    /// The first missing equation index, libprojectm reads 1, 2, 3, ... and
+    /// stops there. Returns the missing index, the index after it and whether
+    /// lines from there on have code.
+    fn equation_gap(&self, lines: &[(usize, u32)]) -> Option<(u32, u32, bool)> {
+        let mut indexes: Vec<u32> = lines.iter().map(|&(_, index)| index).collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+
+        let (expected, &index) = (1..)
+            .zip(&indexes)
+            .find(|&(expected, &index)| index != expected)?;
+        let skips_code = lines.iter().any(|&(line, i)| {
+            i >= index
+                && matches!(&self.lines[line - 1], Line::Property { value, .. } if has_code(value))
+        });
+
+        Some((expected, index, skips_code))
+    }
+
+    /// Equation lines as (line number, index), grouped by their lowercase key prefix.
+    fn equations(&self) -> Vec<(String, Vec<(usize, u32)>)> {
+        let mut groups: Vec<(String, Vec<(usize, u32)>)> = Vec::new();
+
+        for (number, line) in (1..).zip(&self.lines) {
+            if let Line::Property { key, .. } = line {
+                if let Some((prefix, index)) = equation_key(key) {
+                    let prefix = prefix.to_ascii_lowercase();
+                    match groups.iter_mut().find(|(p, _)| *p == prefix) {
+                        Some((_, lines)) => lines.push((number, index)),
+                        None => groups.push((prefix, vec![(number, index)])),
+                    }
+                }
+            }
+        }
+
+        groups
+    }
+}
+
+/// The file contents, one line per [Line] with `\n` line endings.
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Line::Section(name) => write!(f, "[{}]", name),
+            Line::Property { key, value } => write!(f, "{}={}", key, value),
+            Line::Comment(text) => write!(f, "//{}", text),
+            Line::Blank => Ok(()),
+            Line::Unknown(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Line {
+    let trimmed = line.trim();
+
+    if trimmed.is_empty() {
+        return Line::Blank;
+    }
+
+    if let Some(comment) = trimmed.strip_prefix("//") {
+        return Line::Comment(comment.to_owned());
+    }
+
+    if trimmed.starts_with('[') && trimmed.ends_with(']') {
+        return Line::Section(trimmed[1..trimmed.len() - 1].to_owned());
+    }
+
+    match trimmed.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Line::Property {
+            key: key.trim().to_owned(),
+            value: value.to_owned(),
+        },
+        _ => Line::Unknown(line.to_owned()),
+    }
+}
+
+fn is_index(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_enabled(value: &str) -> bool {
+    value
+        .trim()
+        .parse::<f64>()
+        .map(|v| v != 0.0)
+        .unwrap_or(false)
+}
+
+/// Returns true if an equation line contains anything besides a comment.
+fn has_code(value: &str) -> bool {
+    !strip_comment(value).trim().is_empty()
+}
+
+fn strip_comment(value: &str) -> &str {
+    match value.find("//") {
+        Some(pos) => &value[..pos],
+        None => value,
+    }
+}
+
+/// Split an equation or shader key like `per_frame_12` or `wave_0_per_point3`
+/// into its prefix and index.
+fn equation_key(key: &str) -> Option<(&str, u32)> {
+    let prefix = key.trim_end_matches(|c: char| c.is_ascii_digit());
+    let index = key[prefix.len()..].parse().ok()?;
+
+    let lower = prefix.to_ascii_lowercase();
+    let is_equation = match lower.as_str() {
+        "per_frame_" | "per_frame_init_" | "per_pixel_" | "warp_" | "comp_" => true,
+        _ => ["wave_", "shape_"].iter().any(|kind| {
+            lower
+                .strip_prefix(kind)
+                .and_then(|rest| rest.split_once('_'))
+                .is_some_and(|(number, part)| {
+                    is_index(number) && matches!(part, "init" | "per_frame" | "per_point")
+                })
+        }),
+    };
+
+    is_equation.then_some((prefix, index))
+}
+
+/// Collect the user texture names referenced as `sampler_<name>` in shader code.
+fn referenced_textures(code: &str) -> Vec<String> {
+    let mut textures = BTreeSet::new();
+    let mut rest = code;
+
+    while let Some(pos) = rest.find("sampler_") {
+        let preceded_by_ident = rest[..pos]
+            .chars()
+            .next_back()
+            .map(|c| c.is_ascii_alphanumeric() || c == '_')
+            .unwrap_or(false);
+        rest = &rest[pos + "sampler_".len()..];
+
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = rest[..end].to_ascii_lowercase();
+        rest = &rest[end..];
+
+        if preceded_by_ident {
+            continue;
+        }
+
+        // Strip the filtering/wrap mode prefix (fw_, fc_, pw_, pc_) to get the texture name.
+        let name = match name.split_once('_') {
+            Some(("fw" | "fc" | "pw" | "pc", texture)) => texture.to_owned(),
+            _ => name,
+        };
+
+        if !name.is_empty()
+            && !BUILTIN_SAMPLERS.contains(&name.as_str())
+            && !is_random_texture(&name)
+        {
+            textures.insert(name);
+        }
+    }
+
+    textures.into_iter().collect()
+}
+
+/// `rand00` to `rand15` (optionally with a `_prefix` filter) pick a random texture.
+fn is_random_texture(name: &str) -> bool {
+    name.strip_prefix("rand")
+        .map(|n| n.len() >= 2 && n.as_bytes()[..2].iter().all(u8::is_ascii_digit))
+        .unwrap_or(false)
+}
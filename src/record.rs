@@ -0,0 +1,985 @@
+//! Recording and replay of sessions
+//!
+//! [Recorder] is a [Backend] that passes every call on to another backend
+//! and logs the inputs with timestamps: PCM data, parameter changes, preset
+//! loads, touches and rendered frames. A [Replayer] reads the log back and
+//! drives a fresh instance through the same calls, e.g. to reproduce a
+//! glitch a user reported:
+//!
+//! ```no_run
+//! use projectm::core::ProjectM;
+//! use projectm::record::{Recorder, Replayer};
+//!
+//! let projectm = ProjectM::<Recorder>::with_backend();
+//! projectm.record_to_file("session.pmrec").unwrap();
+//! // ... run the visualizer
+//! projectm.stop_recording().unwrap();
+//!
+//! let replay = ProjectM::create();
+//! Replayer::open("session.pmrec").unwrap().replay(&replay).unwrap();
+//! ```
+//!
+//! When recording starts, the current parameters and the preset loaded last
+//! are logged first, so the replay starts from the same settings. The log
+//! holds what reaches the backend, after the gain stage and the delay line, so
+//! replay into an instance without either.
+//!
+//! A replay makes the same calls, it doesn't render the same frames:
+//!
+//! - Only presets loaded through the recorder are logged. A
+//!   [Playlist](crate::playlist::Playlist) created for the recording instance
+//!   loads its presets that way, one connected to the inner libprojectm
+//!   instance doesn't.
+//! - The animation within presets and the transitions between them follow
+//!   libprojectm's own clock, the wall clock. [Replayer::replay] makes the
+//!   calls as fast as it can, [Replayer::replay_in_real_time] at the recorded
+//!   times, which comes closer.
+//! - Presets that use random numbers differ on every run.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::core::{Backend, Libprojectm, ProjectM, ProjectMChannels, ProjectMTouchType};
+use crate::parameters::ParameterError;
+
+const MAGIC: &[u8; 6] = b"PMREC\0";
+const VERSION: u8 = 1;
+
+/// Longest string or sample block accepted when reading, against corrupt files.
+const MAX_LENGTH: u64 = 1 << 28;
+
+/// An input passed to the backend.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    LoadPresetFile {
+        filename: String,
+        smooth_transition: bool,
+    },
+    LoadPresetData {
+        data: String,
+        smooth_transition: bool,
+    },
+    ResetTextures,
+    SetTextureSearchPaths(Vec<PathBuf>),
+    SetBeatSensitivity(f32),
+    SetHardCutDuration(f64),
+    SetHardCutEnabled(bool),
+    SetHardCutSensitivity(f32),
+    SetSoftCutDuration(f64),
+    SetPresetDuration(f64),
+    SetMeshSize(usize, usize),
+    SetFps(u32),
+    SetAspectCorrection(bool),
+    SetEasterEgg(f32),
+    SetPresetLocked(bool),
+    SetWindowSize(usize, usize),
+    RenderFrame,
+    Touch {
+        x: f32,
+        y: f32,
+        pressure: i32,
+        touch_type: ProjectMTouchType,
+    },
+    TouchDrag {
+        x: f32,
+        y: f32,
+        pressure: i32,
+    },
+    TouchDestroy {
+        x: f32,
+        y: f32,
+    },
+    TouchDestroyAll,
+    PcmFloat {
+        samples: Vec<f32>,
+        channels: ProjectMChannels,
+    },
+    PcmInt16 {
+        samples: Vec<i16>,
+        channels: ProjectMChannels,
+    },
+    PcmUint8 {
+        samples: Vec<u8>,
+        channels: ProjectMChannels,
+    },
+    WriteDebugImage(Option<String>),
+}
+
+impl Event {
+    /// Make the call this event was recorded from.
+    pub fn apply<B: Backend>(&self, projectm: &ProjectM<B>) -> Result<(), ParameterError> {
+        match self {
+            Event::LoadPresetFile {
+                filename,
+                smooth_transition,
+            } => projectm.load_preset_file(filename, *smooth_transition)?,
+            Event::LoadPresetData {
+                data,
+                smooth_transition,
+            } => projectm.load_preset_data(data, *smooth_transition)?,
+            Event::ResetTextures => projectm.reset_textures(),
+            Event::SetTextureSearchPaths(paths) => {
+                projectm.set_texture_search_paths(paths)?;
+            }
+            Event::SetBeatSensitivity(sensitivity) => {
+                projectm.set_beat_sensitivity(*sensitivity)?
+            }
+            Event::SetHardCutDuration(seconds) => projectm.set_hard_cut_duration(*seconds)?,
+            Event::SetHardCutEnabled(enabled) => projectm.set_hard_cut_enabled(*enabled),
+            Event::SetHardCutSensitivity(sensitivity) => {
+                projectm.set_hard_cut_sensitivity(*sensitivity)?
+            }
+            Event::SetSoftCutDuration(seconds) => projectm.set_soft_cut_duration(*seconds)?,
+            Event::SetPresetDuration(seconds) => projectm.set_preset_duration(*seconds)?,
+            Event::SetMeshSize(mesh_x, mesh_y) => projectm.set_mesh_size(*mesh_x, *mesh_y)?,
+            Event::SetFps(fps) => projectm.set_fps(*fps)?,
+            Event::SetAspectCorrection(enabled) => projectm.set_aspect_correction(*enabled),
+            Event::SetEasterEgg(value) => projectm.set_easter_egg(*value)?,
+            Event::SetPresetLocked(lock) => projectm.set_preset_locked(*lock),
+            Event::SetWindowSize(width, height) => projectm.set_window_size(*width, *height)?,
+            Event::RenderFrame => projectm.render_frame(),
+            Event::Touch {
+                x,
+                y,
+                pressure,
+                touch_type,
+            } => projectm.touch(*x, *y, *pressure, *touch_type),
+            Event::TouchDrag { x, y, pressure } => projectm.touch_drag(*x, *y, *pressure),
+            Event::TouchDestroy { x, y } => projectm.touch_destroy(*x, *y),
+            Event::TouchDestroyAll => projectm.touch_destroy_all(),
+            Event::PcmFloat { samples, channels } => projectm.pcm_add_float(samples, *channels),
+            Event::PcmInt16 { samples, channels } => projectm.pcm_add_int16(samples, *channels),
+            Event::PcmUint8 { samples, channels } => projectm.pcm_add_uint8(samples, *channels),
+            Event::WriteDebugImage(output_file) => {
+                projectm.write_debug_image_on_next_frame(output_file.as_ref())
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An event and when it happened, relative to the start of the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub time: Duration,
+    pub event: Event,
+}
+
+/// A [Backend] that records the calls to `B`, e.g. `ProjectM::<Recorder>::with_backend()`.
+pub struct Recorder<B: Backend = Libprojectm>(PhantomData<B>);
+
+/// The handle of a [Recorder] instance.
+pub struct RecorderHandle<B: Backend> {
+    inner: B::Handle,
+    log: RefCell<Option<Log>>,
+    // The last preset load, logged when a recording starts
+    preset: RefCell<Option<Event>>,
+}
+
+struct Log {
+    writer: Box<dyn Write>,
+    started: Instant,
+    // Microseconds since the start, the time of the last entry
+    last: u64,
+    error: Option<io::Error>,
+}
+
+impl<B: Backend> RecorderHandle<B> {
+    /// The handle of the backend the calls are passed to.
+    pub fn inner(&self) -> &B::Handle {
+        &self.inner
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.log.borrow().is_some()
+    }
+
+    /// Start logging to `writer`, ending a recording in progress.
+    pub fn start<W: Write + 'static>(&self, writer: W) -> io::Result<()> {
+        self.stop()?;
+
+        let mut writer: Box<dyn Write> = Box::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+
+        *self.log.borrow_mut() = Some(Log {
+            writer,
+            started: Instant::now(),
+            last: 0,
+            error: None,
+        });
+        Ok(())
+    }
+
+    /// End the recording and flush it. Returns the first error writing the
+    /// log hit; logging stops at an error, calls are still passed on.
+    pub fn stop(&self) -> io::Result<()> {
+        match self.log.borrow_mut().take() {
+            Some(mut log) => match log.error {
+                Some(err) => Err(err),
+                None => log.writer.flush(),
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn record(&self, event: Event) {
+        if let Some(log) = self.log.borrow_mut().as_mut() {
+            if log.error.is_some() {
+                return;
+            }
+
+            // Saturates after half a million years
+            let time = log.started.elapsed().as_micros() as u64;
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, time.saturating_sub(log.last));
+            encode(&mut bytes, &event);
+            log.last = time.max(log.last);
+
+            if let Err(err) = log.writer.write_all(&bytes) {
+                log.error = Some(err);
+            }
+        }
+    }
+}
+
+impl<B: Backend> ProjectM<Recorder<B>> {
+    /// Start recording to `writer`, beginning with the current parameters.
+    pub fn start_recording<W: Write + 'static>(&self, writer: W) -> io::Result<()> {
+        let handle = self.get_instance();
+        handle.start(writer)?;
+
+        let (mesh_x, mesh_y) = B::get_mesh_size(&handle.inner);
+        let (width, height) = B::get_window_size(&handle.inner);
+        for event in [
+            Event::SetTextureSearchPaths(self.get_texture_search_paths()),
+            Event::SetBeatSensitivity(B::get_beat_sensitivity(&handle.inner)),
+            Event::SetHardCutDuration(B::get_hard_cut_duration(&handle.inner)),
+            Event::SetHardCutEnabled(B::get_hard_cut_enabled(&handle.inner)),
+            Event::SetHardCutSensitivity(B::get_hard_cut_sensitivity(&handle.inner)),
+            Event::SetSoftCutDuration(B::get_soft_cut_duration(&handle.inner)),
+            Event::SetPresetDuration(B::get_preset_duration(&handle.inner)),
+            Event::SetMeshSize(mesh_x, mesh_y),
+            Event::SetFps(B::get_fps(&handle.inner)),
+            Event::SetAspectCorrection(B::get_aspect_correction(&handle.inner)),
+            Event::SetEasterEgg(B::get_easter_egg(&handle.inner)),
+            Event::SetPresetLocked(B::get_preset_locked(&handle.inner)),
+        ] {
+            handle.record(event);
+        }
+        // The window has no size until the first one is set
+        if width > 0 && height > 0 {
+            handle.record(Event::SetWindowSize(width, height));
+        }
+        let preset = handle.preset.borrow().clone();
+        match preset {
+            Some(Event::LoadPresetFile { filename, .. }) => handle.record(Event::LoadPresetFile {
+                filename,
+                smooth_transition: false,
+            }),
+            Some(Event::LoadPresetData { data, .. }) => handle.record(Event::LoadPresetData {
+                data,
+                smooth_transition: false,
+            }),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Start recording to a new file at `path`.
+    pub fn record_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.start_recording(BufWriter::new(File::create(path)?))
+    }
+
+    /// End the recording, see [RecorderHandle::stop].
+    pub fn stop_recording(&self) -> io::Result<()> {
+        self.get_instance().stop()
+    }
+}
+
+impl<B: Backend> Backend for Recorder<B> {
+    type Handle = RecorderHandle<B>;
+
+    fn create() -> Self::Handle {
+        RecorderHandle {
+            inner: B::create(),
+            log: RefCell::new(None),
+            preset: RefCell::new(None),
+        }
+    }
+
+    fn destroy(instance: &Self::Handle) {
+        let _ = instance.stop();
+        B::destroy(&instance.inner);
+    }
+
+    fn load_preset_file(instance: &Self::Handle, filename: &str, smooth_transition: bool) {
+        B::load_preset_file(&instance.inner, filename, smooth_transition);
+        let event = Event::LoadPresetFile {
+            filename: filename.to_owned(),
+            smooth_transition,
+        };
+        *instance.preset.borrow_mut() = Some(event.clone());
+        instance.record(event);
+    }
+
+    fn load_preset_data(instance: &Self::Handle, data: &str, smooth_transition: bool) {
+        B::load_preset_data(&instance.inner, data, smooth_transition);
+        let event = Event::LoadPresetData {
+            data: data.to_owned(),
+            smooth_transition,
+        };
+        *instance.preset.borrow_mut() = Some(event.clone());
+        instance.record(event);
+    }
+
+    fn reset_textures(instance: &Self::Handle) {
+        B::reset_textures(&instance.inner);
+        instance.record(Event::ResetTextures);
+    }
+
+    fn get_version_components() -> (i32, i32, i32) {
+        B::get_version_components()
+    }
+
+    fn get_version_string() -> String {
+        B::get_version_string()
+    }
+
+    fn get_vcs_version_string() -> String {
+        B::get_vcs_version_string()
+    }
+
+    fn set_preset_switch_requested_event_callback<F: FnMut(bool) + 'static>(
+        instance: &Self::Handle,
+        callback: F,
+    ) {
+        B::set_preset_switch_requested_event_callback(&instance.inner, callback);
+    }
+
+    fn set_preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
+        instance: &Self::Handle,
+        callback: F,
+    ) {
+        B::set_preset_switch_failed_event_callback(&instance.inner, callback);
+    }
+
+    fn set_texture_search_paths(instance: &Self::Handle, texture_search_paths: &[PathBuf]) {
+        B::set_texture_search_paths(&instance.inner, texture_search_paths);
+        instance.record(Event::SetTextureSearchPaths(texture_search_paths.to_vec()));
+    }
+
+    fn get_beat_sensitivity(instance: &Self::Handle) -> f32 {
+        B::get_beat_sensitivity(&instance.inner)
+    }
+
+    fn set_beat_sensitivity(instance: &Self::Handle, sensitivity: f32) {
+        B::set_beat_sensitivity(&instance.inner, sensitivity);
+        instance.record(Event::SetBeatSensitivity(sensitivity));
+    }
+
+    fn get_hard_cut_duration(instance: &Self::Handle) -> f64 {
+        B::get_hard_cut_duration(&instance.inner)
+    }
+
+    fn set_hard_cut_duration(instance: &Self::Handle, seconds: f64) {
+        B::set_hard_cut_duration(&instance.inner, seconds);
+        instance.record(Event::SetHardCutDuration(seconds));
+    }
+
+    fn get_hard_cut_enabled(instance: &Self::Handle) -> bool {
+        B::get_hard_cut_enabled(&instance.inner)
+    }
+
+    fn set_hard_cut_enabled(instance: &Self::Handle, enabled: bool) {
+        B::set_hard_cut_enabled(&instance.inner, enabled);
+        instance.record(Event::SetHardCutEnabled(enabled));
+    }
+
+    fn get_hard_cut_sensitivity(instance: &Self::Handle) -> f32 {
+        B::get_hard_cut_sensitivity(&instance.inner)
+    }
+
+    fn set_hard_cut_sensitivity(instance: &Self::Handle, sensitivity: f32) {
+        B::set_hard_cut_sensitivity(&instance.inner, sensitivity);
+        instance.record(Event::SetHardCutSensitivity(sensitivity));
+    }
+
+    fn get_soft_cut_duration(instance: &Self::Handle) -> f64 {
+        B::get_soft_cut_duration(&instance.inner)
+    }
+
+    fn set_soft_cut_duration(instance: &Self::Handle, seconds: f64) {
+        B::set_soft_cut_duration(&instance.inner, seconds);
+        instance.record(Event::SetSoftCutDuration(seconds));
+    }
+
+    fn get_preset_duration(instance: &Self::Handle) -> f64 {
+        B::get_preset_duration(&instance.inner)
+    }
+
+    fn set_preset_duration(instance: &Self::Handle, seconds: f64) {
+        B::set_preset_duration(&instance.inner, seconds);
+        instance.record(Event::SetPresetDuration(seconds));
+    }
+
+    fn get_mesh_size(instance: &Self::Handle) -> (usize, usize) {
+        B::get_mesh_size(&instance.inner)
+    }
+
+    fn set_mesh_size(instance: &Self::Handle, mesh_x: usize, mesh_y: usize) {
+        B::set_mesh_size(&instance.inner, mesh_x, mesh_y);
+        instance.record(Event::SetMeshSize(mesh_x, mesh_y));
+    }
+
+    fn get_fps(instance: &Self::Handle) -> u32 {
+        B::get_fps(&instance.inner)
+    }
+
+    fn set_fps(instance: &Self::Handle, fps: u32) {
+        B::set_fps(&instance.inner, fps);
+        instance.record(Event::SetFps(fps));
+    }
+
+    fn get_aspect_correction(instance: &Self::Handle) -> bool {
+        B::get_aspect_correction(&instance.inner)
+    }
+
+    fn set_aspect_correction(instance: &Self::Handle, enabled: bool) {
+        B::set_aspect_correction(&instance.inner, enabled);
+        instance.record(Event::SetAspectCorrection(enabled));
+    }
+
+    fn get_easter_egg(instance: &Self::Handle) -> f32 {
+        B::get_easter_egg(&instance.inner)
+    }
+
+    fn set_easter_egg(instance: &Self::Handle, sensitivity: f32) {
+        B::set_easter_egg(&instance.inner, sensitivity);
+        instance.record(Event::SetEasterEgg(sensitivity));
+    }
+
+    fn get_preset_locked(instance: &Self::Handle) -> bool {
+        B::get_preset_locked(&instance.inner)
+    }
+
+    fn set_preset_locked(instance: &Self::Handle, lock: bool) {
+        B::set_preset_locked(&instance.inner, lock);
+        instance.record(Event::SetPresetLocked(lock));
+    }
+
+    fn get_window_size(instance: &Self::Handle) -> (usize, usize) {
+        B::get_window_size(&instance.inner)
+    }
+
+    fn set_window_size(instance: &Self::Handle, width: usize, height: usize) {
+        B::set_window_size(&instance.inner, width, height);
+        instance.record(Event::SetWindowSize(width, height));
+    }
+
+    fn render_frame(instance: &Self::Handle) {
+        // Calls made by the callbacks during the frame are logged after it
+        instance.record(Event::RenderFrame);
+        B::render_frame(&instance.inner);
+    }
+
+    fn touch(
+        instance: &Self::Handle,
+        x: f32,
+        y: f32,
+        pressure: i32,
+        touch_type: ProjectMTouchType,
+    ) {
+        B::touch(&instance.inner, x, y, pressure, touch_type);
+        instance.record(Event::Touch {
+            x,
+            y,
+            pressure,
+            touch_type,
+        });
+    }
+
+    fn touch_drag(instance: &Self::Handle, x: f32, y: f32, pressure: i32) {
+        B::touch_drag(&instance.inner, x, y, pressure);
+        instance.record(Event::TouchDrag { x, y, pressure });
+    }
+
+    fn touch_destroy(instance: &Self::Handle, x: f32, y: f32) {
+        B::touch_destroy(&instance.inner, x, y);
+        instance.record(Event::TouchDestroy { x, y });
+    }
+
+    fn touch_destroy_all(instance: &Self::Handle) {
+        B::touch_destroy_all(&instance.inner);
+        instance.record(Event::TouchDestroyAll);
+    }
+
+    fn pcm_get_max_samples() -> u32 {
+        B::pcm_get_max_samples()
+    }
+
+    fn pcm_add_float(instance: &Self::Handle, samples: &[f32], channels: ProjectMChannels) {
+        B::pcm_add_float(&instance.inner, samples, channels);
+        instance.record(Event::PcmFloat {
+            samples: samples.to_vec(),
+            channels,
+        });
+    }
+
+    fn pcm_add_int16(instance: &Self::Handle, samples: &[i16], channels: ProjectMChannels) {
+        B::pcm_add_int16(&instance.inner, samples, channels);
+        instance.record(Event::PcmInt16 {
+            samples: samples.to_vec(),
+            channels,
+        });
+    }
+
+    fn pcm_add_uint8(instance: &Self::Handle, samples: &[u8], channels: ProjectMChannels) {
+        B::pcm_add_uint8(&instance.inner, samples, channels);
+        instance.record(Event::PcmUint8 {
+            samples: samples.to_vec(),
+            channels,
+        });
+    }
+
+    fn write_debug_image_on_next_frame(instance: &Self::Handle, output_file: Option<&String>) {
+        B::write_debug_image_on_next_frame(&instance.inner, output_file);
+        instance.record(Event::WriteDebugImage(output_file.cloned()));
+    }
+}
+
+/// Reads a recording, one [Entry] at a time.
+pub struct Replayer<R: Read> {
+    reader: R,
+    // Microseconds since the start, the time of the last entry
+    time: u64,
+}
+
+impl Replayer<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Replayer<R> {
+    /// Start reading a recording, checking its header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 7];
+        reader.read_exact(&mut header)?;
+        if &header[..6] != MAGIC {
+            return Err(invalid_data("not a projectM recording"));
+        }
+        if header[6] != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported recording version {}",
+                header[6]
+            )));
+        }
+
+        Ok(Replayer { reader, time: 0 })
+    }
+
+    /// The next entry, or `None` at the end of the recording.
+    pub fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        let mut first = [0];
+        loop {
+            match self.reader.read(&mut first) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let delta = read_varint_from(first[0], &mut self.reader)?;
+        self.time = self.time.saturating_add(delta);
+        let event = decode(&mut self.reader)?;
+
+        Ok(Some(Entry {
+            time: Duration::from_micros(self.time),
+            event,
+        }))
+    }
+
+    /// Make all recorded calls on `projectm` as fast as possible. Returns the
+    /// number of rendered frames.
+    pub fn replay<B: Backend>(&mut self, projectm: &ProjectM<B>) -> io::Result<u64> {
+        self.replay_with(projectm, |_| {})
+    }
+
+    /// Make all recorded calls on `projectm` at the recorded times.
+    pub fn replay_in_real_time<B: Backend>(&mut self, projectm: &ProjectM<B>) -> io::Result<u64> {
+        let started = Instant::now();
+        self.replay_with(projectm, |time| {
+            if let Some(wait) = time.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+        })
+    }
+
+    fn replay_with<B: Backend, F: FnMut(Duration)>(
+        &mut self,
+        projectm: &ProjectM<B>,
+        mut wait: F,
+    ) -> io::Result<u64> {
+        let mut frames = 0;
+
+        while let Some(entry) = self.next_entry()? {
+            wait(entry.time);
+            entry.event.apply(projectm).map_err(invalid_data)?;
+            if entry.event == Event::RenderFrame {
+                frames += 1;
+            }
+        }
+
+        Ok(frames)
+    }
+}
+
+impl<R: Read> Iterator for Replayer<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+fn invalid_data<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+// -----------------
+// Encoding
+// -----------------
+//
+// An entry is the time since the previous one in microseconds, a tag byte
+// and the arguments. Integers are LEB128 varints, floats are little endian,
+// strings and lists start with their length.
+
+fn encode(bytes: &mut Vec<u8>, event: &Event) {
+    match event {
+        Event::LoadPresetFile {
+            filename,
+            smooth_transition,
+        } => {
+            bytes.push(1);
+            write_str(bytes, filename);
+            bytes.push(*smooth_transition as u8);
+        }
+        Event::LoadPresetData {
+            data,
+            smooth_transition,
+        } => {
+            bytes.push(2);
+            write_str(bytes, data);
+            bytes.push(*smooth_transition as u8);
+        }
+        Event::ResetTextures => bytes.push(3),
+        Event::SetTextureSearchPaths(paths) => {
+            bytes.push(4);
+            write_varint(bytes, paths.len() as u64);
+            for path in paths {
+                write_path(bytes, path);
+            }
+        }
+        Event::SetBeatSensitivity(value) => {
+            bytes.push(5);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Event::SetHardCutDuration(value) => {
+            bytes.push(6);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Event::SetHardCutEnabled(value) => bytes.extend_from_slice(&[7, *value as u8]),
+        Event::SetHardCutSensitivity(value) => {
+            bytes.push(8);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Event::SetSoftCutDuration(value) => {
+            bytes.push(9);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Event::SetPresetDuration(value) => {
+            bytes.push(10);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Event::SetMeshSize(mesh_x, mesh_y) => {
+            bytes.push(11);
+            write_varint(bytes, *mesh_x as u64);
+            write_varint(bytes, *mesh_y as u64);
+        }
+        Event::SetFps(fps) => {
+            bytes.push(12);
+            write_varint(bytes, *fps as u64);
+        }
+        Event::SetAspectCorrection(value) => bytes.extend_from_slice(&[13, *value as u8]),
+        Event::SetEasterEgg(value) => {
+            bytes.push(14);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Event::SetPresetLocked(value) => bytes.extend_from_slice(&[15, *value as u8]),
+        Event::SetWindowSize(width, height) => {
+            bytes.push(16);
+            write_varint(bytes, *width as u64);
+            write_varint(bytes, *height as u64);
+        }
+        Event::RenderFrame => bytes.push(17),
+        Event::Touch {
+            x,
+            y,
+            pressure,
+            touch_type,
+        } => {
+            bytes.push(18);
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+            bytes.extend_from_slice(&pressure.to_le_bytes());
+            write_varint(bytes, *touch_type as u64);
+        }
+        Event::TouchDrag { x, y, pressure } => {
+            bytes.push(19);
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+            bytes.extend_from_slice(&pressure.to_le_bytes());
+        }
+        Event::TouchDestroy { x, y } => {
+            bytes.push(20);
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+        }
+        Event::TouchDestroyAll => bytes.push(21),
+        Event::PcmFloat { samples, channels } => {
+            bytes.push(22);
+            write_varint(bytes, *channels as u64);
+            write_varint(bytes, samples.len() as u64);
+            for sample in samples {
+                bytes.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        Event::PcmInt16 { samples, channels } => {
+            bytes.push(23);
+            write_varint(bytes, *channels as u64);
+            write_varint(bytes, samples.len() as u64);
+            for sample in samples {
+                bytes.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        Event::PcmUint8 { samples, channels } => {
+            bytes.push(24);
+            write_varint(bytes, *channels as u64);
+            write_varint(bytes, samples.len() as u64);
+            bytes.extend_from_slice(samples);
+        }
+        Event::WriteDebugImage(output_file) => {
+            bytes.push(25);
+            match output_file {
+                Some(output_file) => {
+                    bytes.push(1);
+                    write_str(bytes, output_file);
+                }
+                None => bytes.push(0),
+            }
+        }
+    }
+}
+
+fn decode<R: Read>(reader: &mut R) -> io::Result<Event> {
+    let event = match read_array::<R, 1>(reader)?[0] {
+        1 => Event::LoadPresetFile {
+            filename: read_str(reader)?,
+            smooth_transition: read_bool(reader)?,
+        },
+        2 => Event::LoadPresetData {
+            data: read_str(reader)?,
+            smooth_transition: read_bool(reader)?,
+        },
+        3 => Event::ResetTextures,
+        4 => {
+            let count = read_length(reader)?;
+            let mut paths = Vec::new();
+            for _ in 0..count {
+                paths.push(read_path(reader)?);
+            }
+            Event::SetTextureSearchPaths(paths)
+        }
+        5 => Event::SetBeatSensitivity(f32::from_le_bytes(read_array(reader)?)),
+        6 => Event::SetHardCutDuration(f64::from_le_bytes(read_array(reader)?)),
+        7 => Event::SetHardCutEnabled(read_bool(reader)?),
+        8 => Event::SetHardCutSensitivity(f32::from_le_bytes(read_array(reader)?)),
+        9 => Event::SetSoftCutDuration(f64::from_le_bytes(read_array(reader)?)),
+        10 => Event::SetPresetDuration(f64::from_le_bytes(read_array(reader)?)),
+        11 => Event::SetMeshSize(read_usize(reader)?, read_usize(reader)?),
+        12 => Event::SetFps(read_u32(reader)?),
+        13 => Event::SetAspectCorrection(read_bool(reader)?),
+        14 => Event::SetEasterEgg(f32::from_le_bytes(read_array(reader)?)),
+        15 => Event::SetPresetLocked(read_bool(reader)?),
+        16 => Event::SetWindowSize(read_usize(reader)?, read_usize(reader)?),
+        17 => Event::RenderFrame,
+        18 => Event::Touch {
+            x: f32::from_le_bytes(read_array(reader)?),
+            y: f32::from_le_bytes(read_array(reader)?),
+            pressure: i32::from_le_bytes(read_array(reader)?),
+            touch_type: read_u32(reader)?,
+        },
+        19 => Event::TouchDrag {
+            x: f32::from_le_bytes(read_array(reader)?),
+            y: f32::from_le_bytes(read_array(reader)?),
+            pressure: i32::from_le_bytes(read_array(reader)?),
+        },
+        20 => Event::TouchDestroy {
+            x: f32::from_le_bytes(read_array(reader)?),
+            y: f32::from_le_bytes(read_array(reader)?),
+        },
+        21 => Event::TouchDestroyAll,
+        22 => {
+            let channels = read_u32(reader)?;
+            let bytes = read_bytes(reader, 4)?;
+            Event::PcmFloat {
+                samples: bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect(),
+                channels,
+            }
+        }
+        23 => {
+            let channels = read_u32(reader)?;
+            let bytes = read_bytes(reader, 2)?;
+            Event::PcmInt16 {
+                samples: bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect(),
+                channels,
+            }
+        }
+        24 => {
+            let channels = read_u32(reader)?;
+            Event::PcmUint8 {
+                samples: read_bytes(reader, 1)?,
+                channels,
+            }
+        }
+        25 => Event::WriteDebugImage(match read_bool(reader)? {
+            true => Some(read_str(reader)?),
+            false => None,
+        }),
+        tag => return Err(invalid_data(format!("unknown event {}", tag))),
+    };
+
+    Ok(event)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_varint(bytes, value.len() as u64);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+// Paths are kept as raw bytes on unix, as they are passed to libprojectm
+#[cfg(unix)]
+fn write_path(bytes: &mut Vec<u8>, path: &Path) {
+    use std::os::unix::ffi::OsStrExt;
+    let path = path.as_os_str().as_bytes();
+    write_varint(bytes, path.len() as u64);
+    bytes.extend_from_slice(path);
+}
+
+#[cfg(not(unix))]
+fn write_path(bytes: &mut Vec<u8>, path: &Path) {
+    write_str(bytes, &path.to_string_lossy());
+}
+
+#[cfg(unix)]
+fn read_path<R: Read>(reader: &mut R) -> io::Result<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(std::ffi::OsString::from_vec(read_bytes(reader, 1)?).into())
+}
+
+#[cfg(not(unix))]
+fn read_path<R: Read>(reader: &mut R) -> io::Result<PathBuf> {
+    read_str(reader).map(PathBuf::from)
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_varint_from<R: Read>(first: u8, reader: &mut R) -> io::Result<u64> {
+    let mut value = (first & 0x7f) as u64;
+    let mut byte = first;
+    let mut shift = 7;
+
+    while byte & 0x80 != 0 {
+        if shift > 63 {
+            return Err(invalid_data("varint is too long"));
+        }
+        byte = read_array::<R, 1>(reader)?[0];
+        // Only the lowest bit of the tenth byte fits
+        if shift == 63 && byte > 1 {
+            return Err(invalid_data("varint is too long"));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+    }
+
+    Ok(value)
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let first = read_array::<R, 1>(reader)?[0];
+    read_varint_from(first, reader)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    u32::try_from(read_varint(reader)?).map_err(invalid_data)
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(read_varint(reader)?).map_err(invalid_data)
+}
+
+fn read_length<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let length = read_varint(reader)?;
+    if length > MAX_LENGTH {
+        return Err(invalid_data(format!("length {} is too large", length)));
+    }
+    Ok(length)
+}
+
+fn read_bool<R: Read>(reader: &mut R) -> io::Result<bool> {
+    match read_array::<R, 1>(reader)?[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        value => Err(invalid_data(format!("invalid bool {}", value))),
+    }
+}
+
+/// A length followed by that many items of `size` bytes.
+fn read_bytes<R: Read>(reader: &mut R, size: u64) -> io::Result<Vec<u8>> {
+    let length = read_length(reader)? * size;
+    let mut bytes = Vec::new();
+    reader.by_ref().take(length).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn read_str<R: Read>(reader: &mut R) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader, 1)?).map_err(invalid_data)
+}
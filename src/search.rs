@@ -0,0 +1,150 @@
+//! Preset search
+//!
+//! Queries over a [PresetIndex]: free text over titles and authors, tag
+//! filters and feature predicates. The results can be loaded into a
+//! [Playlist](crate::playlist::Playlist) with `add_search_results`.
+
+use crate::index::{normalize_tag, IndexEntry, PresetIndex};
+
+/// Preset features that can be required or excluded in a [Query].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    PerFrameCode,
+    PerPixelCode,
+    CustomWaves,
+    CustomShapes,
+    WarpShader,
+    CompositeShader,
+    Textures,
+}
+
+impl Feature {
+    pub fn present_in(self, entry: &IndexEntry) -> bool {
+        let metadata = &entry.metadata;
+        match self {
+            Feature::PerFrameCode => {
+                metadata.per_frame_equations > 0 || metadata.per_frame_init_equations > 0
+            }
+            Feature::PerPixelCode => metadata.per_pixel_equations > 0,
+            Feature::CustomWaves => metadata.custom_waves > 0,
+            Feature::CustomShapes => metadata.custom_shapes > 0,
+            Feature::WarpShader => metadata.has_warp_shader,
+            Feature::CompositeShader => metadata.has_composite_shader,
+            Feature::Textures => !metadata.textures.is_empty(),
+        }
+    }
+}
+
+/// A preset query. All conditions must match; an empty query matches everything.
+///
+/// ```
+/// use projectm::search::{Feature, Query};
+///
+/// let query = Query::new()
+///     .author("geiss")
+///     .include_tag("calm")
+///     .without(Feature::CustomShapes);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    terms: Vec<String>,
+    author: Option<String>,
+    include_tags: Vec<String>,
+    exclude_tags: Vec<String>,
+    with: Vec<Feature>,
+    without: Vec<Feature>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Query::default()
+    }
+
+    /// Match presets whose title, author or file name contain every word of `text`.
+    pub fn text(mut self, text: &str) -> Self {
+        self.terms
+            .extend(text.split_whitespace().map(|term| term.to_lowercase()));
+        self
+    }
+
+    /// Match presets with an author containing `author`.
+    pub fn author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_lowercase());
+        self
+    }
+
+    /// Match presets that have the tag. Several included tags must all be present.
+    pub fn include_tag(mut self, tag: &str) -> Self {
+        self.include_tags.push(normalize_tag(tag));
+        self
+    }
+
+    /// Skip presets that have the tag.
+    pub fn exclude_tag(mut self, tag: &str) -> Self {
+        self.exclude_tags.push(normalize_tag(tag));
+        self
+    }
+
+    /// Match presets that use `feature`.
+    pub fn with(mut self, feature: Feature) -> Self {
+        self.with.push(feature);
+        self
+    }
+
+    /// Match presets that don't use `feature`.
+    pub fn without(mut self, feature: Feature) -> Self {
+        self.without.push(feature);
+        self
+    }
+
+    pub fn matches(&self, entry: &IndexEntry) -> bool {
+        let author = entry
+            .author
+            .as_deref()
+            .map(str::to_lowercase)
+            .unwrap_or_default();
+
+        if let Some(wanted) = &self.author {
+            if !author.contains(wanted.as_str()) {
+                return false;
+            }
+        }
+
+        if !self.terms.is_empty() {
+            let haystack = format!(
+                "{}\n{}\n{}",
+                entry.title.to_lowercase(),
+                author,
+                entry
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_lowercase())
+                    .unwrap_or_default()
+            );
+            if !self
+                .terms
+                .iter()
+                .all(|term| haystack.contains(term.as_str()))
+            {
+                return false;
+            }
+        }
+
+        let has_tag = |tag: &String| entry.tags.contains(tag);
+        if !self.include_tags.iter().all(has_tag) || self.exclude_tags.iter().any(has_tag) {
+            return false;
+        }
+
+        self.with.iter().all(|feature| feature.present_in(entry))
+            && !self.without.iter().any(|feature| feature.present_in(entry))
+    }
+}
+
+impl PresetIndex {
+    /// All indexed presets matching `query`, ordered by path.
+    pub fn search(&self, query: &Query) -> Vec<&IndexEntry> {
+        self.entries()
+            .filter(|entry| query.matches(entry))
+            .collect()
+    }
+}
@@ -0,0 +1,242 @@
+//! Engine settings
+//!
+//! [Settings] holds every engine parameter of a [ProjectM] instance. It can be
+//! taken from a running instance, applied to one in a single call, and (with
+//! the `toml` or `json` feature) stored in a settings file. Missing entries in
+//! a file fall back to the libprojectm defaults.
+
+#[cfg(any(feature = "toml", feature = "json"))]
+use std::fs;
+#[cfg(any(feature = "toml", feature = "json"))]
+use std::io;
+#[cfg(any(feature = "toml", feature = "json"))]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::core::{Backend, ProjectM};
+use crate::parameters::{Parameter, ParameterError};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Settings {
+    pub beat_sensitivity: f32,
+    pub hard_cut_enabled: bool,
+    /// Minimum time between hard cuts in seconds.
+    #[cfg_attr(feature = "serde", serde(with = "seconds"))]
+    pub hard_cut_duration: f64,
+    pub hard_cut_sensitivity: f32,
+    /// Blend time of soft transitions in seconds.
+    #[cfg_attr(feature = "serde", serde(with = "seconds"))]
+    pub soft_cut_duration: f64,
+    /// Display time of a preset in seconds.
+    #[cfg_attr(feature = "serde", serde(with = "seconds"))]
+    pub preset_duration: f64,
+    pub mesh_size: (usize, usize),
+    pub fps: u32,
+    pub aspect_correction: bool,
+    pub easter_egg: f32,
+    pub preset_locked: bool,
+    pub window_size: (usize, usize),
+}
+
+impl Default for Settings {
+    /// The defaults of a newly created libprojectm instance.
+    fn default() -> Self {
+        Settings {
+            beat_sensitivity: 1.0,
+            hard_cut_enabled: false,
+            hard_cut_duration: 20.0,
+            hard_cut_sensitivity: 2.0,
+            soft_cut_duration: 3.0,
+            preset_duration: 30.0,
+            mesh_size: (48, 32),
+            fps: 60,
+            aspect_correction: true,
+            easter_egg: 0.0,
+            preset_locked: false,
+            window_size: (0, 0),
+        }
+    }
+}
+
+/// Supported settings file formats.
+#[cfg(any(feature = "toml", feature = "json"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsFormat {
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+#[cfg(any(feature = "toml", feature = "json"))]
+impl SettingsFormat {
+    /// Guess the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            #[cfg(feature = "toml")]
+            "toml" => Some(SettingsFormat::Toml),
+            #[cfg(feature = "json")]
+            "json" => Some(SettingsFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+impl Settings {
+    /// Read the current parameters of an instance.
+    pub fn from_instance<B: Backend>(projectm: &ProjectM<B>) -> Self {
+        let instance = projectm.get_instance();
+
+        Settings {
+            beat_sensitivity: B::get_beat_sensitivity(&instance),
+            hard_cut_enabled: B::get_hard_cut_enabled(&instance),
+            hard_cut_duration: B::get_hard_cut_duration(&instance),
+            hard_cut_sensitivity: B::get_hard_cut_sensitivity(&instance),
+            soft_cut_duration: B::get_soft_cut_duration(&instance),
+            preset_duration: B::get_preset_duration(&instance),
+            mesh_size: B::get_mesh_size(&instance),
+            fps: B::get_fps(&instance),
+            aspect_correction: B::get_aspect_correction(&instance),
+            easter_egg: B::get_easter_egg(&instance),
+            preset_locked: B::get_preset_locked(&instance),
+            window_size: B::get_window_size(&instance),
+        }
+    }
+
+    /// Check all values against their [Parameter] ranges.
+    pub fn validate(&self) -> Result<(), ParameterError> {
+        Parameter::BeatSensitivity.check(self.beat_sensitivity as f64)?;
+        Parameter::HardCutDuration.check(self.hard_cut_duration)?;
+        Parameter::HardCutSensitivity.check(self.hard_cut_sensitivity as f64)?;
+        Parameter::SoftCutDuration.check(self.soft_cut_duration)?;
+        Parameter::PresetDuration.check(self.preset_duration)?;
+        Parameter::MeshWidth.check(self.mesh_size.0 as f64)?;
+        Parameter::MeshHeight.check(self.mesh_size.1 as f64)?;
+        Parameter::Fps.check(self.fps as f64)?;
+        Parameter::EasterEgg.check(self.easter_egg as f64)?;
+        // 0x0 is the size of an instance that hasn't been shown yet
+        if self.window_size != (0, 0) {
+            Parameter::WindowWidth.check(self.window_size.0 as f64)?;
+            Parameter::WindowHeight.check(self.window_size.1 as f64)?;
+        }
+        Ok(())
+    }
+
+    /// Validate, then set all parameters on an instance. Nothing is changed if
+    /// a value is invalid. The instance stays locked for the whole call, so
+    /// other threads can't observe a partially applied state.
+    pub fn apply<B: Backend>(&self, projectm: &ProjectM<B>) -> Result<(), ParameterError> {
+        self.validate()?;
+
+        let instance = projectm.get_instance();
+
+        B::set_beat_sensitivity(&instance, self.beat_sensitivity);
+        B::set_hard_cut_enabled(&instance, self.hard_cut_enabled);
+        B::set_hard_cut_duration(&instance, self.hard_cut_duration);
+        B::set_hard_cut_sensitivity(&instance, self.hard_cut_sensitivity);
+        B::set_soft_cut_duration(&instance, self.soft_cut_duration);
+        B::set_preset_duration(&instance, self.preset_duration);
+        B::set_mesh_size(&instance, self.mesh_size.0, self.mesh_size.1);
+        B::set_fps(&instance, self.fps);
+        B::set_aspect_correction(&instance, self.aspect_correction);
+        B::set_easter_egg(&instance, self.easter_egg);
+        B::set_preset_locked(&instance, self.preset_locked);
+        if self.window_size != (0, 0) {
+            B::set_window_size(&instance, self.window_size.0, self.window_size.1);
+        }
+        Ok(())
+    }
+
+    /// Load settings, choosing the format from the file extension.
+    #[cfg(any(feature = "toml", feature = "json"))]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let format = SettingsFormat::from_path(path).ok_or_else(|| unsupported_format(path))?;
+        let data = fs::read_to_string(path)?;
+
+        match format {
+            #[cfg(feature = "toml")]
+            SettingsFormat::Toml => Self::from_toml(&data),
+            #[cfg(feature = "json")]
+            SettingsFormat::Json => Self::from_json(&data),
+        }
+    }
+
+    /// Save the settings, choosing the format from the file extension.
+    #[cfg(any(feature = "toml", feature = "json"))]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let format = SettingsFormat::from_path(path).ok_or_else(|| unsupported_format(path))?;
+
+        let data = match format {
+            #[cfg(feature = "toml")]
+            SettingsFormat::Toml => self.to_toml()?,
+            #[cfg(feature = "json")]
+            SettingsFormat::Json => self.to_json()?,
+        };
+
+        fs::write(path, data)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(data: &str) -> io::Result<Self> {
+        toml::from_str(data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> io::Result<String> {
+        toml::to_string_pretty(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    #[cfg(feature = "json")]
+    pub fn from_json(data: &str) -> io::Result<Self> {
+        Ok(serde_json::from_str(data)?)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> io::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// JSON has no infinity, so non-finite durations are stored as "inf"
+#[cfg(feature = "serde")]
+mod seconds {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(seconds: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if seconds.is_finite() {
+            serializer.serialize_f64(*seconds)
+        } else {
+            serializer.serialize_str(&seconds.to_string())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Number(f64),
+            Text(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Number(seconds) => Ok(seconds),
+            Repr::Text(text) => text
+                .parse()
+                .map_err(|_| D::Error::custom(format!("invalid duration: {:?}", text))),
+        }
+    }
+}
+
+#[cfg(any(feature = "toml", feature = "json"))]
+fn unsupported_format(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported settings format: {}", path.display()),
+    )
+}
@@ -0,0 +1,377 @@
+//! Preset sources
+//!
+//! A [PresetSource] lists presets and textures and reads them without going
+//! through libprojectm's file loading. Presets are handed to the instance with
+//! [load_preset_data](ProjectM::load_preset_data), and the textures they sample
+//! are made available through a [TextureProvider]. This allows playing preset
+//! packs straight from a zip archive (with the `zip` feature) instead of
+//! extracting them first.
+//!
+//! ```no_run
+//! # #[cfg(feature = "zip")] {
+//! use projectm::core::ProjectM;
+//! use projectm::source::{SourcePlaylist, ZipSource};
+//!
+//! let projectm = ProjectM::create();
+//! let source = ZipSource::open("presets-cream-of-the-crop.zip").unwrap();
+//! let mut playlist = SourcePlaylist::new(source).unwrap();
+//! playlist.play_next(&projectm, true).unwrap();
+//! # }
+//! ```
+
+use std::fs;
+use std::io;
+#[cfg(feature = "zip")]
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::core::{Backend, Libprojectm, ProjectM};
+use crate::index::is_preset_file;
+use crate::preset::Preset;
+use crate::textures::{ImageFormat, TextureProvider};
+
+/// A collection of presets and textures, addressed by `/` separated names
+/// relative to the root of the source.
+pub trait PresetSource {
+    /// Names of the presets, sorted.
+    fn presets(&self) -> Vec<String>;
+
+    /// Read a preset. Invalid UTF-8 is replaced, many older presets are Latin-1.
+    fn read_preset(&mut self, name: &str) -> io::Result<String>;
+
+    /// Names of the image files, sorted.
+    fn textures(&self) -> Vec<String>;
+
+    /// Read the encoded data of an image file.
+    fn read_texture(&mut self, name: &str) -> io::Result<Vec<u8>>;
+}
+
+/// Presets and textures below a directory on disk.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    root: PathBuf,
+    presets: Vec<String>,
+    textures: Vec<String>,
+}
+
+impl DirectorySource {
+    /// Scan a directory recursively.
+    pub fn open<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let mut presets = Vec::new();
+        let mut textures = Vec::new();
+        collect_files(&root, "", &mut presets, &mut textures)?;
+        presets.sort();
+        textures.sort();
+
+        Ok(DirectorySource {
+            root,
+            presets,
+            textures,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Only names found by the scan are read, so `..` can't escape the root
+    fn path(&self, name: &str, names: &[String]) -> io::Result<PathBuf> {
+        if names
+            .binary_search_by(|known| known.as_str().cmp(name))
+            .is_err()
+        {
+            return Err(not_found(name));
+        }
+        Ok(self.root.join(name))
+    }
+}
+
+impl PresetSource for DirectorySource {
+    fn presets(&self) -> Vec<String> {
+        self.presets.clone()
+    }
+
+    fn read_preset(&mut self, name: &str) -> io::Result<String> {
+        let data = fs::read(self.path(name, &self.presets)?)?;
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    fn textures(&self) -> Vec<String> {
+        self.textures.clone()
+    }
+
+    fn read_texture(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(name, &self.textures)?)
+    }
+}
+
+fn collect_files(
+    directory: &Path,
+    prefix: &str,
+    presets: &mut Vec<String>,
+    textures: &mut Vec<String>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        // Names that aren't valid UTF-8 can't be addressed
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let name = format!("{}{}", prefix, file_name);
+
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", name), presets, textures)?;
+        } else if is_preset_file(Path::new(&name)) {
+            presets.push(name);
+        } else if image_format(&name).is_some() {
+            textures.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// Presets and textures inside a zip archive, read with a pure Rust decoder.
+#[cfg(feature = "zip")]
+pub struct ZipSource<R: Read + Seek> {
+    archive: zip::ZipArchive<R>,
+    presets: Vec<String>,
+    textures: Vec<String>,
+}
+
+#[cfg(feature = "zip")]
+impl ZipSource<io::BufReader<fs::File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        Self::new(io::BufReader::new(file))
+    }
+}
+
+#[cfg(feature = "zip")]
+impl<R: Read + Seek> ZipSource<R> {
+    /// Read the directory of an archive, e.g. one held in an `io::Cursor`.
+    pub fn new(reader: R) -> io::Result<Self> {
+        let archive = zip::ZipArchive::new(reader)?;
+        let mut presets = Vec::new();
+        let mut textures = Vec::new();
+
+        for name in archive.file_names() {
+            // Skip directories and the resource forks added by macOS
+            if name.ends_with('/') || name.starts_with("__MACOSX/") {
+                continue;
+            }
+            if is_preset_file(Path::new(name)) {
+                presets.push(name.to_string());
+            } else if image_format(name).is_some() {
+                textures.push(name.to_string());
+            }
+        }
+        presets.sort();
+        textures.sort();
+
+        Ok(ZipSource {
+            archive,
+            presets,
+            textures,
+        })
+    }
+
+    fn read(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let mut file = self.archive.by_name(name).map_err(|err| match err {
+            zip::result::ZipError::FileNotFound => not_found(name),
+            err => err.into(),
+        })?;
+        // Don't trust the sizes in the archive, a bad one could allocate or read without bound
+        let size = file.size();
+        let mut data = Vec::new();
+        file.by_ref().take(size).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(feature = "zip")]
+impl<R: Read + Seek> PresetSource for ZipSource<R> {
+    fn presets(&self) -> Vec<String> {
+        self.presets.clone()
+    }
+
+    fn read_preset(&mut self, name: &str) -> io::Result<String> {
+        let data = self.read(name)?;
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    fn textures(&self) -> Vec<String> {
+        self.textures.clone()
+    }
+
+    fn read_texture(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        self.read(name)
+    }
+}
+
+/// Plays the presets of a [PresetSource] in order, loading the textures each
+/// preset samples into a [TextureProvider] before the preset is loaded.
+///
+/// The texture directory is removed from the texture search paths of the
+/// instance again when the playlist is dropped.
+pub struct SourcePlaylist<S: PresetSource, B: Backend = Libprojectm> {
+    source: S,
+    presets: Vec<String>,
+    position: Option<usize>,
+    textures: TextureProvider,
+    // The instance the texture directory was added to
+    attached: Option<ProjectM<B>>,
+}
+
+impl<S: PresetSource, B: Backend> SourcePlaylist<S, B> {
+    /// Playlist with a temporary texture cache.
+    pub fn new(source: S) -> io::Result<Self> {
+        Ok(Self::with_textures(source, TextureProvider::new()?))
+    }
+
+    /// Playlist that stores textures in the given provider.
+    pub fn with_textures(source: S, textures: TextureProvider) -> Self {
+        SourcePlaylist {
+            presets: source.presets(),
+            source,
+            position: None,
+            textures,
+            attached: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.presets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.presets.is_empty()
+    }
+
+    pub fn presets(&self) -> &[String] {
+        &self.presets
+    }
+
+    /// Index of the preset played last.
+    pub fn position(&self) -> Option<usize> {
+        self.position
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn textures(&self) -> &TextureProvider {
+        &self.textures
+    }
+
+    /// Load the preset at `index` into an instance.
+    pub fn play(
+        &mut self,
+        projectm: &ProjectM<B>,
+        index: usize,
+        smooth_transition: bool,
+    ) -> io::Result<()> {
+        let name = self
+            .presets
+            .get(index)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no preset at index {}", index),
+                )
+            })?
+            .clone();
+
+        let data = self.source.read_preset(&name)?;
+        let referenced = Preset::parse(&data).metadata().textures;
+        if !referenced.is_empty() {
+            self.load_textures(&referenced)?;
+            self.attach(projectm)?;
+        }
+
+        projectm
+            .load_preset_data(&data, smooth_transition)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        self.position = Some(index);
+        Ok(())
+    }
+
+    /// Play the next preset, wrapping around at the end.
+    pub fn play_next(&mut self, projectm: &ProjectM<B>, smooth_transition: bool) -> io::Result<()> {
+        let index = match self.position {
+            Some(position) if position + 1 < self.len() => position + 1,
+            _ => 0,
+        };
+        self.play(projectm, index, smooth_transition)
+    }
+
+    /// Play the previous preset, wrapping around at the start.
+    pub fn play_previous(
+        &mut self,
+        projectm: &ProjectM<B>,
+        smooth_transition: bool,
+    ) -> io::Result<()> {
+        let index = match self.position {
+            Some(position) if position > 0 => position - 1,
+            _ => self.len().saturating_sub(1),
+        };
+        self.play(projectm, index, smooth_transition)
+    }
+
+    // Move the texture directory over when a different instance is used
+    fn attach(&mut self, projectm: &ProjectM<B>) -> io::Result<()> {
+        if let Some(attached) = &self.attached {
+            if attached.is_same_instance(projectm) {
+                return Ok(());
+            }
+            self.textures.detach(attached);
+        }
+
+        self.textures
+            .attach(projectm)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        self.attached = Some(projectm.clone());
+        Ok(())
+    }
+
+    // Preset texture names are lower case and match image files by stem anywhere in the source
+    fn load_textures(&mut self, referenced: &[String]) -> io::Result<()> {
+        for file in self.source.textures() {
+            let (stem, format) = match image_format(&file) {
+                Some(found) => found,
+                None => continue,
+            };
+            let stem = stem.to_ascii_lowercase();
+            if !referenced.contains(&stem) || self.textures.contains(&stem) {
+                continue;
+            }
+
+            let data = self.source.read_texture(&file)?;
+            self.textures.add_encoded_as(&stem, format, &data)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: PresetSource, B: Backend> Drop for SourcePlaylist<S, B> {
+    fn drop(&mut self) {
+        if let Some(attached) = self.attached.take() {
+            self.textures.detach(&attached);
+        }
+    }
+}
+
+/// The file stem and image format of a name like `textures/clouds.jpg`.
+fn image_format(name: &str) -> Option<(&str, ImageFormat)> {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    ImageFormat::from_extension(extension).map(|format| (stem, format))
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found in source", name),
+    )
+}
@@ -0,0 +1,265 @@
+//! In-memory textures
+//!
+//! libprojectm only loads preset textures from its texture search paths.
+//! A [TextureProvider] makes textures held in memory available to presets by
+//! writing them to a cache directory that is added to the search paths of an
+//! instance. Decoded RGBA buffers are stored as uncompressed TGA, encoded
+//! PNG/JPEG data is stored as-is.
+//!
+//! ```no_run
+//! use projectm::core::ProjectM;
+//! use projectm::textures::TextureProvider;
+//!
+//! let projectm = ProjectM::create();
+//! let mut textures = TextureProvider::new().unwrap();
+//! let clouds = std::fs::read("bundle/clouds.png").unwrap();
+//! textures.add_encoded("clouds", &clouds).unwrap();
+//! textures.attach(&projectm).unwrap();
+//! ```
+//!
+//! Presets reference the texture by its name, e.g. `sampler_clouds`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::core::{Backend, ProjectM};
+use crate::parameters::ParameterError;
+
+/// Encoded image formats that libprojectm can load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Tga,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// Guess the format from the leading bytes. TGA has no signature and is never detected.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageFormat::Jpeg)
+        } else if data.starts_with(b"BM") {
+            Some(ImageFormat::Bmp)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Tga => "tga",
+            ImageFormat::Bmp => "bmp",
+        }
+    }
+
+    /// The format for a file extension, e.g. `"jpeg"`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "tga" => Some(ImageFormat::Tga),
+            "bmp" => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
+}
+
+/// Textures registered by name, backed by files in a cache directory.
+pub struct TextureProvider {
+    directory: PathBuf,
+    // Remove the directory on drop if we created it
+    owned: bool,
+    textures: BTreeMap<String, PathBuf>,
+}
+
+impl TextureProvider {
+    /// Use a new, empty directory below the system's temporary directory.
+    /// It is removed when the provider is dropped.
+    pub fn new() -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let directory = std::env::temp_dir().join(format!(
+            "projectm-textures-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if directory.exists() {
+            fs::remove_dir_all(&directory)?;
+        }
+        fs::create_dir_all(&directory)?;
+
+        Ok(TextureProvider {
+            directory,
+            owned: true,
+            textures: BTreeMap::new(),
+        })
+    }
+
+    /// Use the given directory, e.g. a persistent cache. It is created if
+    /// needed and left in place on drop.
+    pub fn with_directory<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+
+        Ok(TextureProvider {
+            directory,
+            owned: false,
+            textures: BTreeMap::new(),
+        })
+    }
+
+    /// The directory to add to the texture search paths.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Register a decoded image with 4 bytes (R, G, B, A) per pixel, rows top to bottom.
+    pub fn add_rgba(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> io::Result<()> {
+        let data = encode_tga(width, height, pixels)?;
+        self.insert(name, ImageFormat::Tga, &data)
+    }
+
+    /// Register an encoded PNG, JPEG or BMP image; the format is detected from the data.
+    pub fn add_encoded(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let format = ImageFormat::detect(data)
+            .ok_or_else(|| invalid_input(format!("unknown image format for texture '{}'", name)))?;
+        self.insert(name, format, data)
+    }
+
+    /// Register encoded image data of a known format, e.g. one taken from the file extension.
+    pub fn add_encoded_as(
+        &mut self,
+        name: &str,
+        format: ImageFormat,
+        data: &[u8],
+    ) -> io::Result<()> {
+        self.insert(name, format, data)
+    }
+
+    /// Forget a texture and delete its file. Returns false if it wasn't registered.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.textures.remove(texture_name(name)) {
+            Some(path) => {
+                let _ = fs::remove_file(path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.textures.contains_key(texture_name(name))
+    }
+
+    /// Names of the registered textures, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.textures.keys().map(String::as_str)
+    }
+
+    /// The cache file of a texture.
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.textures.get(texture_name(name)).map(PathBuf::as_path)
+    }
+
+    /// Add the cache directory to the texture search paths of an instance.
+    /// Textures replaced after a preset used them are only picked up after
+    /// [reset_textures](ProjectM::reset_textures).
+    pub fn attach<B: Backend>(&self, projectm: &ProjectM<B>) -> Result<(), ParameterError> {
+        projectm.add_texture_search_path(&self.directory)?;
+        Ok(())
+    }
+
+    /// Remove the cache directory from the texture search paths of an instance.
+    pub fn detach<B: Backend>(&self, projectm: &ProjectM<B>) {
+        projectm.remove_texture_search_path(&self.directory);
+    }
+
+    fn insert(&mut self, name: &str, format: ImageFormat, data: &[u8]) -> io::Result<()> {
+        let name = texture_name(name);
+        check_name(name)?;
+
+        // A texture may change format, don't leave the old file to shadow the new one
+        self.remove(name);
+
+        let path = self
+            .directory
+            .join(format!("{}.{}", name, format.extension()));
+        fs::write(&path, data)?;
+        self.textures.insert(name.to_string(), path);
+        Ok(())
+    }
+}
+
+impl Drop for TextureProvider {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = fs::remove_dir_all(&self.directory);
+        }
+    }
+}
+
+// Presets refer to textures without extension, accept "clouds.png" as "clouds"
+fn texture_name(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if ImageFormat::from_extension(extension).is_some() => stem,
+        _ => name,
+    }
+}
+
+fn check_name(name: &str) -> io::Result<()> {
+    if name.is_empty() || name.contains(['/', '\\', '\0']) || name == "." || name == ".." {
+        return Err(invalid_input(format!("invalid texture name '{}'", name)));
+    }
+    Ok(())
+}
+
+/// Encode RGBA pixels as an uncompressed 32 bit TGA image.
+pub fn encode_tga(width: u32, height: u32, pixels: &[u8]) -> io::Result<Vec<u8>> {
+    if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(invalid_input(format!(
+            "texture size {}x{} is not supported",
+            width, height
+        )));
+    }
+    if pixels.len() != width as usize * height as usize * 4 {
+        return Err(invalid_input(format!(
+            "expected {} bytes of RGBA data for {}x{}, got {}",
+            width as usize * height as usize * 4,
+            width,
+            height,
+            pixels.len()
+        )));
+    }
+
+    let mut tga = Vec::with_capacity(18 + pixels.len());
+    // No image ID, no color map, uncompressed true color
+    tga.extend_from_slice(&[0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    tga.extend_from_slice(&(width as u16).to_le_bytes());
+    tga.extend_from_slice(&(height as u16).to_le_bytes());
+    // 32 bits per pixel, 8 alpha bits, top-left origin
+    tga.extend_from_slice(&[32, 0x28]);
+
+    for pixel in pixels.chunks_exact(4) {
+        tga.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+    }
+
+    Ok(tga)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
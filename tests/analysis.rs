@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod analysis {
+    use projectm::audio::analysis::*;
+    use projectm::core::MONO;
+
+    // Decaying 60 Hz kicks at the given tempo, mono at 44.1 kHz
+    fn kicks(bpm: f32, seconds: f32) -> Vec<f32> {
+        let sample_rate = 44100.0;
+        let period = (sample_rate * 60.0 / bpm) as usize;
+        (0..(sample_rate * seconds) as usize)
+            .map(|i| {
+                let t = (i % period) as f32 / sample_rate;
+                (2.0 * std::f32::consts::PI * 60.0 * t).sin() * (-t * 30.0).exp()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn beats_and_tempo() {
+        let mut analyzer = Analyzer::new(44100);
+        assert_eq!(analyzer.frame_length(), 735);
+
+        let mut frames = 0;
+        let mut beats = 0;
+        analyzer.process(&kicks(120.0, 10.0), MONO, |frame| {
+            frames += 1;
+            if frame.beat {
+                beats += 1;
+                assert!(frame.bass > frame.treb);
+            }
+        });
+        assert_eq!(frames, 600);
+        assert_eq!(beats, 20);
+
+        let tempo = analyzer.tempo().unwrap();
+        assert!((tempo - 120.0).abs() < 1.0, "tempo {}", tempo);
+
+        assert!((analyzer.latest().unwrap().time - 10.0).abs() < 0.001);
+
+        analyzer.set_beat_sensitivity(0.0);
+        analyzer.process(&kicks(120.0, 2.0), MONO, |frame| assert!(!frame.beat));
+    }
+
+    #[test]
+    fn frame_rate() {
+        let mut analyzer = Analyzer::new(44100);
+        analyzer.process(&kicks(120.0, 1.0), MONO, |_| {});
+
+        analyzer.set_frame_rate(30);
+        assert_eq!(analyzer.frame_length(), 1470);
+
+        let mut frames = 0;
+        analyzer.process(&kicks(120.0, 1.0), MONO, |_| frames += 1);
+        assert_eq!(frames, 30);
+        assert!((analyzer.latest().unwrap().time - 2.0).abs() < 0.001);
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn beat_events() {
+        use projectm::core::ProjectM;
+        use std::sync::{Arc, Mutex};
+
+        let projectm = ProjectM::create();
+        let beats = Arc::new(Mutex::new(Vec::new()));
+        let beats_clone = beats.clone();
+        projectm.set_beat_event_callback(move |frame| beats_clone.lock().unwrap().push(frame.time));
+
+        // not analyzed until enabled
+        let max_samples = ProjectM::pcm_get_max_samples() as usize;
+        for chunk in kicks(120.0, 1.0).chunks(max_samples) {
+            projectm.pcm_add_float(chunk, MONO);
+        }
+        assert_eq!(projectm.get_audio_frame(), None);
+
+        projectm.enable_audio_analysis(44100);
+        let samples: Vec<i16> = kicks(120.0, 2.0)
+            .iter()
+            .map(|sample| (sample * 32767.0) as i16)
+            .collect();
+        for chunk in samples.chunks(max_samples) {
+            projectm.pcm_add_int16(chunk, MONO);
+        }
+        assert_eq!(beats.lock().unwrap().len(), 4);
+        assert!(projectm.get_audio_frame().is_some());
+
+        // the instance's beat sensitivity applies
+        projectm.set_beat_sensitivity(0.0).unwrap();
+        for chunk in samples.chunks(max_samples) {
+            projectm.pcm_add_int16(chunk, MONO);
+        }
+        assert_eq!(beats.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn beat_callback_uses_instance() {
+        use projectm::core::ProjectM;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let projectm = ProjectM::create();
+        projectm.set_fps(30).unwrap();
+        projectm.enable_audio_analysis(44100);
+
+        let beats = Arc::new(AtomicUsize::new(0));
+        let beats_clone = beats.clone();
+        let instance = projectm.clone();
+        projectm.set_beat_event_callback(move |_| {
+            beats_clone.fetch_add(1, Ordering::Relaxed);
+            instance.pcm_add_float(&[0.0; 64], MONO);
+        });
+
+        let max_samples = ProjectM::pcm_get_max_samples() as usize;
+        for chunk in kicks(120.0, 2.0).chunks(max_samples) {
+            projectm.pcm_add_float(chunk, MONO);
+        }
+        assert_eq!(beats.load(Ordering::Relaxed), 4);
+
+        // analyzed at the instance's fps
+        let time = projectm.get_audio_frame().unwrap().time;
+        assert_eq!((time * 44100.0).round() as u64 % 1470, 0);
+    }
+}
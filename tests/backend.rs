@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod backend {
+    use projectm::audio::capture::CaptureBuffer;
+    use projectm::audio::pipe::{PcmFormat, PcmSource, SampleFormat};
+    use projectm::core::*;
+    use projectm::playlist::Playlist;
+    use std::cell::{Cell, RefCell};
+    use std::path::{Path, PathBuf};
+
+    // A backend that only keeps the beat sensitivity and counts frames
+    #[derive(Default)]
+    struct State {
+        beat_sensitivity: Cell<f32>,
+        frames: Cell<u32>,
+        presets: RefCell<Vec<String>>,
+        pcm_lengths: RefCell<Vec<usize>>,
+    }
+
+    struct Counter;
+
+    impl Backend for Counter {
+        type Handle = State;
+
+        fn create() -> State {
+            State::default()
+        }
+        fn destroy(_: &State) {}
+        fn load_preset_file(instance: &State, filename: &str, _: bool) {
+            instance.presets.borrow_mut().push(filename.to_string());
+        }
+        fn load_preset_data(_: &State, _: &str, _: bool) {}
+        fn reset_textures(_: &State) {}
+        fn get_version_components() -> (i32, i32, i32) {
+            (0, 0, 0)
+        }
+        fn get_version_string() -> String {
+            String::new()
+        }
+        fn get_vcs_version_string() -> String {
+            String::new()
+        }
+        fn set_preset_switch_requested_event_callback<F: FnMut(bool) + 'static>(_: &State, _: F) {}
+        fn set_preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
+            _: &State,
+            _: F,
+        ) {
+        }
+        fn set_texture_search_paths(_: &State, _: &[PathBuf]) {}
+        fn get_beat_sensitivity(instance: &State) -> f32 {
+            instance.beat_sensitivity.get()
+        }
+        fn set_beat_sensitivity(instance: &State, sensitivity: f32) {
+            instance.beat_sensitivity.set(sensitivity);
+        }
+        fn get_hard_cut_duration(_: &State) -> f64 {
+            0.0
+        }
+        fn set_hard_cut_duration(_: &State, _: f64) {}
+        fn get_hard_cut_enabled(_: &State) -> bool {
+            false
+        }
+        fn set_hard_cut_enabled(_: &State, _: bool) {}
+        fn get_hard_cut_sensitivity(_: &State) -> f32 {
+            0.0
+        }
+        fn set_hard_cut_sensitivity(_: &State, _: f32) {}
+        fn get_soft_cut_duration(_: &State) -> f64 {
+            0.0
+        }
+        fn set_soft_cut_duration(_: &State, _: f64) {}
+        fn get_preset_duration(_: &State) -> f64 {
+            0.0
+        }
+        fn set_preset_duration(_: &State, _: f64) {}
+        fn get_mesh_size(_: &State) -> (usize, usize) {
+            (0, 0)
+        }
+        fn set_mesh_size(_: &State, _: usize, _: usize) {}
+        fn get_fps(_: &State) -> u32 {
+            0
+        }
+        fn set_fps(_: &State, _: u32) {}
+        fn get_aspect_correction(_: &State) -> bool {
+            false
+        }
+        fn set_aspect_correction(_: &State, _: bool) {}
+        fn get_easter_egg(_: &State) -> f32 {
+            0.0
+        }
+        fn set_easter_egg(_: &State, _: f32) {}
+        fn get_preset_locked(_: &State) -> bool {
+            false
+        }
+        fn set_preset_locked(_: &State, _: bool) {}
+        fn get_window_size(_: &State) -> (usize, usize) {
+            (0, 0)
+        }
+        fn set_window_size(_: &State, _: usize, _: usize) {}
+        fn render_frame(instance: &State) {
+            instance.frames.set(instance.frames.get() + 1);
+        }
+        fn touch(_: &State, _: f32, _: f32, _: i32, _: ProjectMTouchType) {}
+        fn touch_drag(_: &State, _: f32, _: f32, _: i32) {}
+        fn touch_destroy(_: &State, _: f32, _: f32) {}
+        fn touch_destroy_all(_: &State) {}
+        fn pcm_get_max_samples() -> u32 {
+            0
+        }
+        fn pcm_add_float(instance: &State, samples: &[f32], _: ProjectMChannels) {
+            instance.pcm_lengths.borrow_mut().push(samples.len());
+        }
+        fn pcm_add_int16(instance: &State, samples: &[i16], _: ProjectMChannels) {
+            instance.pcm_lengths.borrow_mut().push(samples.len());
+        }
+        fn pcm_add_uint8(_: &State, _: &[u8], _: ProjectMChannels) {}
+        fn write_debug_image_on_next_frame(_: &State, _: Option<&String>) {}
+    }
+
+    fn is_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn custom_backend() {
+        // Only backends that promise it, like libprojectm, can be shared between threads
+        is_send_sync::<ProjectM>();
+        is_send_sync::<Playlist>();
+
+        let projectm = ProjectM::<Counter>::with_backend();
+        projectm.set_beat_sensitivity(2.5).unwrap();
+        projectm.load_preset_file("a.milk", true).unwrap();
+        projectm.render_frame();
+        projectm.render_frame();
+
+        assert_eq!(projectm.get_beat_sensitivity(), 2.5);
+
+        let state = projectm.get_instance();
+        assert_eq!(state.frames.get(), 2);
+        assert_eq!(*state.presets.borrow(), vec!["a.milk".to_string()]);
+    }
+
+    // Audio is split by the backend's own limit, here less than a frame
+    #[test]
+    fn audio_on_backend() {
+        let projectm = ProjectM::<Counter>::with_backend();
+
+        let capture = CaptureBuffer::new(44100, STEREO);
+        capture.push_float(&[0.0; 8]);
+        assert_eq!(capture.feed(&projectm), 8);
+
+        let format = PcmFormat::new(SampleFormat::S16Le, STEREO, 44100);
+        let mut source = PcmSource::new(&[0u8; 12][..], format).unwrap();
+        assert_eq!(source.feed_frames(&projectm, 3).unwrap(), 3);
+
+        let state = projectm.get_instance();
+        assert_eq!(*state.pcm_lengths.borrow(), [2, 2, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn playlist_on_backend() {
+        let projectm = ProjectM::<Counter>::with_backend();
+        let mut playlist = Playlist::create(&projectm);
+        playlist.add_preset(Path::new("a.milk"), false).unwrap();
+        playlist.add_preset(Path::new("b.milk"), false).unwrap();
+        playlist.play(1);
+        playlist.play_next();
+
+        let state = projectm.get_instance();
+        assert_eq!(*state.presets.borrow(), ["b.milk", "a.milk"]);
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod builder {
+    use projectm::core::ProjectM;
+    use projectm::parameters::{Parameter, ParameterError};
+
+    #[test]
+    fn rejects_invalid_values() {
+        let result = ProjectM::builder()
+            .window_size(800, 600)
+            .mesh_size(0, 32)
+            .build();
+        assert_eq!(
+            result.err(),
+            Some(ParameterError::OutOfRange {
+                parameter: Parameter::MeshWidth,
+                value: 0.0,
+            })
+        );
+
+        let error = ProjectM::builder()
+            .soft_cut_duration(-1.0)
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "soft cut duration must be at least 0, got -1"
+        );
+
+        assert!(ProjectM::builder()
+            .preset_duration(f64::NAN)
+            .validate()
+            .is_err());
+        assert!(ProjectM::builder()
+            .preset("bad\0.milk", true)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn configures_instance() {
+        use projectm::mock::{self, Call};
+
+        let projectm = ProjectM::builder()
+            .window_size(1280, 720)
+            .mesh_size(64, 48)
+            .fps(30)
+            .preset_duration(15.0)
+            .preset("presets/a.milk", false)
+            .build()
+            .unwrap();
+
+        assert_eq!(projectm.get_window_size(), (1280, 720));
+        assert_eq!(projectm.get_mesh_size(), (64, 48));
+        assert_eq!(projectm.get_fps(), 30);
+        assert_eq!(projectm.get_preset_duration(), 15.0);
+        assert_eq!(
+            mock::calls(&projectm).last(),
+            Some(&Call::LoadPresetFile {
+                filename: "presets/a.milk".to_string(),
+                smooth_transition: false,
+            })
+        );
+    }
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "mock")]
+
+#[cfg(test)]
+mod capture {
+    use projectm::audio::capture::*;
+    use projectm::core::ProjectM;
+    use projectm::mock::{self, Call};
+
+    #[test]
+    fn loopback() {
+        let projectm = ProjectM::create();
+        let mut capture = Capture::open(Input::Loopback {
+            sample_rate: 44100,
+            channels: 4,
+        })
+        .unwrap();
+        assert_eq!(capture.buffer().channels(), 2);
+
+        // the capture thread only touches the buffer
+        let buffer = capture.buffer().clone();
+        std::thread::spawn(move || {
+            buffer.push_float(&[0.5, -0.5, 0.9, 0.9, 0.25, -0.25, 0.9, 0.9]);
+            buffer.push_int16(&vec![16384; 4 * 3000]);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(capture.buffer().len(), 4 + 2 * 3000);
+
+        mock::clear_calls(&projectm);
+        assert_eq!(capture.feed(&projectm), 4 + 2 * 3000);
+        assert!(capture.buffer().is_empty());
+        assert!(capture.is_connected());
+
+        // int16 audio is split into pieces of at most pcm_get_max_samples
+        assert_eq!(
+            mock::calls(&projectm),
+            vec![
+                Call::PcmAdd {
+                    samples: 2,
+                    channels: 2
+                },
+                Call::PcmAdd {
+                    samples: 1024,
+                    channels: 2
+                },
+                Call::PcmAdd {
+                    samples: 1024,
+                    channels: 2
+                },
+                Call::PcmAdd {
+                    samples: 952,
+                    channels: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn null() {
+        let projectm = ProjectM::create();
+        let mut capture = Capture::open(Input::Null {
+            sample_rate: 44100,
+            channels: 1,
+        })
+        .unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(capture.feed(&projectm) > 0);
+        assert!(projectm
+            .get_waveform()
+            .left
+            .iter()
+            .all(|sample| *sample == 0.0));
+    }
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "cli")]
+
+#[cfg(test)]
+mod cli {
+    use std::process::{Command, Output};
+
+    fn run(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_projectm-cli"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    fn help() {
+        let output = run(&["presets", "--help"]);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage: projectm-cli"));
+    }
+
+    // Options are checked before a context is created or anything is read
+    #[test]
+    fn invalid_options() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--fps", "30"], "no presets given"),
+            (&["presets", "--bogus"], "unknown option '--bogus'"),
+            (&["presets", "--fps"], "--fps needs a value"),
+            (
+                &["presets", "--fps=0", "-d", "1"],
+                "--fps must be at least 1",
+            ),
+            (&["presets", "-r", "fast"], "invalid value 'fast' for -r"),
+            (
+                &["presets", "--size", "1280", "-d", "1"],
+                "invalid size '1280' for --size, use WxH",
+            ),
+            (
+                &["presets", "--raw", "s24le", "-a", "-"],
+                "unknown sample format 's24le'",
+            ),
+            (
+                &["presets", "--preset-duration=-5", "-d", "1"],
+                "--preset-duration must be positive",
+            ),
+            (&["presets"], "--duration is needed without --audio"),
+        ];
+
+        for (args, message) in cases {
+            let output = run(args);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert_eq!(output.status.code(), Some(2), "{:?}", args);
+            assert!(
+                stderr.starts_with(&format!("projectm-cli: {}", message)),
+                "{:?}: {}",
+                args,
+                stderr
+            );
+        }
+    }
+}
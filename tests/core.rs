@@ -1,9 +1,12 @@
 #[cfg(test)]
 mod core {
     use projectm::core::*;
+    #[cfg(not(feature = "mock"))]
     use std::process::Command;
+    #[cfg(not(feature = "mock"))]
     use std::str;
 
+    #[cfg(not(feature = "mock"))]
     fn get_git_hash_by_command() -> Option<String> {
         let output = Command::new("git")
             .arg("rev-parse")
@@ -20,7 +23,9 @@ mod core {
         }
     }
 
+    // The mock backend reports a fixed version without a VCS hash
     #[test]
+    #[cfg(not(feature = "mock"))]
     fn test_get_versions() {
         let version_tuple = ProjectM::get_version_components();
         assert_eq!(version_tuple, (4, 0, 0));
@@ -32,8 +37,12 @@ mod core {
         assert_eq!(vcs_version_string, get_git_hash_by_command().unwrap());
     }
 
-    // #[test]
-    // fn test_create() {
-    //     let projectm = ProjectM::create();
-    // }
+    // Creating an instance needs an OpenGL context, unless it's the mock
+    #[test]
+    #[cfg(feature = "mock")]
+    fn test_create() {
+        let projectm = ProjectM::create();
+        projectm.render_frame();
+        assert_eq!(projectm::mock::frames_rendered(&projectm), 1);
+    }
 }
@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod delay {
+    use projectm::audio::delay::DelayLine;
+    use projectm::core::{MONO, STEREO};
+    use std::time::Duration;
+
+    #[test]
+    fn release_by_latency() {
+        let mut delay = DelayLine::new(Duration::from_millis(200));
+        let mut released = Vec::new();
+        delay.push(&[0.1; 4], STEREO);
+        delay.advance(Duration::from_millis(100), |samples, channels| {
+            released.push((samples.to_vec(), channels))
+        });
+        delay.push(&[0.2; 2], MONO);
+        assert_eq!(delay.len(), 6);
+
+        delay.advance(Duration::from_millis(99), |samples, channels| {
+            released.push((samples.to_vec(), channels))
+        });
+        assert!(released.is_empty());
+
+        delay.advance(Duration::from_millis(51), |samples, channels| {
+            released.push((samples.to_vec(), channels))
+        });
+        assert_eq!(released, vec![(vec![0.1; 4], STEREO)]);
+        assert_eq!(delay.time(), Duration::from_millis(250));
+
+        // a shorter latency applies to the audio already held
+        delay.set_latency(Duration::from_millis(50));
+        delay.advance(Duration::from_millis(10), |samples, channels| {
+            released.push((samples.to_vec(), channels))
+        });
+        assert_eq!(released.len(), 2);
+        assert!(delay.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn latency_on_instance() {
+        use projectm::core::ProjectM;
+
+        let projectm = ProjectM::create();
+        assert_eq!(projectm.get_audio_latency(), 0);
+
+        projectm.set_audio_latency(60_000);
+        assert_eq!(projectm.get_audio_latency(), 60_000);
+        projectm.pcm_add_int16(&[i16::MAX / 2; 64], MONO);
+        projectm.render_frame();
+        assert!(projectm.get_waveform().is_empty());
+
+        // turning the latency off passes the held audio on
+        projectm.set_audio_latency(0);
+        assert_eq!(projectm.get_waveform().len(), 64);
+
+        // two frames at 60 fps are due after 20 ms, however fast they are rendered
+        projectm.set_audio_latency(20);
+        projectm.pcm_add_float(&[0.5; 32], MONO);
+        projectm.render_frame();
+        assert_eq!(projectm.get_waveform().len(), 64);
+        projectm.render_frame();
+        assert_eq!(projectm.get_waveform().len(), 96);
+    }
+}
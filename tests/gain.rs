@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod gain {
+    use projectm::audio::gain::*;
+    use projectm::audio::signals::{Signal, Sine};
+    use projectm::core::MONO;
+
+    fn sine(db: f32, seconds: f64) -> Vec<f32> {
+        Sine::new(44100, 440.0, db_to_linear(db))
+            .chunks_of(MONO, seconds, usize::MAX)
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn automatic() {
+        let mut stage = GainStage::new(
+            GainMode::Automatic {
+                target_db: -12.0,
+                attack: 0.01,
+                release: 1.0,
+                max_gain_db: 30.0,
+            },
+            44100,
+        );
+
+        // a quiet station is brought up to the target
+        let mut quiet = sine(-32.0, 5.0);
+        stage.process(&mut quiet, MONO);
+        let meter = stage.meter();
+        assert!((meter.input_peak_db + 32.0).abs() < 0.1);
+        assert!((meter.gain_db - 20.0).abs() < 1.0, "{:?}", meter);
+        let settled = &quiet[quiet.len() - 44100..];
+        let peak = settled.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((linear_to_db(peak) + 12.0).abs() < 1.0);
+
+        // a loud one is brought down within a few attack times
+        let mut loud = sine(-2.0, 0.1);
+        stage.process(&mut loud, MONO);
+        let settled = &loud[loud.len() / 2..];
+        let peak = settled.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((linear_to_db(peak) + 12.0).abs() < 2.0);
+        assert!(stage.gain_db() < -8.0);
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn fixed_on_instance() {
+        use projectm::core::{ProjectM, STEREO};
+
+        let projectm = ProjectM::create();
+        assert_eq!(projectm.get_gain_meter(), None);
+        projectm.set_gain_stage(Some(GainStage::new(
+            GainMode::Fixed { gain_db: 6.0206 },
+            44100,
+        )));
+
+        projectm.pcm_add_int16(&[8192, -8192, 24576, 0], STEREO);
+        let waveform = projectm.get_waveform();
+        assert!((waveform.left[0] - 0.5).abs() < 1e-4);
+        assert!((waveform.right[0] + 0.5).abs() < 1e-4);
+        // clipped at full scale
+        assert_eq!(waveform.left[1], 1.0);
+
+        let meter = projectm.get_gain_meter().unwrap();
+        assert_eq!(meter.clipped, 1);
+        assert!((meter.gain_db - 6.0206).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod index {
+    use projectm::index::*;
+    use std::path::Path;
+
+    #[test]
+    fn scan_presets() {
+        let presets_dir = std::env::current_dir().unwrap().join("presets");
+
+        let mut index = PresetIndex::new();
+        let stats = index.scan(&presets_dir, true).unwrap();
+        assert_eq!(stats.added, 20);
+        assert_eq!(index.len(), 20);
+
+        let per_pixel = index.get(&presets_dir.join("110-per_pixel.milk")).unwrap();
+        assert_eq!(per_pixel.metadata.per_frame_equations, 3);
+        assert_eq!(per_pixel.metadata.per_pixel_equations, 1);
+
+        let wave = index
+            .get(&presets_dir.join("210-wave-smooth-80.milk"))
+            .unwrap();
+        assert_eq!(wave.metadata.custom_waves, 1);
+        assert_eq!(wave.metadata.per_frame_equations, 0);
+
+        // rescanning does not read anything again
+        let stats = index.scan(&presets_dir, true).unwrap();
+        assert_eq!(stats.unchanged, 20);
+
+        let index_file =
+            std::env::temp_dir().join(format!("projectm-test-index-{}.txt", std::process::id()));
+        index.save(&index_file).unwrap();
+        let loaded = PresetIndex::load(&index_file).unwrap();
+        std::fs::remove_file(&index_file).unwrap();
+        assert!(loaded.entries().eq(index.entries()));
+    }
+
+    // Paths are saved as they are, not as lossy UTF-8
+    #[test]
+    #[cfg(unix)]
+    fn save_raw_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("projectm-test-index-paths-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let preset = dir.join(OsStr::from_bytes(b"Geiss - \\x41\t\xff.milk"));
+        std::fs::write(&preset, "[preset00]\n").unwrap();
+
+        let mut index = PresetIndex::new();
+        index.scan(&dir, false).unwrap();
+        let index_file = dir.join("index.txt");
+        index.save(&index_file).unwrap();
+        let loaded = PresetIndex::load(&index_file).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.get(&preset), index.get(&preset));
+        assert!(loaded.get(&preset).is_some());
+    }
+
+    // A directory behind a preset name can't be read, like a file without permission
+    #[test]
+    #[cfg(unix)]
+    fn scan_unreadable() {
+        let dir = std::env::temp_dir().join(format!(
+            "projectm-test-index-unreadable-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("textures")).unwrap();
+        std::fs::write(dir.join("a.milk"), "[preset00]\n").unwrap();
+        std::fs::write(dir.join("b.milk"), "[preset00]\n").unwrap();
+
+        let mut index = PresetIndex::new();
+        assert_eq!(index.scan(&dir, false).unwrap().added, 2);
+        assert!(index.add_tag(&dir.join("b.milk"), "calm"));
+
+        std::fs::remove_file(dir.join("b.milk")).unwrap();
+        std::os::unix::fs::symlink(dir.join("textures"), dir.join("b.milk")).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("c.milk")).unwrap();
+
+        let stats = index.scan(&dir, false).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(stats.unchanged, 1);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.added, 0);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(&dir.join("b.milk")).unwrap().tags, ["calm"]);
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(
+            parse_file_name(Path::new("Geiss + Rovastar - Octopus.milk")),
+            (Some("Geiss + Rovastar".to_string()), "Octopus".to_string())
+        );
+        assert_eq!(
+            parse_file_name(Path::new("000-empty.milk")),
+            (None, "000-empty".to_string())
+        );
+    }
+}
@@ -0,0 +1,101 @@
+#![cfg(feature = "mock")]
+
+#[cfg(test)]
+mod mock {
+    use projectm::core::ProjectM;
+    use projectm::mock::{self, Call};
+    use std::cell::RefCell;
+    use std::panic::AssertUnwindSafe;
+    use std::rc::Rc;
+
+    #[test]
+    fn records_calls_and_parameters() {
+        let projectm = ProjectM::create();
+        assert_eq!(mock::calls(&projectm), vec![Call::Create]);
+        mock::clear_calls(&projectm);
+
+        projectm.set_beat_sensitivity(1.5).unwrap();
+        projectm.set_preset_duration(10.0).unwrap();
+        projectm.set_mesh_size(64, 48).unwrap();
+        projectm.set_window_size(800, 600).unwrap();
+        projectm
+            .load_preset_file("presets/test.milk", false)
+            .unwrap();
+        projectm.render_frame();
+
+        assert_eq!(projectm.get_beat_sensitivity(), 1.5);
+        assert_eq!(projectm.get_preset_duration(), 10.0);
+        assert_eq!(projectm.get_mesh_size(), (64, 48));
+        assert_eq!(projectm.get_window_size(), (800, 600));
+        assert_eq!(mock::frames_rendered(&projectm), 1);
+        assert_eq!(
+            mock::calls(&projectm),
+            vec![
+                Call::SetBeatSensitivity(1.5),
+                Call::SetPresetDuration(10.0),
+                Call::SetMeshSize(64, 48),
+                Call::SetWindowSize(800, 600),
+                Call::LoadPresetFile {
+                    filename: "presets/test.milk".to_string(),
+                    smooth_transition: false,
+                },
+                Call::RenderFrame,
+            ]
+        );
+
+        projectm.destroy();
+        let calls = std::panic::catch_unwind(AssertUnwindSafe(|| mock::calls(&projectm)));
+        assert!(calls.is_err());
+    }
+
+    #[test]
+    fn fires_callbacks() {
+        let projectm = ProjectM::create();
+
+        let requested = Rc::new(RefCell::new(Vec::new()));
+        let requested_clone = requested.clone();
+        projectm.set_preset_switch_requested_event_callback(move |is_hard_cut| {
+            requested_clone.borrow_mut().push(is_hard_cut);
+        });
+
+        let failed = Rc::new(RefCell::new(Vec::new()));
+        let failed_clone = failed.clone();
+        projectm.set_preset_switch_failed_event_callback(move |preset, message| {
+            failed_clone.borrow_mut().push((preset, message));
+        });
+
+        mock::fire_preset_switch_requested(&projectm, true);
+        mock::fire_preset_switch_failed(&projectm, "broken.milk", "syntax error");
+
+        assert_eq!(*requested.borrow(), vec![true]);
+        assert_eq!(
+            *failed.borrow(),
+            vec![("broken.milk".to_string(), "syntax error".to_string())]
+        );
+    }
+    // Clones take turns across threads, the thread holding the instance may use it again
+    #[test]
+    fn shared_between_threads() {
+        let projectm = ProjectM::create();
+        let threads: Vec<_> = (1..=4)
+            .map(|fps| {
+                let projectm = projectm.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        projectm.set_fps(fps).unwrap();
+                        projectm.render_frame();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(mock::frames_rendered(&projectm), 400);
+
+        let instance = projectm.get_instance();
+        projectm.set_fps(30).unwrap();
+        drop(instance);
+        assert_eq!(projectm.get_fps(), 30);
+    }
+}
@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod parameters {
+    use projectm::parameters::{Parameter, Seconds, Sensitivity};
+
+    #[test]
+    fn ranges() {
+        assert_eq!(Parameter::MeshWidth.range(), 8.0..=300.0);
+        assert!(Parameter::PresetDuration.check(f64::INFINITY).is_ok());
+        assert!(Parameter::SoftCutDuration.check(-0.5).is_err());
+        assert!(Parameter::BeatSensitivity.check(f64::NAN).is_err());
+        assert!(Parameter::Fps.check(i32::MAX as f64 + 1.0).is_err());
+        assert!(Parameter::EasterEgg.check(-1.0).is_err());
+
+        for parameter in Parameter::ALL {
+            let range = parameter.range();
+            assert!(parameter.check(*range.start()).is_ok(), "{}", parameter);
+            assert!(parameter.check(*range.end()).is_ok(), "{}", parameter);
+        }
+
+        assert_eq!(Seconds::new(2.5).unwrap().get(), 2.5);
+        assert_eq!(Seconds::new(-1.0), None);
+        assert_eq!(Sensitivity::new(f32::NAN), None);
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn setters() {
+        use projectm::core::ProjectM;
+        use projectm::parameters::ParameterError;
+
+        let projectm = ProjectM::create();
+
+        assert_eq!(
+            projectm.set_mesh_size(0, 0),
+            Err(ParameterError::OutOfRange {
+                parameter: Parameter::MeshWidth,
+                value: 0.0,
+            })
+        );
+        assert!(projectm.set_fps(0).is_err());
+        assert_eq!(
+            projectm.set_soft_cut_duration(-3.0),
+            Err(ParameterError::OutOfRange {
+                parameter: Parameter::SoftCutDuration,
+                value: -3.0,
+            })
+        );
+        assert!(projectm.set_beat_sensitivity(f32::NAN).is_err());
+
+        // rejected values don't reach the engine
+        assert_eq!(projectm.get_mesh_size(), (48, 32));
+        assert_eq!(projectm.get_soft_cut_duration(), 3.0);
+
+        projectm
+            .set_soft_cut_duration(Seconds::new(5.0).unwrap())
+            .unwrap();
+        projectm
+            .set_beat_sensitivity(Sensitivity::new(0.5).unwrap())
+            .unwrap();
+        projectm.set_fps(30).unwrap();
+        assert_eq!(projectm.get_soft_cut_duration(), 5.0);
+        assert_eq!(projectm.get_beat_sensitivity(), 0.5);
+        assert_eq!(projectm.get_fps(), 30);
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn nul_bytes() {
+        use projectm::core::ProjectM;
+        use projectm::parameters::ParameterError;
+        use projectm::playlist::Playlist;
+        use std::path::Path;
+
+        let projectm = ProjectM::create();
+        assert_eq!(
+            projectm.load_preset_file("a\0.milk", false),
+            Err(ParameterError::InvalidString("a\0.milk".to_string()))
+        );
+        assert!(projectm
+            .load_preset_data("per_frame_1=a;\0", false)
+            .is_err());
+
+        let playlist = Playlist::create(&projectm);
+        assert!(playlist.add_preset(Path::new("a\0.milk"), false).is_err());
+        assert_eq!(
+            playlist.add_presets(&["b.milk", "c\0.milk"], false),
+            Err(ParameterError::InvalidString("c\0.milk".to_string()))
+        );
+        assert!(playlist.is_empty());
+    }
+}
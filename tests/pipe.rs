@@ -0,0 +1,58 @@
+#![cfg(feature = "mock")]
+
+#[cfg(test)]
+mod pipe {
+    use projectm::audio::pipe::*;
+    use projectm::core::ProjectM;
+    use projectm::mock::{self, Call};
+    use std::io::Cursor;
+
+    #[test]
+    fn frame_pacing() {
+        let projectm = ProjectM::create();
+        mock::clear_calls(&projectm);
+
+        // 0.05 s of stereo s16le audio and half a sample frame
+        let mut data: Vec<u8> = (0..2205 * 2)
+            .flat_map(|i| (i as i16 * 4).to_le_bytes())
+            .collect();
+        data.extend_from_slice(&[1, 2]);
+
+        let format = PcmFormat::new("s16le".parse().unwrap(), 2, 44100);
+        let mut source = PcmSource::new(Cursor::new(data), format).unwrap();
+
+        // 44100 / 60 = 735 frames per video frame
+        assert!(source.feed_frame(&projectm, 60).unwrap());
+        assert!(source.feed_frame(&projectm, 60).unwrap());
+        assert!(source.feed_frame(&projectm, 60).unwrap());
+        assert!(!source.feed_frame(&projectm, 60).unwrap());
+        assert_eq!(source.frames_fed(), 2205);
+        assert!((source.position() - 0.05).abs() < 1e-9);
+
+        assert_eq!(
+            mock::calls(&projectm),
+            vec![
+                Call::PcmAdd {
+                    samples: 735,
+                    channels: 2
+                };
+                3
+            ]
+        );
+        let waveform = projectm.get_waveform();
+        assert_eq!(*waveform.right.last().unwrap(), (4409 * 4) as f32 / 32768.0);
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            "F32LE".parse::<SampleFormat>().unwrap(),
+            SampleFormat::F32Le
+        );
+        assert!("s24le".parse::<SampleFormat>().is_err());
+        assert_eq!(PcmFormat::default().bytes_per_frame(), 4);
+
+        let format = PcmFormat::new(SampleFormat::U8, 6, 44100);
+        assert!(PcmSource::new(Cursor::new(Vec::new()), format).is_err());
+    }
+}
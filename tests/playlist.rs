@@ -13,7 +13,9 @@ mod playlist {
         // get absolute path to ../presets
         let path = std::env::current_dir().unwrap();
         let presets_dir = path.join("presets");
-        playlist.add_path(presets_dir.to_str().unwrap(), true);
+        playlist
+            .add_path(presets_dir.to_str().unwrap(), true)
+            .unwrap();
         assert_eq!(playlist.len(), 20);
     }
 }
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod playlist_file {
+    use projectm::playlist_file::*;
+    use std::path::Path;
+
+    #[test]
+    fn m3u() {
+        let m3u = "#EXTM3U\n#EXTINF:45,Line\n001-line.milk\n\n/abs/other.milk\n";
+        let playlist = PlaylistFile::from_m3u(m3u, Path::new("/shows/set1"));
+
+        assert_eq!(playlist.items.len(), 2);
+        assert_eq!(
+            playlist.items[0].path,
+            Path::new("/shows/set1/001-line.milk")
+        );
+        assert_eq!(playlist.items[0].duration, Some(45.0));
+        assert_eq!(playlist.items[1].path, Path::new("/abs/other.milk"));
+        assert_eq!(playlist.items[1].duration, None);
+
+        assert_eq!(
+            playlist.to_m3u(Path::new("/shows/set1")),
+            "#EXTM3U\n#EXTINF:45,001-line\n001-line.milk\n/abs/other.milk\n"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        let dir = std::env::temp_dir().join(format!(
+            "projectm-test-playlist-file-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut item = PlaylistItem::new(dir.join("presets").join("200-wave.milk"));
+        item.duration = Some(12.5);
+        item.weight = Some(2.0);
+        item.transition = Some(Transition::Soft { duration: 3.0 });
+        item.tags = vec!["calm".to_string()];
+
+        let mut playlist = PlaylistFile::new();
+        playlist.items.push(item);
+        playlist.items.push(PlaylistItem::new("/abs/other.milk"));
+
+        let file = dir.join("set.json");
+        playlist.save(&file).unwrap();
+        let saved = std::fs::read_to_string(&file).unwrap();
+        assert!(saved.contains("\"presets/200-wave.milk\""));
+
+        let loaded = PlaylistFile::load(&file).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded, playlist);
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod preset {
+    use projectm::preset::{Preset, Severity};
+
+    const PRESET: &str = "[preset00]\r
+zoom=1.01  \r
+rot=fast\r
+zoom=1.02\r
+per_frame_1=a = sin(time;\r
+per_frame_3=b = 2;\r
+\r
+\r
+oops\r
+per_pixel_2=zoom = zoom + rad*0.1;\r
+\r
+";
+
+    #[test]
+    fn lint() {
+        let preset = Preset::parse(PRESET);
+        let found: Vec<_> = preset
+            .lint()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.code))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (3, "invalid-number"),
+                (4, "duplicate-key"),
+                (5, "unbalanced-parentheses"),
+                (6, "equation-gap"),
+                (9, "unknown-line"),
+                (10, "equation-gap"),
+            ]
+        );
+        assert!(preset
+            .lint()
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error));
+
+        let presets_dir = std::env::current_dir().unwrap().join("presets");
+        let clean = Preset::read(&presets_dir.join("110-per_pixel.milk")).unwrap();
+        assert!(clean.lint().is_empty());
+    }
+
+    #[test]
+    fn format() {
+        let formatted = Preset::parse(PRESET).format();
+
+        assert_eq!(
+            formatted.to_string(),
+            "[preset00]
+zoom=1.01
+rot=fast
+zoom=1.02
+per_frame_1=a = sin(time;
+per_frame_3=b = 2;
+
+oops
+per_pixel_2=zoom = zoom + rad*0.1;
+"
+        );
+        // closing the gaps would run code libprojectm skips
+        let gaps = formatted
+            .lint()
+            .iter()
+            .filter(|diagnostic| diagnostic.code == "equation-gap")
+            .count();
+        assert_eq!(gaps, 2);
+        let skipped = Preset::parse("per_frame_5=b;\nper_frame_2=a;\n");
+        assert_eq!(skipped.format(), skipped);
+
+        let comment = Preset::parse("per_frame_1=a;\nper_frame_3=// later\n").format();
+        assert_eq!(
+            comment.to_string(),
+            "per_frame_1=a;\nper_frame_2=// later\n"
+        );
+
+        // equations are numbered in the order libprojectm runs them in
+        let reordered =
+            Preset::parse("per_frame_4=// c\nper_frame_2=b;\nper_frame_1=a;\n").format();
+        assert_eq!(
+            reordered.to_string(),
+            "per_frame_3=// c\nper_frame_2=b;\nper_frame_1=a;\n"
+        );
+        assert_eq!(reordered.format(), reordered);
+
+        // libprojectm only runs the first of two equations with the same number
+        let duplicate = Preset::parse("per_frame_2=b;\nper_frame_1=a;\nper_frame_1=c;\n");
+        assert_eq!(duplicate.format(), duplicate);
+    }
+}
@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod record {
+    use projectm::record::*;
+    use std::io;
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn record_and_replay() {
+        use projectm::core::*;
+        use std::cell::RefCell;
+        use std::io::Write;
+        use std::rc::Rc;
+
+        // A log that stays readable after the recorder owns the writer
+        #[derive(Clone, Default)]
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        fn events(log: &Shared) -> Vec<Event> {
+            let bytes = log.0.borrow();
+            Replayer::new(&bytes[..])
+                .unwrap()
+                .map(|entry| entry.unwrap().event)
+                .collect()
+        }
+
+        let log = Shared::default();
+        let projectm = ProjectM::<Recorder>::with_backend();
+        projectm.set_fps(30).unwrap();
+        projectm.start_recording(log.clone()).unwrap();
+        projectm.load_preset_file("a.milk", false).unwrap();
+        projectm.pcm_add_int16(&[1, -2, 3, -4], STEREO);
+        projectm.pcm_add_float(&[0.25, -0.5], MONO);
+        projectm.render_frame();
+        projectm.set_window_size(640, 480).unwrap();
+        projectm.touch(0.5, 0.25, 1, TOUCH_TYPE_CIRCLE);
+        projectm.pcm_add_uint8(&[0, 128, 255], MONO);
+        projectm.render_frame();
+        projectm.stop_recording().unwrap();
+
+        // the parameters come first, then the calls in order
+        let recorded = events(&log);
+        assert!(recorded.contains(&Event::SetFps(30)));
+        assert_eq!(
+            &recorded[recorded.len() - 8..],
+            &[
+                Event::LoadPresetFile {
+                    filename: "a.milk".to_string(),
+                    smooth_transition: false
+                },
+                Event::PcmInt16 {
+                    samples: vec![1, -2, 3, -4],
+                    channels: STEREO
+                },
+                Event::PcmFloat {
+                    samples: vec![0.25, -0.5],
+                    channels: MONO
+                },
+                Event::RenderFrame,
+                Event::SetWindowSize(640, 480),
+                Event::Touch {
+                    x: 0.5,
+                    y: 0.25,
+                    pressure: 1,
+                    touch_type: TOUCH_TYPE_CIRCLE
+                },
+                Event::PcmUint8 {
+                    samples: vec![0, 128, 255],
+                    channels: MONO
+                },
+                Event::RenderFrame,
+            ]
+        );
+
+        // a fresh instance gets the same calls
+        let replay_log = Shared::default();
+        let replay = ProjectM::<Recorder>::with_backend();
+        replay.start_recording(replay_log.clone()).unwrap();
+        let frames = Replayer::new(&log.0.borrow()[..])
+            .unwrap()
+            .replay(&replay)
+            .unwrap();
+        replay.stop_recording().unwrap();
+
+        assert_eq!(frames, 2);
+        assert_eq!(replay.get_fps(), 30);
+        assert_eq!(replay.get_window_size(), (640, 480));
+        let replayed = events(&replay_log);
+        assert_eq!(&replayed[replayed.len() - recorded.len()..], &recorded[..]);
+    }
+
+    // The current state is logged first, paths as they are
+    #[test]
+    #[cfg(all(feature = "mock", unix))]
+    fn recording_starts_from_state() {
+        use projectm::core::ProjectM;
+        use projectm::playlist::Playlist;
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::Path;
+
+        let projectm = ProjectM::<Recorder>::with_backend();
+        let textures = Path::new(OsStr::from_bytes(b"textures-\xff"));
+        projectm.set_texture_search_paths([textures]).unwrap();
+        let mut playlist = Playlist::create(&projectm);
+        playlist.add_preset(Path::new("b.milk"), false).unwrap();
+        playlist.play(0);
+
+        let file = std::env::temp_dir().join(format!(
+            "projectm-test-recording-{}.pmrec",
+            std::process::id()
+        ));
+        projectm.record_to_file(&file).unwrap();
+        projectm.stop_recording().unwrap();
+        let events: Vec<Event> = Replayer::open(&file)
+            .unwrap()
+            .map(|entry| entry.unwrap().event)
+            .collect();
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(
+            events[0],
+            Event::SetTextureSearchPaths(vec![textures.to_path_buf()])
+        );
+        assert_eq!(
+            events.last(),
+            Some(&Event::LoadPresetFile {
+                filename: "b.milk".to_string(),
+                smooth_transition: false
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_recordings() {
+        let err = Replayer::new(&b"RIFF\0\0\0\0"[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // an empty recording, then one cut off inside a PCM block
+        assert!(Replayer::new(&b"PMREC\0\x01"[..])
+            .unwrap()
+            .next_entry()
+            .unwrap()
+            .is_none());
+        let mut truncated = Replayer::new(&b"PMREC\0\x01\x00\x18\x01\x04\x01\x02"[..]).unwrap();
+        assert!(truncated.next_entry().is_err());
+
+        // a time that doesn't fit into 64 bits
+        let mut overflow =
+            Replayer::new(&b"PMREC\0\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02"[..]).unwrap();
+        let err = overflow.next_entry().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
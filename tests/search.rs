@@ -0,0 +1,35 @@
+#[cfg(test)]
+mod search {
+    use projectm::index::PresetIndex;
+    use projectm::search::{Feature, Query};
+
+    #[test]
+    fn search_index() {
+        let presets_dir = std::env::current_dir().unwrap().join("presets");
+        let mut index = PresetIndex::new();
+        index.scan(&presets_dir, true).unwrap();
+
+        assert_eq!(index.search(&Query::new()).len(), 20);
+        assert_eq!(index.search(&Query::new().text("wave smooth")).len(), 3);
+
+        let per_pixel = index.search(&Query::new().with(Feature::PerPixelCode));
+        assert_eq!(per_pixel.len(), 1);
+        assert!(per_pixel[0].path.ends_with("110-per_pixel.milk"));
+
+        assert!(index.add_tag(&presets_dir.join("000-empty.milk"), "calm"));
+        assert!(index.add_tag(&presets_dir.join("001-line.milk"), "Calm"));
+        assert!(index.add_tag(&presets_dir.join("001-line.milk"), "lines"));
+
+        assert_eq!(
+            index.get(&presets_dir.join("001-line.milk")).unwrap().tags,
+            ["calm", "lines"]
+        );
+        assert_eq!(index.search(&Query::new().include_tag(" CALM ")).len(), 2);
+        assert_eq!(
+            index
+                .search(&Query::new().include_tag("calm").exclude_tag("lines"))
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod settings {
+    #[test]
+    #[cfg(feature = "mock")]
+    fn apply_and_snapshot() {
+        use projectm::core::ProjectM;
+        use projectm::settings::Settings;
+
+        let projectm = ProjectM::create();
+        assert_eq!(Settings::from_instance(&projectm), Settings::default());
+
+        let settings = Settings {
+            beat_sensitivity: 1.5,
+            preset_duration: 12.0,
+            mesh_size: (64, 48),
+            window_size: (1280, 720),
+            preset_locked: true,
+            ..Settings::default()
+        };
+        settings.apply(&projectm).unwrap();
+
+        assert_eq!(projectm.get_preset_duration(), 12.0);
+        assert_eq!(Settings::from_instance(&projectm), settings);
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn toml() {
+        use projectm::settings::Settings;
+
+        let settings = Settings {
+            hard_cut_enabled: true,
+            fps: 30,
+            ..Settings::default()
+        };
+
+        let toml = settings.to_toml().unwrap();
+        assert_eq!(Settings::from_toml(&toml).unwrap(), settings);
+
+        // missing entries keep their defaults
+        let partial = Settings::from_toml("fps = 30\nhard_cut_enabled = true\n").unwrap();
+        assert_eq!(partial, settings);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_infinite_duration() {
+        use projectm::settings::Settings;
+
+        let settings = Settings {
+            preset_duration: f64::INFINITY,
+            ..Settings::default()
+        };
+        assert!(settings.validate().is_ok());
+
+        let json = settings.to_json().unwrap();
+        assert_eq!(Settings::from_json(&json).unwrap(), settings);
+    }
+}
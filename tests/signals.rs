@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod signals {
+    use projectm::audio::analysis::Analyzer;
+    use projectm::audio::signals::*;
+    use projectm::core::{MONO, STEREO};
+
+    #[test]
+    fn chunks() {
+        let chunks: Vec<Vec<f32>> = Sine::new(44100, 440.0, 0.5)
+            .chunks_of(STEREO, 1.0, 2048)
+            .collect();
+        assert_eq!(chunks.len(), 44);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 2048));
+        assert_eq!(chunks.iter().map(Vec::len).sum::<usize>(), 2 * 44100);
+        assert!(chunks.concat().iter().all(|sample| sample.abs() <= 0.5));
+
+        // noise is repeatable
+        let noise = |seed| {
+            WhiteNoise::new(44100, 1.0, seed)
+                .chunks_of(MONO, 0.1, 1024)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(noise(7), noise(7));
+        assert_ne!(noise(7), noise(8));
+
+        // out of phase channels cancel out in mono
+        let mono = StereoPhase::new(44100, 100.0, 180.0, 1.0)
+            .chunks_of(MONO, 0.1, 1024)
+            .flatten();
+        assert!(mono.into_iter().all(|sample| sample.abs() < 1e-5));
+    }
+
+    #[test]
+    fn drum_tempo() {
+        let mut analyzer = Analyzer::new(44100);
+        let mut beats = 0;
+        for chunk in DrumPattern::new(44100, 120.0, "k...").chunks_of(MONO, 8.0, 2048) {
+            analyzer.process(&chunk, MONO, |frame| {
+                if frame.beat {
+                    beats += 1;
+                }
+            });
+        }
+        assert_eq!(beats, 16);
+        assert!((analyzer.tempo().unwrap() - 120.0).abs() < 1.0);
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod source {
+    use projectm::source::*;
+
+    #[test]
+    fn directory() {
+        let mut source = DirectorySource::open("presets").unwrap();
+        let presets = source.presets();
+        assert_eq!(presets.len(), 20);
+        assert_eq!(presets[0], "000-empty.milk");
+        assert!(source.textures().is_empty());
+
+        let data = source.read_preset("110-per_pixel.milk").unwrap();
+        assert!(data.contains("per_pixel_1="));
+
+        // only scanned names can be read
+        assert!(source.read_preset("../Cargo.toml").is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "mock", feature = "zip"))]
+    fn zip_playlist() {
+        use projectm::core::ProjectM;
+        use projectm::mock::{self, Call};
+        use std::io::{Cursor, Write};
+        use zip::write::{FileOptions, ZipWriter};
+
+        let preset = "[preset00]\ncomp_1=`ret = tex2D(sampler_fw_clouds, uv).xyz;\n";
+        let png = b"\x89PNG\r\n\x1a\n not really a png";
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("pack/clouds.milk", FileOptions::default())
+            .unwrap();
+        writer.write_all(preset.as_bytes()).unwrap();
+        writer
+            .start_file("pack/textures/Clouds.PNG", FileOptions::default())
+            .unwrap();
+        writer.write_all(png).unwrap();
+        writer
+            .start_file("__MACOSX/pack/._clouds.milk", FileOptions::default())
+            .unwrap();
+        let archive = writer.finish().unwrap();
+
+        let source = ZipSource::new(archive).unwrap();
+        assert_eq!(source.presets(), vec!["pack/clouds.milk"]);
+        assert_eq!(source.textures(), vec!["pack/textures/Clouds.PNG"]);
+
+        let projectm = ProjectM::create();
+        let mut playlist = SourcePlaylist::new(source).unwrap();
+        playlist.play_next(&projectm, false).unwrap();
+        assert_eq!(playlist.position(), Some(0));
+
+        let texture = playlist.textures().path("clouds").unwrap();
+        assert_eq!(std::fs::read(texture).unwrap(), png);
+        assert_eq!(
+            projectm.get_texture_search_paths(),
+            vec![playlist.textures().directory().to_path_buf()]
+        );
+        assert!(mock::calls(&projectm).contains(&Call::LoadPresetData {
+            data: preset.to_string(),
+            smooth_transition: false,
+        }));
+
+        drop(playlist);
+        assert!(projectm.get_texture_search_paths().is_empty());
+    }
+}
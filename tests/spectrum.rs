@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod spectrum {
+    use projectm::audio::spectrum::*;
+
+    fn sine(frequency: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / 44100.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn magnitudes() {
+        // Bins are 43 Hz wide, the sine sits exactly on bin 25
+        let spectrum = Spectrum::new(1024, 44100).window(WindowFunction::Hann);
+        let frequency = spectrum.bin_frequency(25);
+        let magnitudes = spectrum.compute(&sine(frequency, 4096));
+        assert_eq!(magnitudes.len(), 513);
+
+        let peak = (0..magnitudes.len())
+            .max_by(|a, b| magnitudes[*a].total_cmp(&magnitudes[*b]))
+            .unwrap();
+        assert_eq!(peak, 25);
+        assert!((magnitudes[25] - 1.0).abs() < 0.01, "{}", magnitudes[25]);
+        assert!(magnitudes[100] < 0.001);
+
+        let spectrum = spectrum.binning(Binning::Logarithmic {
+            bands: 10,
+            min_frequency: 20.0,
+        });
+        let bands = spectrum.compute(&sine(frequency, 4096));
+        let edges = spectrum.band_edges();
+        assert_eq!(bands.len(), 10);
+        let band = edges
+            .iter()
+            .position(|(low, high)| *low <= frequency && frequency < *high)
+            .unwrap();
+        assert!((bands[band] - 1.0).abs() < 0.01);
+        assert!((edges[9].1 - 22050.0).abs() < 1.0);
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn snapshots() {
+        use projectm::core::{ProjectM, STEREO};
+
+        let projectm = ProjectM::create();
+        assert!(projectm.get_waveform().is_empty());
+
+        projectm.set_waveform_length(4);
+        projectm.pcm_add_float(&[0.1, -0.1, 0.2, -0.2, 0.3, -0.3], STEREO);
+        projectm.pcm_add_int16(&[16384, -16384, 0, 0], STEREO);
+
+        let waveform = projectm.get_waveform();
+        assert_eq!(waveform.left, vec![0.2, 0.3, 0.5, 0.0]);
+        assert_eq!(waveform.right, vec![-0.2, -0.3, -0.5, 0.0]);
+        assert_eq!(waveform.mono(), vec![0.0; 4]);
+
+        let spectrum = Spectrum::new(8, 44100);
+        assert_eq!(projectm.get_spectrum(&spectrum), vec![0.0; 5]);
+    }
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "mock")]
+
+#[cfg(test)]
+mod texture_search_paths {
+    use projectm::core::ProjectM;
+    use projectm::mock;
+    use std::path::PathBuf;
+
+    #[test]
+    fn set_add_remove() {
+        let projectm = ProjectM::create();
+        let existing = PathBuf::from("presets");
+        let missing = std::env::temp_dir().join("projectm-test-missing-textures");
+
+        let not_found = projectm
+            .set_texture_search_paths([&existing, &missing])
+            .unwrap();
+        assert_eq!(not_found, vec![missing.clone()]);
+        assert_eq!(
+            mock::parameters(&projectm).texture_search_paths,
+            vec![
+                "presets".to_string(),
+                missing.to_string_lossy().into_owned()
+            ]
+        );
+
+        // adding a path twice keeps a single entry
+        projectm.add_texture_search_path("presets").unwrap();
+        assert_eq!(projectm.get_texture_search_paths().len(), 2);
+
+        assert!(projectm.remove_texture_search_path(&missing));
+        assert!(!projectm.remove_texture_search_path(&missing));
+        assert_eq!(projectm.get_texture_search_paths(), vec![existing]);
+
+        assert!(projectm.add_texture_search_path("bad\0path").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let projectm = ProjectM::create();
+        let path = PathBuf::from(OsStr::from_bytes(b"textures/caf\xe9"));
+
+        projectm.set_texture_search_paths([&path]).unwrap();
+        assert_eq!(projectm.get_texture_search_paths(), vec![path]);
+    }
+}
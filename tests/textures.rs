@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod textures {
+    use projectm::textures::{ImageFormat, TextureProvider};
+
+    #[test]
+    fn provider() {
+        let mut textures = TextureProvider::new().unwrap();
+        let directory = textures.directory().to_path_buf();
+
+        // 2x1 image: red, half transparent blue
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 128];
+        textures.add_rgba("stripes", 2, 1, &pixels).unwrap();
+        let tga = std::fs::read(directory.join("stripes.tga")).unwrap();
+        assert_eq!(tga.len(), 18 + 8);
+        assert_eq!(&tga[12..16], &[2, 0, 1, 0]);
+        assert_eq!(&tga[18..], &[0, 0, 255, 255, 255, 0, 0, 128]);
+
+        let png = b"\x89PNG\r\n\x1a\n not really a png";
+        assert_eq!(ImageFormat::detect(png), Some(ImageFormat::Png));
+        textures.add_encoded("clouds.png", png).unwrap();
+        assert!(directory.join("clouds.png").exists());
+
+        // replacing a texture removes the old file
+        textures.add_rgba("clouds", 2, 1, &pixels).unwrap();
+        assert!(!directory.join("clouds.png").exists());
+        assert_eq!(
+            textures.names().collect::<Vec<_>>(),
+            vec!["clouds", "stripes"]
+        );
+
+        assert!(textures.add_encoded("unknown", b"GIF89a").is_err());
+        assert!(textures.add_rgba("short", 2, 2, &pixels).is_err());
+        assert!(textures.add_rgba("../escape", 2, 1, &pixels).is_err());
+
+        assert!(textures.remove("stripes"));
+        assert!(!directory.join("stripes.tga").exists());
+
+        drop(textures);
+        assert!(!directory.exists());
+    }
+
+    #[test]
+    #[cfg(feature = "mock")]
+    fn attach() {
+        use projectm::core::ProjectM;
+
+        let projectm = ProjectM::create();
+        let textures = TextureProvider::new().unwrap();
+
+        textures.attach(&projectm).unwrap();
+        assert_eq!(
+            projectm.get_texture_search_paths(),
+            vec![textures.directory().to_path_buf()]
+        );
+
+        textures.detach(&projectm);
+        assert!(projectm.get_texture_search_paths().is_empty());
+    }
+}